@@ -1,8 +1,8 @@
 use ahash::AHashSet;
 
 use crate::{
-    data::{Product, ProductContainer, RawProductOption},
-    serialize::{Deserializable, Serializable},
+    data::{Product, ProductContainer, RawProductOption, RawSelectedOption},
+    serialize::{sequential_array, Deserializable, Serializable},
 };
 
 #[derive(PartialEq, Eq)]
@@ -21,6 +21,8 @@ pub struct CategoryOption<'a> {
     pub serialization_id: usize,
     content: Vec<&'a Product<'a>>,
     products_by_serialization_id: AHashSet<usize>,
+    // The serialization ids of the variants with this option
+    variants: AHashSet<usize>,
 }
 
 impl<'a> Serializable for Category<'a> {
@@ -37,6 +39,7 @@ impl<'a> Serializable for Category<'a> {
             option.name.serialize(output);
 
             Product::serialize_to_sequential_array(&option.content, output);
+            sequential_array::serialize(option.variants.iter().copied(), output);
         }
     }
 }
@@ -57,6 +60,7 @@ impl<'a> Category<'a> {
 
             let (new_input, products, product_ids) =
                 Product::deserialize_from_sequential_ids(input_after_name, all_products)?;
+            let (new_input, variants) = sequential_array::deserialize::<usize>(new_input)?;
 
             let serialization_id = *next_serialization_id;
             *next_serialization_id += 1;
@@ -68,6 +72,7 @@ impl<'a> Category<'a> {
                 content: products,
                 serialization_id,
                 products_by_serialization_id: product_ids.into_iter().collect(),
+                variants: variants.into_iter().collect(),
             });
         }
 
@@ -96,6 +101,7 @@ impl<'a> CategoryOption<'a> {
             name,
             content: Vec::new(),
             products_by_serialization_id: AHashSet::new(),
+            variants: AHashSet::new(),
             serialization_id,
         }
     }
@@ -114,46 +120,86 @@ impl<'a> CategoryOption<'a> {
         self.products_by_serialization_id
             .contains(&product.serialization_id)
     }
+
+    pub fn contains_variant(&self, variant: usize) -> bool {
+        self.variants.contains(&variant)
+    }
+}
+
+impl<'a> Category<'a> {
+    // Finds the option with the name, adding it if it's new
+    fn option_mut(
+        &mut self,
+        name: &str,
+        next_serialization_id: &mut usize,
+    ) -> &mut CategoryOption<'a> {
+        let position = match self.options.iter().position(|o| o.name == name) {
+            Some(position) => position,
+            None => {
+                self.options.push(CategoryOption::new(
+                    name.to_string(),
+                    *next_serialization_id,
+                ));
+                *next_serialization_id += 1;
+                self.options.len() - 1
+            }
+        };
+        &mut self.options[position]
+    }
+
+    /// True if any variant of the product has one of the options, so the category is filtered per variant
+    pub fn has_variants_of(&self, product: &Product<'_>) -> bool {
+        product
+            .variants
+            .clone()
+            .any(|variant| self.options.iter().any(|o| o.contains_variant(variant)))
+    }
 }
 
 impl<'a> CategoryIndex<'a> {
+    // `variant_options` holds the selected options of every variant, by its serialization id
     pub fn index(
         options_list: Vec<Vec<RawProductOption>>,
+        variant_options: Vec<Vec<RawSelectedOption>>,
         container: &'a ProductContainer<'a>,
     ) -> CategoryIndex<'a> {
+        fn category_mut<'c, 'a>(
+            categories: &'c mut Vec<Category<'a>>,
+            name: &str,
+        ) -> &'c mut Category<'a> {
+            let position = match categories.iter().position(|c| c.name == name) {
+                Some(position) => position,
+                None => {
+                    categories.push(Category::new(name.to_string()));
+                    categories.len() - 1
+                }
+            };
+            &mut categories[position]
+        }
+
         let mut categories: Vec<Category> = Vec::new();
         let mut next_serialization_id = 0;
         // Then we register the categories for the options now they're read only
         for (i, options) in options_list.into_iter().enumerate() {
             for raw_option in options {
-                let category = match categories.iter_mut().find(|c| c.name == raw_option.name) {
-                    Some(v) => v,
-                    None => {
-                        let new = Category::new(raw_option.name.to_string());
-                        categories.push(new);
-                        let just_inserted = categories.len() - 1;
-                        categories.get_mut(just_inserted).unwrap()
-                    }
-                };
-
+                let category = category_mut(&mut categories, &raw_option.name);
                 for raw_value in raw_option.values {
-                    let option = match category.options.iter_mut().find(|o| o.name == raw_value) {
-                        Some(v) => v,
-                        None => {
-                            let new =
-                                CategoryOption::new(raw_value.to_string(), next_serialization_id);
-                            next_serialization_id += 1;
-                            category.options.push(new);
-                            let just_inserted = category.options.len() - 1;
-                            category.options.get_mut(just_inserted).unwrap()
-                        }
-                    };
-
+                    let option = category.option_mut(&raw_value, &mut next_serialization_id);
                     option.add(&container.products[i]);
                 }
             }
         }
 
+        for (variant, options) in variant_options.into_iter().enumerate() {
+            let product = &container.products[container.variants[variant].product];
+            for RawSelectedOption { name, value } in options {
+                let category = category_mut(&mut categories, &name);
+                let option = category.option_mut(&value, &mut next_serialization_id);
+                option.add(product);
+                option.variants.insert(variant);
+            }
+        }
+
         CategoryIndex(categories)
     }
 
@@ -0,0 +1,270 @@
+use ahash::{AHashMap, AHashSet};
+
+use crate::{
+    data::{Product, ProductContainer, RawCollection},
+    normalize::TextNormalization,
+    serialize::{Deserializable, Serializable},
+};
+
+#[derive(PartialEq, Eq)]
+pub struct Collection<'a> {
+    pub id: String,
+    pub title: String,
+    // The name in the collection's url, like "summer-posters"
+    pub handle: String,
+    // In the collection's manual sort order
+    products: Vec<&'a Product<'a>>,
+    products_by_serialization_id: AHashSet<usize>,
+}
+
+#[derive(PartialEq, Eq)]
+pub struct CollectionIndex<'a>(Vec<Collection<'a>>);
+
+impl<'a> Collection<'a> {
+    pub fn contains(&self, product: &Product<'_>) -> bool {
+        self.products_by_serialization_id
+            .contains(&product.serialization_id)
+    }
+
+    /// The products of the collection, in its manual sort order
+    pub fn products(&self) -> impl Iterator<Item = &'a Product<'a>> + '_ {
+        self.products.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.products.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.products.is_empty()
+    }
+}
+
+impl Serializable for Collection<'_> {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        let Collection {
+            id,
+            title,
+            handle,
+            products,
+            ..
+        } = self;
+        id.serialize(output);
+        title.serialize(output);
+        handle.serialize(output);
+        // The ids are kept in the manual order, so they aren't stored as a sequential array
+        let ids: Vec<usize> = products.iter().map(|p| p.serialization_id).collect();
+        ids.serialize(output);
+    }
+}
+
+impl Serializable for CollectionIndex<'_> {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        let collections = &self.0;
+        collections.serialize(output);
+    }
+}
+
+impl<'a> CollectionIndex<'a> {
+    pub fn deserialize<'i>(
+        input: &'i [u8],
+        existing_products: &'a [Product<'a>],
+    ) -> Option<(&'i [u8], CollectionIndex<'a>)> {
+        let (mut input, collection_count) = usize::deserialize(input)?;
+        let mut collections = Vec::with_capacity(collection_count.min(input.len()));
+        for _ in 0..collection_count {
+            let (new_input, id) = String::deserialize(input)?;
+            let (new_input, title) = String::deserialize(new_input)?;
+            let (new_input, handle) = String::deserialize(new_input)?;
+            let (new_input, ids): (_, Vec<usize>) = Deserializable::deserialize(new_input)?;
+            input = new_input;
+
+            let mut products = Vec::with_capacity(ids.len());
+            for id in &ids {
+                products.push(existing_products.get(*id)?);
+            }
+            collections.push(Collection {
+                id,
+                title,
+                handle,
+                products,
+                products_by_serialization_id: ids.into_iter().collect(),
+            });
+        }
+
+        Some((input, CollectionIndex(collections)))
+    }
+
+    /// Collections are sorted by title. The products of a collection are in the order of their position in it,
+    /// and the ones without a position come last, in the order of their ids
+    pub fn index<'out>(
+        collections_for_product: Vec<Vec<RawCollection<'_>>>,
+        container: &'out ProductContainer<'out>,
+    ) -> CollectionIndex<'out> {
+        let mut by_id: AHashMap<String, (Collection<'out>, Vec<Option<usize>>)> = AHashMap::new();
+        for (id, collections) in collections_for_product.into_iter().enumerate() {
+            let product = &container.products[id];
+            for raw in collections {
+                let (collection, positions) =
+                    by_id.entry(raw.id.to_string()).or_insert_with(|| {
+                        let collection = Collection {
+                            id: raw.id.to_string(),
+                            title: String::new(),
+                            handle: String::new(),
+                            products: Vec::new(),
+                            products_by_serialization_id: AHashSet::new(),
+                        };
+                        (collection, Vec::new())
+                    });
+                // Products can repeat the title and handle of their collections, the first given is kept
+                if collection.title.is_empty() {
+                    collection.title = raw.title.to_string();
+                }
+                if collection.handle.is_empty() {
+                    collection.handle = raw.handle.to_string();
+                }
+                if collection.products_by_serialization_id.insert(id) {
+                    collection.products.push(product);
+                    positions.push(raw.position);
+                }
+            }
+        }
+
+        let mut collections: Vec<Collection> = by_id
+            .into_iter()
+            .map(|(_, (mut collection, positions))| {
+                let mut ordered: Vec<_> = positions.into_iter().zip(collection.products).collect();
+                // Products are in the order of their ids, so a stable sort keeps that among equal positions
+                ordered.sort_by_key(|(position, _)| position.unwrap_or(usize::MAX));
+                collection.products = ordered.into_iter().map(|(_, p)| p).collect();
+                collection
+            })
+            .collect();
+        collections.sort_by(|a, b| (&a.title, &a.id).cmp(&(&b.title, &b.id)));
+
+        CollectionIndex(collections)
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Collection<'a>> {
+        self.0.get(id)
+    }
+
+    /// The collection with the handle or id, for the url of a collection page
+    pub fn find(&self, handle_or_id: &str) -> Option<(usize, &Collection<'a>)> {
+        self.0.iter().enumerate().find(|(_, collection)| {
+            collection.handle == handle_or_id || collection.id == handle_or_id
+        })
+    }
+
+    /// The collections whose title has words starting with the words of the query,
+    /// by the share of the query's words they have, and then by title
+    pub fn search(&self, query: &str, normalization: &TextNormalization) -> Vec<(usize, f32)> {
+        let query = normalization.normalize(query);
+        let words: Vec<&str> = query.split_whitespace().collect();
+        if words.is_empty() {
+            return Vec::new();
+        }
+
+        let mut found: Vec<(usize, f32)> = self
+            .0
+            .iter()
+            .enumerate()
+            .filter_map(|(id, collection)| {
+                let title = normalization.normalize(&collection.title);
+                let matched = words
+                    .iter()
+                    .filter(|word| title.split_whitespace().any(|t| t.starts_with(**word)))
+                    .count();
+                #[allow(clippy::cast_precision_loss)]
+                let score = matched as f32 / words.len() as f32;
+                (matched > 0).then_some((id, score))
+            })
+            .collect();
+        // Collections are sorted by title, so a stable sort keeps them that way among equal scores
+        found.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        found
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Collection<'a>> {
+        self.0.iter()
+    }
+}
+
+#[test]
+fn test_collections() {
+    use crate::{
+        config::IndexConfig,
+        data::RawCollection,
+        testing::{build_test_index, test_products},
+    };
+
+    let mut products = test_products();
+    let summer = |position| RawCollection {
+        id: "gid://shopify/Collection/1".into(),
+        title: "Summer posters".into(),
+        handle: "summer-posters".into(),
+        position,
+    };
+    products[0].collections.push(summer(Some(2)));
+    products[1].collections.push(summer(None));
+    products[2].collections.push(summer(Some(0)));
+    products[2].collections.push(RawCollection {
+        id: "gid://shopify/Collection/2".into(),
+        title: "Autumn".into(),
+        handle: "autumn".into(),
+        position: Some(0),
+    });
+    let ids: Vec<String> = products.iter().map(|p| p.id.to_string()).collect();
+
+    let built = build_test_index(products, IndexConfig::default());
+    let (container, deserialized) = (built.container, built.loaded_classic);
+    let bytes = |collections: &CollectionIndex<'_>| {
+        let mut out = Vec::new();
+        collections.serialize(&mut |b| out.push(b));
+        out
+    };
+    assert_eq!(
+        bytes(&deserialized.collections),
+        bytes(&built.classic.collections)
+    );
+
+    let collections = &deserialized.collections;
+    assert_eq!(collections.len(), 2);
+    // By handle or by id
+    let (id, summer) = collections.find("summer-posters").unwrap();
+    assert_eq!(
+        collections.find("gid://shopify/Collection/1").unwrap().0,
+        id
+    );
+    assert!(collections.find("winter").is_none());
+
+    // The manual order, with the product without a position last
+    let in_order: Vec<&str> = summer.products().map(|p| p.id.as_str()).collect();
+    assert_eq!(in_order, [&ids[2], &ids[0], &ids[1]]);
+    assert!(summer.contains(&container.products[3]) == false);
+
+    let order = deserialized
+        .order
+        .collection_order(summer, container.products.len());
+    let mut sorted: Vec<usize> = (0..container.products.len()).collect();
+    sorted.sort_by_key(|id| order[*id]);
+    let first: Vec<&str> = sorted[..3]
+        .iter()
+        .map(|id| container.products[*id].id.as_str())
+        .collect();
+    assert_eq!(first, in_order);
+
+    let normalization = &container.config.normalization;
+    let found = collections.search("summ", normalization);
+    assert_eq!(found, [(id, 1.0)]);
+    assert_eq!(collections.search("summer autumn", normalization).len(), 2);
+    assert!(collections.search("", normalization).is_empty());
+}
@@ -0,0 +1,99 @@
+use ahash::AHashMap;
+
+use crate::{
+    data::{Product, ProductContainer},
+    serialize::{Deserializable, Serializable},
+};
+
+// Scanners and people type codes with stray spaces and in either case
+fn lookup_key(code: &str) -> String {
+    code.trim().to_ascii_lowercase()
+}
+
+/// Exact lookups of products by their id, or the SKU or barcode of one of their variants
+#[derive(Debug, PartialEq, Eq, Default)]
+pub struct LookupIndex {
+    // Both the full id and its numeric part, see `Product::get_id`
+    ids: AHashMap<String, usize>,
+    codes: AHashMap<String, usize>,
+}
+
+impl LookupIndex {
+    pub fn index(codes_for_product: Vec<Vec<&str>>, container: &ProductContainer<'_>) -> Self {
+        let mut index = LookupIndex::default();
+        for product in &container.products {
+            index.add_id(product);
+        }
+        for (id, codes) in codes_for_product.into_iter().enumerate() {
+            for code in codes {
+                // A code shared by several products finds the first of them
+                index.codes.entry(lookup_key(code)).or_insert(id);
+            }
+        }
+        index
+    }
+
+    fn add_id(&mut self, product: &Product<'_>) {
+        let id = product.serialization_id;
+        self.ids.entry(lookup_key(&product.id)).or_insert(id);
+        if product.id.ends_with(|c: char| c.is_ascii_digit()) {
+            self.ids.entry(product.get_id().to_string()).or_insert(id);
+        }
+    }
+
+    /// The serialization id of the product with this id
+    pub fn product(&self, id: &str) -> Option<usize> {
+        self.ids.get(&lookup_key(id)).copied()
+    }
+
+    /// The serialization id of the product with a variant with this SKU or barcode
+    pub fn code(&self, code: &str) -> Option<usize> {
+        self.codes.get(&lookup_key(code)).copied()
+    }
+}
+
+impl Serializable for LookupIndex {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        self.ids.serialize(output);
+        self.codes.serialize(output);
+    }
+}
+
+impl Deserializable for LookupIndex {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (input, ids) = AHashMap::deserialize(input)?;
+        let (input, codes) = AHashMap::deserialize(input)?;
+        Some((input, LookupIndex { ids, codes }))
+    }
+}
+
+#[test]
+fn test_lookup_index() {
+    use crate::{
+        config::IndexConfig,
+        testing::{build_test_index, test_products},
+    };
+
+    let built = build_test_index(test_products(), IndexConfig::default());
+    let (container, classic) = (built.container, built.loaded_classic);
+
+    let product_id = "gid://shopify/Product/6837964669113";
+    let found = |id: Option<usize>| id.map(|id| container.products[id].id.as_str());
+
+    assert_eq!(found(classic.lookup.product(product_id)), Some(product_id));
+    assert_eq!(
+        found(classic.lookup.product("6837964669113")),
+        Some(product_id)
+    );
+    assert_eq!(
+        found(classic.lookup.code("P-CA-3040-POSTE-0769-S")),
+        Some(product_id)
+    );
+    // Scanned codes can come in with whitespace and in another case
+    assert_eq!(
+        found(classic.lookup.code(" p-ca-5070-poste-0770-s\n")),
+        Some(product_id)
+    );
+    assert_eq!(classic.lookup.code("P-CA-0000"), None);
+    assert_eq!(classic.lookup.product("0"), None);
+}
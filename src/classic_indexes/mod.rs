@@ -1,15 +1,23 @@
 mod categorical;
+mod collection;
+mod lookup;
 mod order;
+mod spelling;
+mod suggestion;
 mod tag;
 
 pub use categorical::{Category, CategoryIndex, CategoryOption};
+pub use collection::{Collection, CollectionIndex};
 
 use crate::{
     data::ProductContainer,
     serialize::{Deserializable, Serializable},
 };
 
-pub use order::OrderIndex;
+pub use lookup::LookupIndex;
+pub use order::{OrderIndex, COLLECTION_DEFAULT};
+pub use spelling::Vocabulary;
+pub use suggestion::{suggest_filters, FilterSuggestion, FilterTarget, MIN_SUGGESTION_SCORE};
 pub use tag::{Tag, TagIndex};
 
 #[derive(PartialEq, Eq)]
@@ -17,6 +25,10 @@ pub struct ClassicIndexes<'a> {
     pub categories: CategoryIndex<'a>,
     pub tags: TagIndex<'a>,
     pub order: OrderIndex,
+    pub lookup: LookupIndex,
+    pub collections: CollectionIndex<'a>,
+    // The words of the catalogue, for spelling suggestions
+    pub spelling: Vocabulary,
 }
 
 impl<'a> ClassicIndexes<'a> {
@@ -27,12 +39,18 @@ impl<'a> ClassicIndexes<'a> {
         let (input, categories) = CategoryIndex::deserialize_many(input, &data.products)?;
         let (input, tags) = TagIndex::deserialize(input, &data.products)?;
         let (input, order) = OrderIndex::deserialize(input)?;
+        let (input, lookup) = LookupIndex::deserialize(input)?;
+        let (input, collections) = CollectionIndex::deserialize(input, &data.products)?;
+        let (input, spelling) = Vocabulary::deserialize(input)?;
         Some((
             input,
             ClassicIndexes {
                 categories,
                 tags,
                 order,
+                lookup,
+                collections,
+                spelling,
             },
         ))
     }
@@ -41,11 +59,17 @@ impl<'a> ClassicIndexes<'a> {
         categories: CategoryIndex<'a>,
         tags: TagIndex<'a>,
         order: OrderIndex,
+        lookup: LookupIndex,
+        collections: CollectionIndex<'a>,
+        spelling: Vocabulary,
     ) -> ClassicIndexes<'a> {
         ClassicIndexes {
             categories,
             tags,
             order,
+            lookup,
+            collections,
+            spelling,
         }
     }
 }
@@ -55,5 +79,8 @@ impl<'a> Serializable for ClassicIndexes<'a> {
         self.categories.serialize(output);
         self.tags.serialize(output);
         self.order.serialize(output);
+        self.lookup.serialize(output);
+        self.collections.serialize(output);
+        self.spelling.serialize(output);
     }
 }
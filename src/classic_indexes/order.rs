@@ -1,13 +1,19 @@
 use ahash::AHashMap;
 
 use crate::{
+    classic_indexes::Collection,
     data::{FeatureSet, Product, ProductContainer},
     serialize::{Deserializable, Serializable},
 };
 
+/// The order of a collection's manual sort, see `OrderIndex::collection_order`
+pub const COLLECTION_DEFAULT: &str = "Collection default";
+
 #[derive(Debug, PartialEq, Eq)]
 pub struct OrderIndex {
     orders: AHashMap<String, Vec<usize>>,
+    // Orders that depend on the currency, like the price orders
+    by_currency: AHashMap<String, AHashMap<String, Vec<usize>>>,
 }
 
 impl OrderIndex {
@@ -32,6 +38,17 @@ impl OrderIndex {
         cmp: &'a dyn Fn(&T, &T) -> std::cmp::Ordering,
         key: String,
     ) {
+        let order = self.order_of(products, maker, cmp);
+        self.orders.insert(key, order);
+    }
+
+    /// The position of every product in the order, by its serialization id
+    pub fn order_of<'a, T: 'a>(
+        &self,
+        products: &'a ProductContainer<'a>,
+        maker: &'a dyn Fn(&'a Product, &'a FeatureSet) -> T,
+        cmp: &'a dyn Fn(&T, &T) -> std::cmp::Ordering,
+    ) -> Vec<usize> {
         let features = &products.extra_features;
         let mut data_and_id: Vec<(T, usize)> = products
             .products
@@ -52,12 +69,20 @@ impl OrderIndex {
             order[serialization_id] = order_position;
         }
 
-        self.orders.insert(key, order);
+        order
+    }
+
+    pub fn add_in_currency(&mut self, currency: &str, key: &str, order: Vec<usize>) {
+        self.by_currency
+            .entry(currency.to_string())
+            .or_default()
+            .insert(key.to_string(), order);
     }
 
     pub fn new() -> Self {
         Self {
             orders: AHashMap::new(),
+            by_currency: AHashMap::new(),
         }
     }
 
@@ -65,8 +90,40 @@ impl OrderIndex {
         self.orders.get(feature)
     }
 
+    /// The order in the currency, if it depends on it
+    pub fn get_orders_in(&self, feature: &str, currency: &str) -> Option<&Vec<usize>> {
+        self.by_currency
+            .get(currency)
+            .and_then(|orders| orders.get(feature))
+            .or_else(|| self.get_orders(feature))
+    }
+
+    /// The collection's own order, with the products that aren't in it after, in alphabetical order
+    pub fn collection_order(
+        &self,
+        collection: &Collection<'_>,
+        product_count: usize,
+    ) -> Vec<usize> {
+        let alphabetical = self.get_orders("Alphabetical");
+        let mut order: Vec<usize> = (0..product_count)
+            .map(|id| {
+                collection.len() + alphabetical.and_then(|a| a.get(id)).copied().unwrap_or(id)
+            })
+            .collect();
+        for (position, product) in collection.products().enumerate() {
+            order[product.serialization_id] = position;
+        }
+        order
+    }
+
     pub fn options(&self) -> impl Iterator<Item = &str> {
-        self.orders.keys().map(String::as_str)
+        let mut options: Vec<&str> = self.orders.keys().map(String::as_str).collect();
+        for orders in self.by_currency.values() {
+            options.extend(orders.keys().map(String::as_str));
+        }
+        options.sort_unstable();
+        options.dedup();
+        options.into_iter()
     }
 }
 
@@ -79,12 +136,20 @@ impl Default for OrderIndex {
 impl Deserializable for OrderIndex {
     fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
         let (input, orders) = Deserializable::deserialize(input)?;
-        Some((input, OrderIndex { orders }))
+        let (input, by_currency) = Deserializable::deserialize(input)?;
+        Some((
+            input,
+            OrderIndex {
+                orders,
+                by_currency,
+            },
+        ))
     }
 }
 
 impl Serializable for OrderIndex {
     fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
         self.orders.serialize(output);
+        self.by_currency.serialize(output);
     }
 }
@@ -0,0 +1,268 @@
+use ahash::AHashMap;
+
+use crate::{
+    normalize::TextNormalization,
+    serialize::{Deserializable, Serializable},
+};
+
+// How many corrections of each word are combined into suggested queries
+const CANDIDATES_PER_WORD: usize = 3;
+// A known word is still corrected when a close word is this many times more common,
+// as rare words are often typos that made it into the catalogue
+const RARE_WORD_RATIO: usize = 10;
+
+/// The whole words of the indexed text, with how many products each is in, for spelling suggestions
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Vocabulary {
+    // Sorted by word
+    words: Vec<(String, usize)>,
+}
+
+// A word without the punctuation inside it, so "t-shirt" is "tshirt"
+pub(super) fn clean_word(word: &str) -> String {
+    word.chars().filter(|c| c.is_alphanumeric()).collect()
+}
+
+// The words of a text
+pub(super) fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split_whitespace()
+        .map(clean_word)
+        .filter(|word| word.is_empty() == false)
+}
+
+// Short words have so many neighbours that corrections would mostly be wrong
+pub(super) fn max_edits(word: &[char]) -> usize {
+    match word.len() {
+        0..=2 => 0,
+        3..=4 => 1,
+        _ => 2,
+    }
+}
+
+/// The Damerau-Levenshtein distance between two words, if it's at most `max`.
+/// Swapping two neighbouring letters is a single edit, as it's the most common typo
+pub(super) fn edit_distance(a: &[char], b: &[char], max: usize) -> Option<usize> {
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+    // The rows for the prefixes of `a` one and two characters shorter than the current one
+    let mut before_previous = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a_char) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            let mut distance = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+            if i > 0 && j > 0 && *a_char == b[j - 1] && a[i - 1] == *b_char {
+                distance = distance.min(before_previous[j - 1] + 1);
+            }
+            current[j + 1] = distance;
+        }
+        // A row is never below both of the two before it, so once they're over the limit, all the rows after are
+        let over = |row: &[usize]| row.iter().min().is_some_and(|min| *min > max);
+        if over(&current) && over(&previous) {
+            return None;
+        }
+        std::mem::swap(&mut before_previous, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+    Some(previous[b.len()]).filter(|distance| *distance <= max)
+}
+
+impl Vocabulary {
+    /// Counts the words of every product's texts, normalised the way queries are
+    pub fn index<T: AsRef<str>>(
+        texts_for_product: impl Iterator<Item = Vec<T>>,
+        normalization: &TextNormalization,
+    ) -> Vocabulary {
+        let mut counts: AHashMap<String, usize> = AHashMap::new();
+        for texts in texts_for_product {
+            let mut product_words: Vec<String> = texts
+                .into_iter()
+                .flat_map(|text| words(&normalization.normalize(text.as_ref())).collect::<Vec<_>>())
+                .collect();
+            product_words.sort_unstable();
+            product_words.dedup();
+            for word in product_words {
+                *counts.entry(word).or_default() += 1;
+            }
+        }
+        let mut words: Vec<(String, usize)> = counts.into_iter().collect();
+        words.sort_unstable();
+        Vocabulary { words }
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// How many products have the word
+    pub fn frequency(&self, word: &str) -> usize {
+        self.words
+            .binary_search_by(|(known, _)| known.as_str().cmp(word))
+            .map_or(0, |found| self.words[found].1)
+    }
+
+    // The likeliest spellings of a word, by edit distance and then by how common they are.
+    // A known word is its own only candidate, unless it's rare next to a close word
+    fn candidates(&self, word: &str) -> Vec<(&str, usize, usize)> {
+        let chars: Vec<char> = word.chars().collect();
+        let max = max_edits(&chars);
+        let frequency = self.frequency(word);
+
+        let mut found: Vec<(&str, usize, usize)> = Vec::new();
+        // One buffer for the characters of every known word
+        let mut known_chars: Vec<char> = Vec::new();
+        for (known, count) in &self.words {
+            // A char takes up to 4 bytes, so words that can't be close enough are skipped before decoding them
+            if known.len() + max < chars.len() || known.len() > (chars.len() + max) * 4 {
+                continue;
+            }
+            known_chars.clear();
+            known_chars.extend(known.chars());
+            let Some(distance) = edit_distance(&chars, &known_chars, max) else {
+                continue;
+            };
+            let likelier = frequency == 0 || *count >= frequency * RARE_WORD_RATIO;
+            if distance == 0 || likelier {
+                found.push((known.as_str(), distance, *count));
+            }
+        }
+        found.sort_by(|(a, a_distance, a_count), (b, b_distance, b_count)| {
+            (a_distance, b_count, a).cmp(&(b_distance, a_count, b))
+        });
+        // A rare known word only gives way to the more common one
+        if frequency > 0 && found.len() > 1 {
+            found.retain(|(_, distance, _)| *distance > 0);
+        }
+        found.truncate(CANDIDATES_PER_WORD);
+        found
+    }
+
+    /// Corrected versions of the query, best first, or nothing if every word of it is spelled as in the catalogue.
+    ///
+    /// Queries are ranked by their total edit distance, and then by how common their corrected words are
+    pub fn suggest(
+        &self,
+        query: &str,
+        normalization: &TextNormalization,
+        limit: usize,
+    ) -> Vec<String> {
+        let query: Vec<String> = words(&normalization.normalize(query)).collect();
+
+        // The best combinations of the candidates of the words so far, with their distance and frequency
+        let mut queries: Vec<(Vec<&str>, usize, usize)> = vec![(Vec::new(), 0, 0)];
+        let mut corrected = false;
+        for word in &query {
+            let mut candidates = self.candidates(word);
+            if candidates.is_empty() {
+                // Words with nothing close, like part numbers, are kept as they are
+                candidates.push((word.as_str(), 0, 0));
+            }
+            corrected |= candidates.iter().all(|(candidate, _, _)| candidate != word);
+
+            let mut extended = Vec::with_capacity(queries.len() * candidates.len());
+            for (words, distance, frequency) in &queries {
+                for (candidate, candidate_distance, count) in &candidates {
+                    let mut words = words.clone();
+                    words.push(candidate);
+                    extended.push((words, distance + candidate_distance, frequency + count));
+                }
+            }
+            extended.sort_by(|(_, a_distance, a_count), (_, b_distance, b_count)| {
+                (a_distance, b_count).cmp(&(b_distance, a_count))
+            });
+            extended.truncate(limit);
+            queries = extended;
+        }
+
+        if corrected == false {
+            return Vec::new();
+        }
+        queries
+            .into_iter()
+            .map(|(words, _, _)| words.join(" "))
+            .filter(|suggestion| *suggestion != query.join(" "))
+            .collect()
+    }
+}
+
+// Words are sorted, so each only stores what differs from the word before it
+impl Serializable for Vocabulary {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        self.words.len().serialize(output);
+        let mut previous = "";
+        for (word, count) in &self.words {
+            let shared: usize = previous
+                .chars()
+                .zip(word.chars())
+                .take_while(|(a, b)| a == b)
+                .map(|(c, _)| c.len_utf8())
+                .sum();
+            shared.serialize(output);
+            (&word[shared..]).serialize(output);
+            count.serialize(output);
+            previous = word;
+        }
+    }
+}
+
+impl Deserializable for Vocabulary {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (mut input, len) = usize::deserialize(input)?;
+        let mut words: Vec<(String, usize)> = Vec::with_capacity(len.min(input.len()));
+        for _ in 0..len {
+            let (new_input, shared) = usize::deserialize(input)?;
+            let (new_input, suffix) = String::deserialize(new_input)?;
+            let (new_input, count) = usize::deserialize(new_input)?;
+            input = new_input;
+
+            let previous = words.last().map_or("", |(word, _)| word.as_str());
+            let mut word = previous.get(..shared)?.to_string();
+            word.push_str(&suffix);
+            words.push((word, count));
+        }
+        Some((input, Vocabulary { words }))
+    }
+}
+
+#[test]
+fn test_suggest() {
+    let normalization = TextNormalization::default();
+    let vocabulary = Vocabulary::index(
+        [
+            vec!["Blue T-Shirt", "A soft shirt"],
+            vec!["Red T-shirt"],
+            vec!["Green t-shirt", "Organic cotton"],
+            vec!["Poster"],
+            vec!["Postre"],
+            vec!["Poster frame"],
+        ]
+        .into_iter()
+        .chain(std::iter::repeat_n(vec!["poster"], 20)),
+        &normalization,
+    );
+    assert_eq!(vocabulary.frequency("tshirt"), 3);
+
+    let mut bytes = Vec::new();
+    vocabulary.serialize(&mut |b| bytes.push(b));
+    let (rest, deserialized) = Vocabulary::deserialize(&bytes).unwrap();
+    assert!(rest.is_empty());
+    assert_eq!(deserialized, vocabulary);
+
+    let suggest = |query: &str| vocabulary.suggest(query, &normalization, 3);
+    // A swap of two letters is one edit
+    assert_eq!(suggest("tshrit"), ["tshirt", "shirt"]);
+    assert_eq!(suggest("tshrti")[0], "tshirt");
+    assert_eq!(suggest("Red tshrit")[0], "red tshirt");
+    // A known word that is much rarer than a close one is likely a typo
+    assert_eq!(suggest("postre"), ["poster"]);
+    // Nothing to correct
+    assert!(suggest("blue tshirt").is_empty());
+    assert!(suggest("xyzzy 42").is_empty());
+}
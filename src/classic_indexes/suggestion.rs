@@ -0,0 +1,196 @@
+use std::ops::Range;
+
+use crate::{data::Vendor, normalize::TextNormalization};
+
+use super::{
+    spelling::{clean_word, edit_distance, max_edits, words},
+    ClassicIndexes,
+};
+
+/// The score suggestions need by default, below it a name is more likely a coincidence than a typo
+pub const MIN_SUGGESTION_SCORE: f32 = 0.8;
+
+/// What a part of a query can be turned into a filter for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterTarget {
+    // By its position in the tag index
+    Tag(usize),
+    // By the positions of the category and of the option in it
+    CategoryOption { category: usize, option: usize },
+    // By its vendor id
+    Vendor(usize),
+}
+
+/// A filter that words of a query likely name
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterSuggestion {
+    pub target: FilterTarget,
+    // From 0 to 1, 1 if the words are the name once normalised
+    pub score: f32,
+    // The positions of the words of the query it covers
+    pub words: Range<usize>,
+    // The bytes of the query it covers
+    pub span: Range<usize>,
+}
+
+// The words of a query with the bytes they're at, normalised like the names they're compared to
+fn query_words(query: &str, normalization: TextNormalization) -> Vec<(Range<usize>, String)> {
+    let mut out = Vec::new();
+    let mut start = None;
+    for (offset, char) in query
+        .char_indices()
+        .chain(std::iter::once((query.len(), ' ')))
+    {
+        match (char.is_whitespace(), start) {
+            (false, None) => start = Some(offset),
+            (true, Some(word_start)) => {
+                let word = clean_word(&normalization.normalize(&query[word_start..offset]));
+                if word.is_empty() == false {
+                    out.push((word_start..offset, word));
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+// How alike the words of a span are to a name, by their edit distance relative to the longer one
+fn similarity(span: &[char], name: &[char]) -> Option<f32> {
+    let distance = edit_distance(span, name, max_edits(name))?;
+    let longest = span.len().max(name.len());
+    #[allow(clippy::cast_precision_loss)]
+    Some(1.0 - distance as f32 / longest as f32)
+}
+
+/// The tags, category options and vendors that spans of the query likely name, best first.
+/// Only names scoring at least `min_score` are suggested.
+///
+/// Names of several words are matched against as many words of the query, so "red shirt sale" can
+/// become the query "shirt" with the "Red" color and the "Sale" tag
+pub fn suggest_filters(
+    query: &str,
+    classic: &ClassicIndexes<'_>,
+    vendors: &[&Vendor],
+    normalization: &TextNormalization,
+    min_score: f32,
+    limit: usize,
+) -> Vec<FilterSuggestion> {
+    let query = query_words(query, *normalization);
+
+    let tags = classic
+        .tags
+        .iter()
+        .enumerate()
+        .map(|(id, tag)| (FilterTarget::Tag(id), tag.name.as_str()));
+    let options = classic
+        .categories
+        .0
+        .iter()
+        .enumerate()
+        .flat_map(|(category, c)| {
+            c.options.iter().enumerate().map(move |(option, o)| {
+                (
+                    FilterTarget::CategoryOption { category, option },
+                    o.name.as_str(),
+                )
+            })
+        });
+    let vendors = vendors
+        .iter()
+        .map(|vendor| (FilterTarget::Vendor(vendor.id), vendor.name.as_str()));
+
+    let mut found = Vec::new();
+    for (target, name) in tags.chain(options).chain(vendors) {
+        let name_words: Vec<String> = words(&normalization.normalize(name)).collect();
+        if name_words.is_empty() || name_words.len() > query.len() {
+            continue;
+        }
+        let name: Vec<char> = name_words.join(" ").chars().collect();
+
+        // The best span of the query for the name, the first one if several are as good
+        let mut best: Option<FilterSuggestion> = None;
+        for (start, span) in query.windows(name_words.len()).enumerate() {
+            let text: Vec<char> = span
+                .iter()
+                .map(|(_, word)| word.as_str())
+                .collect::<Vec<_>>()
+                .join(" ")
+                .chars()
+                .collect();
+            let Some(score) = similarity(&text, &name).filter(|score| *score >= min_score) else {
+                continue;
+            };
+            if best.as_ref().is_none_or(|best| score > best.score) {
+                best = Some(FilterSuggestion {
+                    target,
+                    score,
+                    words: start..start + span.len(),
+                    span: span[0].0.start..span[span.len() - 1].0.end,
+                });
+            }
+        }
+        found.extend(best);
+    }
+
+    // Names covering more of the query go first among equal scores
+    found.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then(b.words.len().cmp(&a.words.len()))
+            .then(a.words.start.cmp(&b.words.start))
+    });
+    found.truncate(limit);
+    found
+}
+
+#[test]
+fn test_suggest_filters() {
+    use crate::{
+        config::IndexConfig,
+        testing::{build_test_index, test_products},
+    };
+
+    let mut products = test_products();
+    products[0].tags.push("Summer Sale".into());
+    let built = build_test_index(products, IndexConfig::default());
+    let (container, classic) = (built.index.container, built.classic);
+    let normalization = &container.config.normalization;
+    let vendors = &container.vendors.by_id;
+    let suggest_above = |query: &str, min_score| {
+        suggest_filters(query, &classic, vendors, normalization, min_score, 5)
+    };
+    let suggest = |query: &str| suggest_above(query, MIN_SUGGESTION_SCORE);
+    let name = |target: FilterTarget| match target {
+        FilterTarget::Tag(id) => classic.tags.get(id).unwrap().name.clone(),
+        FilterTarget::CategoryOption { category, option } => {
+            classic.categories.0[category].options[option].name.clone()
+        }
+        FilterTarget::Vendor(id) => container.vendors.by_id[id].name.clone(),
+    };
+
+    // A multi-word tag with a typo and a swap, found where it is in the query
+    let query = "poster sumer slae";
+    let found = suggest(query);
+    let sale = found
+        .iter()
+        .find(|s| name(s.target) == "Summer Sale")
+        .unwrap();
+    assert_eq!(sale.words, 1..3);
+    assert_eq!(&query[sale.span.clone()], "sumer slae");
+    assert!(sale.score < 1.0);
+    // Unless it has to be closer
+    let found = suggest_above(query, 0.9);
+    assert!(found.iter().all(|s| name(s.target) != "Summer Sale"));
+
+    // An exact match is the best one, whatever its case
+    let vendor = &container.vendors.by_id[0].name;
+    let found = suggest(&format!("Ø {}", vendor.to_uppercase()));
+    assert_eq!(name(found[0].target), *vendor);
+    assert!((found[0].score - 1.0).abs() < f32::EPSILON);
+    assert_eq!(found[0].span.start, "Ø ".len());
+
+    assert!(found.len() <= 5);
+    assert!(suggest("").is_empty());
+}
@@ -100,6 +100,9 @@ impl<'a> TagIndex<'a> {
             }
         }
 
+        let mut products_for_tag: Vec<_> = products_for_tag.into_iter().collect();
+        products_for_tag.sort_by_key(|(name, _)| *name);
+
         TagIndex(
             products_for_tag
                 .into_iter()
@@ -0,0 +1,294 @@
+use std::borrow::Cow;
+
+use crate::{
+    data::ValidationPolicy,
+    language::{AnalysisConfig, Synonyms},
+    merchandising::MerchandisingRules,
+    ngram::TypoTolerance,
+    normalize::TextNormalization,
+    preprocessor::{GramPruning, Normalization, PreprocessConfig},
+    serialize::{limit_string_len, Deserializable, Serializable},
+};
+
+/// How a product field is kept in the serialized index.
+///
+/// Every field is indexed in full regardless, this only decides what a search result can show.
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct StoragePolicy {
+    pub stored: bool,
+    // The markup and entities are removed before the text is cut
+    pub strip_html: bool,
+    // Cut at the last space before the given amount of characters
+    pub max_chars: Option<usize>,
+}
+
+impl StoragePolicy {
+    pub const FULL: StoragePolicy = StoragePolicy {
+        stored: true,
+        strip_html: false,
+        max_chars: None,
+    };
+    pub const NOT_STORED: StoragePolicy = StoragePolicy {
+        stored: false,
+        strip_html: false,
+        max_chars: None,
+    };
+
+    pub fn apply<'s>(&self, input: &'s str) -> Cow<'s, str> {
+        self.store(input).0
+    }
+
+    /// The stored text, and whether the length limit cut it
+    pub fn store<'s>(&self, input: &'s str) -> (Cow<'s, str>, bool) {
+        if self.stored == false {
+            return (Cow::Borrowed(""), false);
+        }
+        let text = if self.strip_html {
+            Normalization::HTML.apply(input)
+        } else {
+            Cow::Borrowed(input)
+        };
+        let Some(max_chars) = self.max_chars else {
+            return (text, false);
+        };
+        let limited = match &text {
+            Cow::Borrowed(text) => Cow::Borrowed(limit_string_len(text, max_chars)),
+            Cow::Owned(text) => Cow::Owned(limit_string_len(text, max_chars).to_string()),
+        };
+        let truncated = limited.len() < text.len();
+        (limited, truncated)
+    }
+
+    pub fn is_stored(&self) -> bool {
+        self.stored
+    }
+}
+
+impl Default for StoragePolicy {
+    fn default() -> Self {
+        StoragePolicy::FULL
+    }
+}
+
+impl Serializable for StoragePolicy {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        let StoragePolicy {
+            stored,
+            strip_html,
+            max_chars,
+        } = self;
+        let flags =
+            u8::from(*stored) | u8::from(*strip_html) << 1 | u8::from(max_chars.is_some()) << 2;
+        flags.serialize(output);
+        if let Some(max_chars) = max_chars {
+            max_chars.serialize(output);
+        }
+    }
+}
+
+impl Deserializable for StoragePolicy {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (input, flags) = u8::deserialize(input)?;
+        let (input, max_chars) = if flags & 1 << 2 != 0 {
+            let (input, max_chars) = usize::deserialize(input)?;
+            (input, Some(max_chars))
+        } else {
+            (input, None)
+        };
+        Some((
+            input,
+            StoragePolicy {
+                stored: flags & 1 != 0,
+                strip_html: flags & 1 << 1 != 0,
+                max_chars,
+            },
+        ))
+    }
+}
+
+/// The product fields a storage policy can be set for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProductField {
+    Title,
+    Description,
+}
+
+impl ProductField {
+    pub fn from_name(name: &str) -> Option<ProductField> {
+        match name {
+            "title" => Some(ProductField::Title),
+            "description" => Some(ProductField::Description),
+            _ => None,
+        }
+    }
+}
+
+/// Reads a number that has to be finite, so the configs holding one can still be `Eq`
+pub(crate) fn finite_f32<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<f32, D::Error> {
+    let value = <f32 as serde::Deserialize>::deserialize(deserializer)?;
+    if value.is_finite() {
+        Ok(value)
+    } else {
+        Err(serde::de::Error::custom("expected a finite number"))
+    }
+}
+
+/// Settings that decide how an index is built.
+///
+/// The config is stored in the index, so a loaded index knows how its products were stored.
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct IndexConfig {
+    pub title: StoragePolicy,
+    pub description: StoragePolicy,
+    pub preprocess: PreprocessConfig,
+    pub pruning: GramPruning,
+    pub analysis: AnalysisConfig,
+    pub synonyms: Synonyms,
+    // Used by searches that don't bring their own
+    pub typo_tolerance: TypoTolerance,
+    // Written in the index header rather than with the rest of the config, see `write_sections`
+    pub normalization: TextNormalization,
+    // Written in the rules section, so the rules can be replaced on their own
+    pub rules: MerchandisingRules,
+    // Only used while indexing, so it isn't written at all
+    pub validation: ValidationPolicy,
+}
+
+impl IndexConfig {
+    pub fn policy(&self, field: ProductField) -> StoragePolicy {
+        match field {
+            ProductField::Title => self.title,
+            ProductField::Description => self.description,
+        }
+    }
+}
+
+impl Default for IndexConfig {
+    fn default() -> Self {
+        IndexConfig {
+            title: StoragePolicy::FULL,
+            // Only the start of the description is shown on result cards
+            description: StoragePolicy {
+                stored: true,
+                strip_html: false,
+                max_chars: Some(100),
+            },
+            preprocess: PreprocessConfig::default(),
+            pruning: GramPruning::default(),
+            analysis: AnalysisConfig::default(),
+            synonyms: Synonyms::default(),
+            typo_tolerance: TypoTolerance::default(),
+            normalization: TextNormalization::default(),
+            rules: MerchandisingRules::default(),
+            validation: ValidationPolicy::default(),
+        }
+    }
+}
+
+impl Serializable for IndexConfig {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        let IndexConfig {
+            title,
+            description,
+            preprocess,
+            pruning,
+            analysis,
+            synonyms,
+            typo_tolerance,
+            ..
+        } = self;
+        title.serialize(output);
+        description.serialize(output);
+        preprocess.serialize(output);
+        pruning.serialize(output);
+        analysis.serialize(output);
+        synonyms.serialize(output);
+        typo_tolerance.serialize(output);
+    }
+}
+
+impl Deserializable for IndexConfig {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (input, title) = StoragePolicy::deserialize(input)?;
+        let (input, description) = StoragePolicy::deserialize(input)?;
+        let (input, preprocess) = PreprocessConfig::deserialize(input)?;
+        let (input, pruning) = GramPruning::deserialize(input)?;
+        let (input, analysis) = AnalysisConfig::deserialize(input)?;
+        let (input, synonyms) = Synonyms::deserialize(input)?;
+        let (input, typo_tolerance) = TypoTolerance::deserialize(input)?;
+        Some((
+            input,
+            IndexConfig {
+                title,
+                description,
+                preprocess,
+                pruning,
+                analysis,
+                synonyms,
+                typo_tolerance,
+                normalization: TextNormalization::default(),
+                rules: MerchandisingRules::default(),
+                validation: ValidationPolicy::default(),
+            },
+        ))
+    }
+}
+
+#[test]
+fn test_storage_policies() {
+    use crate::testing::{build_test_index, test_products};
+
+    let config = IndexConfig {
+        title: StoragePolicy::NOT_STORED,
+        description: StoragePolicy {
+            max_chars: Some(20),
+            ..StoragePolicy::FULL
+        },
+        ..IndexConfig::default()
+    };
+    let built = build_test_index(test_products(), config.clone());
+    let (original, stored) = (built.index.container, built.container);
+
+    assert_eq!(stored.config, config);
+    let mut saw_truncated = false;
+    for (original, stored) in original.products.iter().zip(&stored.products) {
+        assert_eq!(original.id, stored.id);
+        assert!(stored.title.is_empty());
+        assert!(original.description.starts_with(&stored.description));
+        assert!(stored.description.chars().count() <= 20);
+
+        let truncated = stored.truncated.contains(ProductField::Description);
+        assert_eq!(
+            truncated,
+            stored.description.len() < original.description.len()
+        );
+        assert!(stored.truncated.contains(ProductField::Title) == false);
+        saw_truncated |= truncated;
+    }
+    assert!(saw_truncated);
+
+    // Descriptions can be stripped of their markup before they're cut
+    let mut products = test_products();
+    products[0].description = format!("<p>{}</p>", "Abstrakt <b>kunst</b> &amp; ".repeat(10));
+    let mut config = IndexConfig::default();
+    config.description.strip_html = true;
+    let built = build_test_index(products, config.clone());
+    let (original, stored) = (built.index.container, built.container);
+    assert_eq!(stored.config, config);
+    for (original, stored) in original.products.iter().zip(&stored.products) {
+        let text = Normalization::HTML.apply(&original.description);
+        assert!(text.starts_with(stored.description.as_str()));
+        assert!(stored.description.chars().count() <= 100);
+        assert_eq!(
+            stored.truncated.contains(ProductField::Description),
+            stored.description.len() < text.len()
+        );
+    }
+    let stored = &stored.products[0];
+    assert!(stored.description.starts_with("Abstrakt kunst & Abstrakt"));
+    assert!(stored.truncated.contains(ProductField::Description));
+}
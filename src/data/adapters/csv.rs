@@ -0,0 +1,141 @@
+use std::io::{self, Read};
+
+use ::csv::{ReaderBuilder, StringRecord};
+use ahash::AHashMap;
+
+use super::mapping::{FieldMapping, Source};
+use crate::data::{IndexReport, Outcome, ProductIssue, RawProduct, RawVariant};
+
+// A row of the file, with the positions of the columns by their header
+struct Row<'r> {
+    columns: &'r AHashMap<String, usize>,
+    record: &'r StringRecord,
+}
+
+impl Source for Row<'_> {
+    fn text(&self, column: &str) -> Option<String> {
+        let text = self.record.get(*self.columns.get(column)?)?.trim();
+        (text.is_empty() == false).then(|| text.to_string())
+    }
+}
+
+/// Reads products from CSV with a header row, finding their fields by the mapping's column headers.
+/// With a variant id column every row is a variant, and the rows of a product are merged into it
+pub fn read_csv<R: Read>(
+    reader: R,
+    mapping: &FieldMapping,
+    report: &mut IndexReport,
+    mut add: impl FnMut(RawProduct<'static>),
+) -> Result<(), ::csv::Error> {
+    let delimiter = u8::try_from(mapping.delimiter).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("the delimiter {:?} isn't a single byte", mapping.delimiter),
+        )
+    })?;
+    let mut reader = ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(reader);
+    let columns: AHashMap<String, usize> = reader
+        .headers()?
+        .iter()
+        .enumerate()
+        .map(|(i, header)| (header.trim().to_string(), i))
+        .collect();
+    let missing: Vec<&str> = mapping
+        .fields()
+        .filter(|field| columns.contains_key(*field) == false)
+        .collect();
+    if missing.is_empty() == false {
+        let message = format!("the CSV has no column {}", missing.join(", "));
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message).into());
+    }
+
+    // The first row of every product, with the variants of all its rows, in the order they're given
+    let mut products: Vec<(String, StringRecord, Vec<RawVariant<'static>>)> = Vec::new();
+    let mut positions: AHashMap<String, usize> = AHashMap::new();
+    for record in reader.records() {
+        let record = record?;
+        let line = record
+            .position()
+            .map_or_else(String::new, |position| position.line().to_string());
+        let row = Row {
+            columns: &columns,
+            record: &record,
+        };
+        if mapping.variant_id.is_none() {
+            match mapping.product(&row, Vec::new()) {
+                Ok(product) => add(product),
+                Err(issue) => report.add(&line, issue, Outcome::Skipped),
+            }
+            continue;
+        }
+
+        let Some(id) = row.text(&mapping.id) else {
+            let issue = ProductIssue::MissingField {
+                field: "id".to_string(),
+            };
+            report.add(&line, issue, Outcome::Skipped);
+            continue;
+        };
+        let variant = mapping.variant(&row);
+        let position = *positions.entry(id).or_insert_with(|| {
+            products.push((line, record.clone(), Vec::new()));
+            products.len() - 1
+        });
+        products[position].2.extend(variant);
+    }
+
+    for (line, record, variants) in products {
+        let row = Row {
+            columns: &columns,
+            record: &record,
+        };
+        match mapping.product(&row, variants) {
+            Ok(product) => add(product),
+            Err(issue) => report.add(&line, issue, Outcome::Skipped),
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_read_csv() {
+    let mapping: FieldMapping = serde_json::from_str(
+        r#"{"id": "Handle", "title": "Title", "tags": "Tags", "price": "Price",
+        "currency": "Currency", "default_currency": "DKK", "variant_id": "Variant",
+        "sku": "SKU", "delimiter": ";", "options": [{"name": "Size", "source": "Size"}]}"#,
+    )
+    .unwrap();
+    let input = "Handle;Title;Tags;Price;Currency;Variant;SKU;Size\n\
+        camouflage;\"Camouflage; 01\";Kunst, Beige;375.0;;v1;P-1;30x40\n\
+        forest;Forest;;99.95;EUR;v3;P-3;\n\
+        camouflage;;;875.0;;v2;P-2;70x100\n\
+        ;Nameless;;10;;v4;;\n";
+
+    let mut report = IndexReport::default();
+    let mut products = Vec::new();
+    read_csv(input.as_bytes(), &mapping, &mut report, |product| {
+        products.push(product);
+    })
+    .unwrap();
+
+    assert_eq!(products.len(), 2);
+    let camouflage = &products[0];
+    assert_eq!(camouflage.title, "Camouflage; 01");
+    assert_eq!(camouflage.tags, ["Kunst", "Beige"]);
+    assert_eq!(camouflage.variants.len(), 2);
+    assert_eq!(camouflage.price.min.amount, "375.0");
+    assert_eq!(camouflage.price.max.amount, "875.0");
+    assert_eq!(camouflage.options[0].values, ["30x40", "70x100"]);
+    assert_eq!(products[1].price.min.currency_code, "EUR");
+    assert_eq!(report.skipped, 1);
+
+    let mapping = FieldMapping {
+        title: "Name".to_string(),
+        ..mapping
+    };
+    let missing = read_csv(input.as_bytes(), &mapping, &mut report, |_| {});
+    assert!(missing.is_err());
+}
@@ -0,0 +1,376 @@
+use std::{borrow::Cow, io::BufRead};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::data::{
+    raw_parser::{CurrencyAmount, MediaItem, ProductPrice},
+    reader::read_values,
+    IndexReport, InputFormat, Outcome, ProductIssue, RawProduct, RawProductOption,
+    RawSelectedOption, RawVariant,
+};
+
+/// Where the fields of a product are found in the input, as column headers for CSV,
+/// or as JSON pointers like "/name/en" for JSON. Only the id and title are required
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FieldMapping {
+    pub id: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub vendor: Option<String>,
+    pub tags: Option<String>,
+    // Tags and option values given in one string are split by this
+    #[serde(default = "comma")]
+    pub separator: String,
+    // The price of a product or variant, products without one cost what their variants cost
+    pub price: Option<String>,
+    pub max_price: Option<String>,
+    pub currency: Option<String>,
+    // The currency of prices without a currency field
+    pub default_currency: String,
+    pub image_url: Option<String>,
+    pub language: Option<String>,
+    #[serde(default)]
+    pub options: Vec<OptionMapping>,
+    // For JSON, the array of variants, whose fields are then found relative to each of them.
+    // For CSV, every row with a variant id is a variant, and rows with the same product id are merged
+    pub variants: Option<String>,
+    pub variant_id: Option<String>,
+    pub variant_title: Option<String>,
+    pub sku: Option<String>,
+    pub barcode: Option<String>,
+    // Variants with this field are sold out unless it's "true", "yes" or "1"
+    pub available: Option<String>,
+    // The delimiter of CSV, for files from spreadsheets set to use semicolons
+    #[serde(default = "comma_delimiter")]
+    pub delimiter: char,
+}
+
+/// A field whose values are options of a category, like the sizes of a poster
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OptionMapping {
+    pub name: String,
+    pub source: String,
+}
+
+fn comma() -> String {
+    ",".to_string()
+}
+
+fn comma_delimiter() -> char {
+    ','
+}
+
+fn split(text: Option<String>, separator: &str) -> Vec<String> {
+    let text = text.unwrap_or_default();
+    text.split(separator)
+        .map(str::trim)
+        .filter(|value| value.is_empty() == false)
+        .map(str::to_string)
+        .collect()
+}
+
+// A CSV row or a JSON product, which the fields of a mapping are looked up in
+pub(super) trait Source {
+    // The text of a field, missing if it's empty
+    fn text(&self, field: &str) -> Option<String>;
+
+    // The values of a field holding a list, or a string of values
+    fn list(&self, field: &str, separator: &str) -> Vec<String> {
+        split(self.text(field), separator)
+    }
+}
+
+impl Source for Value {
+    fn text(&self, field: &str) -> Option<String> {
+        let text = match self.pointer(field)? {
+            Value::String(text) => text.trim().to_string(),
+            Value::Number(number) => number.to_string(),
+            Value::Bool(bool) => bool.to_string(),
+            _ => return None,
+        };
+        (text.is_empty() == false).then_some(text)
+    }
+
+    fn list(&self, field: &str, separator: &str) -> Vec<String> {
+        match self.pointer(field) {
+            Some(Value::Array(values)) => values.iter().filter_map(|v| v.text("")).collect(),
+            _ => split(self.text(field), separator),
+        }
+    }
+}
+
+impl FieldMapping {
+    // Every field that has to be in the input, to check CSV headers up front
+    pub(super) fn fields(&self) -> impl Iterator<Item = &str> {
+        let optional = [
+            &self.description,
+            &self.vendor,
+            &self.tags,
+            &self.price,
+            &self.max_price,
+            &self.currency,
+            &self.image_url,
+            &self.language,
+            &self.variant_id,
+            &self.variant_title,
+            &self.sku,
+            &self.barcode,
+            &self.available,
+        ];
+        [&self.id, &self.title]
+            .into_iter()
+            .chain(optional.into_iter().flatten())
+            .chain(self.options.iter().map(|option| &option.source))
+            .map(String::as_str)
+    }
+
+    fn amount(&self, source: &impl Source, amount: String) -> CurrencyAmount {
+        let currency = self.currency.as_ref().and_then(|field| source.text(field));
+        CurrencyAmount {
+            amount,
+            currency_code: currency.unwrap_or_else(|| self.default_currency.clone()),
+        }
+    }
+
+    /// The variant a source describes, if the mapping has variant ids
+    pub(super) fn variant(&self, source: &impl Source) -> Option<RawVariant<'static>> {
+        let text = |field: &Option<String>| field.as_ref().and_then(|field| source.text(field));
+        let id = text(&self.variant_id)?;
+        let available = text(&self.available);
+        Some(RawVariant {
+            id: Cow::Owned(id),
+            title: Cow::Owned(text(&self.variant_title).unwrap_or_default()),
+            sku: text(&self.sku).map(Cow::Owned),
+            barcode: text(&self.barcode).map(Cow::Owned),
+            available_for_sale: available.is_none_or(|available| {
+                matches!(available.to_lowercase().as_str(), "true" | "yes" | "1")
+            }),
+            price: text(&self.price).map(|amount| self.amount(source, amount)),
+            price_lists: Vec::new(),
+            media: text(&self.image_url)
+                .map(|url| MediaItem {
+                    url: Cow::Owned(url),
+                })
+                .into_iter()
+                .collect(),
+            selected_options: self
+                .options
+                .iter()
+                .filter_map(|option| {
+                    Some(RawSelectedOption {
+                        name: Cow::Owned(option.name.clone()),
+                        value: Cow::Owned(source.text(&option.source)?),
+                    })
+                })
+                .collect(),
+        })
+    }
+
+    /// The product a source describes, with the variants found for it
+    pub(super) fn product(
+        &self,
+        source: &impl Source,
+        variants: Vec<RawVariant<'static>>,
+    ) -> Result<RawProduct<'static>, ProductIssue> {
+        let text = |field: &Option<String>| field.as_ref().and_then(|field| source.text(field));
+        let required = |field: &str, name: &str| {
+            source
+                .text(field)
+                .ok_or_else(|| ProductIssue::MissingField {
+                    field: name.to_string(),
+                })
+        };
+        let id = required(&self.id, "id")?;
+        let title = required(&self.title, "title")?;
+
+        // The price covers the variants, as the price of a CSV row is the price of its variant
+        let own: Vec<CurrencyAmount> = text(&self.price)
+            .into_iter()
+            .chain(text(&self.max_price))
+            .map(|amount| self.amount(source, amount))
+            .collect();
+        let variant_prices = variants.iter().filter_map(|variant| variant.price.as_ref());
+        let (price, price_lists) =
+            price_ranges(own.iter().chain(variant_prices)).ok_or_else(|| {
+                ProductIssue::MissingField {
+                    field: "price".to_string(),
+                }
+            })?;
+
+        Ok(RawProduct {
+            description: text(&self.description).unwrap_or_default(),
+            tags: self
+                .tags
+                .as_ref()
+                .map(|field| source.list(field, &self.separator))
+                .unwrap_or_default()
+                .into_iter()
+                .map(Cow::Owned)
+                .collect(),
+            title: Cow::Owned(title),
+            vendor: Cow::Owned(text(&self.vendor).unwrap_or_default()),
+            id: Cow::Owned(id),
+            options: self.product_options(source, &variants),
+            price,
+            price_lists,
+            media: text(&self.image_url)
+                .map(|url| MediaItem {
+                    url: Cow::Owned(url),
+                })
+                .into_iter()
+                .collect(),
+            language: text(&self.language).map(Cow::Owned),
+            variants,
+            collections: Vec::new(),
+        })
+    }
+
+    // The values of the options of the product itself, followed by the ones of its variants
+    fn product_options(
+        &self,
+        source: &impl Source,
+        variants: &[RawVariant<'static>],
+    ) -> Vec<RawProductOption<'static>> {
+        let mut options = Vec::new();
+        for mapping in &self.options {
+            let mut values: Vec<Cow<'static, str>> = source
+                .list(&mapping.source, &self.separator)
+                .into_iter()
+                .map(Cow::Owned)
+                .collect();
+            let selected = variants
+                .iter()
+                .flat_map(|variant| &variant.selected_options);
+            for option in selected.filter(|option| option.name == mapping.name.as_str()) {
+                if values.contains(&option.value) == false {
+                    values.push(option.value.clone());
+                }
+            }
+            if values.is_empty() == false {
+                options.push(RawProductOption {
+                    name: Cow::Owned(mapping.name.clone()),
+                    values,
+                });
+            }
+        }
+        options
+    }
+}
+
+// The range of the prices in every currency they're in. The currency of the first of them is the main price,
+// and the others are its price lists
+fn price_ranges<'p>(
+    prices: impl Iterator<Item = &'p CurrencyAmount>,
+) -> Option<(ProductPrice, Vec<ProductPrice>)> {
+    let mut currencies: Vec<Vec<&CurrencyAmount>> = Vec::new();
+    for price in prices {
+        let same_currency = currencies
+            .iter_mut()
+            .find(|prices| prices[0].currency_code == price.currency_code);
+        match same_currency {
+            Some(prices) => prices.push(price),
+            None => currencies.push(vec![price]),
+        }
+    }
+    let mut ranges = currencies.iter().map(|prices| price_range(prices));
+    let price = ranges.next()?;
+    Some((price, ranges.collect()))
+}
+
+// The range of prices in one currency
+fn price_range(prices: &[&CurrencyAmount]) -> ProductPrice {
+    let readable: Vec<(u64, &CurrencyAmount)> = prices
+        .iter()
+        .filter_map(|price| Some((price.minor_units()?, *price)))
+        .collect();
+    let min = readable.iter().min_by_key(|(units, _)| *units);
+    let max = readable.iter().max_by_key(|(units, _)| *units);
+    // Prices that can't be read are kept, so validation reports them
+    let (min, max) = match min.zip(max) {
+        Some(((_, min), (_, max))) => (*min, *max),
+        None => (prices[0], prices[0]),
+    };
+    ProductPrice {
+        min: min.clone(),
+        max: max.clone(),
+    }
+}
+
+/// Reads products of any JSON shape, finding their fields with the mapping's JSON pointers
+pub fn read_mapped<R: BufRead>(
+    reader: R,
+    format: InputFormat,
+    mapping: &FieldMapping,
+    report: &mut IndexReport,
+    mut add: impl FnMut(RawProduct<'static>),
+) -> Result<(), serde_json::Error> {
+    read_values(reader, format, |key, value| {
+        let product = serde_json::from_str::<Value>(value)
+            .map_err(|e| ProductIssue::unreadable(&e))
+            .and_then(|value| {
+                let variants = mapping
+                    .variants
+                    .as_ref()
+                    .and_then(|field| value.pointer(field))
+                    .and_then(Value::as_array)
+                    .map(|variants| variants.iter().filter_map(|v| mapping.variant(v)).collect())
+                    .unwrap_or_default();
+                mapping.product(&value, variants)
+            });
+        match product {
+            Ok(product) => add(product),
+            Err(issue) => report.add(key, issue, Outcome::Skipped),
+        }
+    })
+}
+
+#[test]
+fn test_read_mapped() {
+    let mapping: FieldMapping = serde_json::from_str(
+        r#"{"id": "/sku", "title": "/name/da", "tags": "/keywords", "vendor": "/brand/name",
+        "default_currency": "EUR", "variants": "/sizes", "variant_id": "/code",
+        "variant_title": "/label", "price": "/cost", "currency": "/currency", "available": "/in_stock",
+        "options": [{"name": "Size", "source": "/label"}]}"#,
+    )
+    .unwrap();
+    let input = r#"[
+        {"sku": "P-1", "name": {"da": "Plakat"}, "keywords": "kunst, plakat", "brand": {"name": "Art"},
+            "sizes": [{"code": "P-1-S", "label": "30x40", "cost": 75, "in_stock": true},
+                {"code": "P-1-L", "label": "50x70", "cost": "120.5", "in_stock": false},
+                {"code": "P-1-L-DK", "label": "50x70", "cost": 899, "currency": "DKK"}]},
+        {"sku": "P-2", "name": {"en": "Poster"}}
+    ]"#;
+
+    let mut report = IndexReport::default();
+    let mut products = Vec::new();
+    read_mapped(
+        input.as_bytes(),
+        InputFormat::Auto,
+        &mapping,
+        &mut report,
+        |product| products.push(product),
+    )
+    .unwrap();
+
+    assert_eq!(products.len(), 1);
+    let product = &products[0];
+    assert_eq!(product.title, "Plakat");
+    assert_eq!(product.tags, ["kunst", "plakat"]);
+    // The product costs what its variants cost
+    assert_eq!(product.price.min.amount, "75");
+    assert_eq!(product.price.max.amount, "120.5");
+    assert_eq!(product.price.min.currency_code, "EUR");
+    // Prices in other currencies are price lists
+    assert_eq!(product.price_lists.len(), 1);
+    assert_eq!(product.price_lists[0].min.amount, "899");
+    assert_eq!(product.price_lists[0].max.currency_code, "DKK");
+    assert_eq!(product.options[0].values, ["30x40", "50x70"]);
+    assert!(!product.variants[1].available_for_sale);
+    assert_eq!(
+        report.diagnostics[0].issue,
+        ProductIssue::MissingField {
+            field: "title".to_string()
+        }
+    );
+}
@@ -0,0 +1,7 @@
+mod csv;
+mod mapping;
+mod shopify_bulk;
+
+pub use self::csv::read_csv;
+pub use mapping::{read_mapped, FieldMapping, OptionMapping};
+pub use shopify_bulk::read_shopify_bulk;
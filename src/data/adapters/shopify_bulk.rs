@@ -0,0 +1,169 @@
+use std::{borrow::Cow, io::BufRead};
+
+use ahash::AHashMap;
+use serde::Deserialize;
+
+use crate::data::{
+    raw_parser::MediaItem, reader::read_values, IndexReport, InputFormat, Outcome, ProductIssue,
+    RawCollection, RawProduct, RawVariant,
+};
+
+const VARIANT_ID: &str = "gid://shopify/ProductVariant/";
+const COLLECTION_ID: &str = "gid://shopify/Collection/";
+
+// What a line of a bulk export is, children have the id of the object they belong to
+#[derive(Deserialize)]
+struct Line<'a> {
+    #[serde(default, borrow)]
+    id: Option<Cow<'a, str>>,
+    #[serde(rename = "__parentId", default, borrow)]
+    parent: Option<Cow<'a, str>>,
+    // Media have their url at the top, or in their image
+    #[serde(default, borrow)]
+    url: Option<Cow<'a, str>>,
+    #[serde(default, borrow)]
+    image: Option<MediaItem<'a>>,
+}
+
+#[derive(Clone, Copy)]
+enum Parent {
+    Product(usize),
+    Variant(usize, usize),
+}
+
+/// Reads a Shopify bulk operation export, where variants, media and collections are on lines of their own,
+/// with the id of their product or variant in `__parentId`.
+/// Products are given once the whole export is read, as Shopify doesn't promise that the children of a product
+/// come before the next product. Every product of the export is held in memory until then,
+/// so a large export takes about as much memory as its products would as JSON
+pub fn read_shopify_bulk<R: BufRead>(
+    reader: R,
+    report: &mut IndexReport,
+    add: impl FnMut(RawProduct<'static>),
+) -> Result<(), serde_json::Error> {
+    let mut products: Vec<RawProduct<'static>> = Vec::new();
+    let mut parents: AHashMap<String, Parent> = AHashMap::new();
+
+    read_values(reader, InputFormat::Lines, |line_number, value| {
+        let line = match serde_json::from_str::<Line>(value) {
+            Ok(line) => line,
+            Err(e) => {
+                return report.add(line_number, ProductIssue::unreadable(&e), Outcome::Skipped)
+            }
+        };
+        let Some(parent_id) = line.parent else {
+            match serde_json::from_str::<RawProduct>(value) {
+                Ok(product) => {
+                    parents.insert(product.id.to_string(), Parent::Product(products.len()));
+                    products.push(product.into_owned());
+                }
+                Err(e) => report.add(line_number, ProductIssue::unreadable(&e), Outcome::Skipped),
+            }
+            return;
+        };
+        let Some(parent) = parents.get(parent_id.as_ref()).copied() else {
+            let issue = ProductIssue::Malformed {
+                message: format!("the parent {parent_id} isn't before it in the export"),
+            };
+            return report.add(line_number, issue, Outcome::Skipped);
+        };
+
+        match (parent, line.id) {
+            (Parent::Product(product), Some(id)) if id.starts_with(VARIANT_ID) => {
+                match serde_json::from_str::<RawVariant>(value) {
+                    Ok(variant) => {
+                        let variants = &mut products[product].variants;
+                        parents.insert(id.to_string(), Parent::Variant(product, variants.len()));
+                        variants.push(variant.into_owned());
+                    }
+                    Err(e) => {
+                        report.add(line_number, ProductIssue::unreadable(&e), Outcome::Skipped);
+                    }
+                }
+            }
+            (Parent::Product(product), Some(id)) if id.starts_with(COLLECTION_ID) => {
+                match serde_json::from_str::<RawCollection>(value) {
+                    Ok(collection) => products[product].collections.push(collection.into_owned()),
+                    Err(e) => {
+                        report.add(line_number, ProductIssue::unreadable(&e), Outcome::Skipped);
+                    }
+                }
+            }
+            (parent, _) => {
+                // Other children, like metafields, aren't indexed
+                let Some(url) = line.url.or(line.image.map(|image| image.url)) else {
+                    return;
+                };
+                let media = MediaItem {
+                    url: Cow::Owned(url.into_owned()),
+                };
+                match parent {
+                    Parent::Product(product) => products[product].media.push(media),
+                    Parent::Variant(product, variant) => {
+                        products[product].variants[variant].media.push(media);
+                    }
+                }
+            }
+        }
+    })?;
+
+    products.into_iter().for_each(add);
+    Ok(())
+}
+
+#[test]
+fn test_read_shopify_bulk() {
+    use serde_json::Value;
+
+    // The products of test.json are bulk exported products with their children put back in them
+    let file = std::fs::read_to_string("./test.json").unwrap();
+    let products: AHashMap<String, Value> = serde_json::from_str(&file).unwrap();
+    let mut products: Vec<Value> = products.into_iter().map(|(_, p)| p).take(20).collect();
+    products.sort_by_key(|product| product["id"].as_str().unwrap_or_default().to_string());
+
+    let mut lines = Vec::new();
+    for product in &products {
+        let mut product = product.clone();
+        let children = product.as_object_mut().unwrap();
+        let media = children.remove("media").unwrap_or_default();
+        let variants = children.remove("variants").unwrap_or_default();
+        lines.push(product.to_string());
+        for child in media
+            .as_array()
+            .into_iter()
+            .chain(variants.as_array())
+            .flatten()
+        {
+            lines.push(child.to_string());
+        }
+    }
+    lines.push(
+        r#"{"id": "gid://shopify/Collection/1", "__parentId": "gid://shopify/Product/1"}"#
+            .to_string(),
+    );
+    let first = products[0]["id"].as_str().unwrap();
+    lines.push(format!(
+        r#"{{"id": "gid://shopify/Collection/2", "title": "Posters", "handle": "posters", "__parentId": "{first}"}}"#
+    ));
+
+    let mut report = IndexReport::default();
+    let mut read = Vec::new();
+    read_shopify_bulk(lines.join("\n").as_bytes(), &mut report, |product| {
+        read.push(product);
+    })
+    .unwrap();
+
+    let products: Vec<String> = products.iter().map(Value::to_string).collect();
+    let mut expected: Vec<RawProduct> = products
+        .iter()
+        .map(|product| serde_json::from_str(product).unwrap())
+        .collect();
+    expected[0].collections.push(RawCollection {
+        id: "gid://shopify/Collection/2".into(),
+        title: "Posters".into(),
+        handle: "posters".into(),
+        position: None,
+    });
+    assert_eq!(read, expected);
+    assert_eq!(report.skipped, 1);
+}
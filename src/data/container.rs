@@ -1,15 +1,23 @@
 use colosseum::sync::Arena;
 use std::sync::Arc;
 
+use crate::config::IndexConfig;
 use crate::serialize::{ArenaDeserializableCollection, Deserializable, Serializable};
 
-use super::{vendor::VendorManager, FeatureSet, Product};
+use super::{vendor::VendorManager, FeatureSet, Prices, Product, Variant};
 
 #[derive(PartialEq, Eq)]
 pub struct ProductContainer<'a> {
     pub products: Vec<Product<'a>>,
     pub vendors: Arc<VendorManager<'a>>,
     pub extra_features: FeatureSet,
+    pub config: IndexConfig,
+    pub variants: Vec<Variant>,
+    // The features of the variants, by the variant's serialization id
+    pub variant_features: FeatureSet,
+    // Exact prices in every currency, kept out of the float features
+    pub prices: Prices,
+    pub variant_prices: Prices,
 }
 
 impl<'a> ProductContainer<'a> {
@@ -17,11 +25,17 @@ impl<'a> ProductContainer<'a> {
         products: Vec<Product<'a>>,
         vendors: Arc<VendorManager<'a>>,
         extra_features: FeatureSet,
+        config: IndexConfig,
     ) -> ProductContainer<'a> {
         ProductContainer {
             products,
             vendors,
             extra_features,
+            config,
+            variants: Vec::new(),
+            variant_features: FeatureSet::new_empty(),
+            prices: Prices::default(),
+            variant_prices: Prices::default(),
         }
     }
 
@@ -33,6 +47,7 @@ impl<'a> ProductContainer<'a> {
         'outerarena: 'a,
         'a: 'input,
     {
+        let (input, config) = IndexConfig::deserialize(input)?;
         let (input, vendors) =
             VendorManager::deserialize_arena(input, super_alloc.alloc(Arena::new()))?;
 
@@ -41,14 +56,30 @@ impl<'a> ProductContainer<'a> {
         let (mut input, product_count) = usize::deserialize(input)?;
         let (input, products) = {
             let mut products = Vec::new();
+            let mut first_variant = 0;
             for id in 0..product_count {
-                let (new_input, product) = Product::deserialize(input, id, &vendors)?;
+                let (new_input, product) =
+                    Product::deserialize(input, id, first_variant, &vendors)?;
+                first_variant = product.variants.end;
                 products.push(product);
                 input = new_input;
             }
             (input, products)
         };
-        let (input, extra_features) = FeatureSet::deserialize(input)?;
+        let (mut input, extra_features) = FeatureSet::deserialize(input)?;
+
+        let mut variants = Vec::new();
+        for product in &products {
+            for id in product.variants.clone() {
+                let (new_input, variant) =
+                    Variant::deserialize(input, product.serialization_id, id)?;
+                variants.push(variant);
+                input = new_input;
+            }
+        }
+        let (input, variant_features) = FeatureSet::deserialize(input)?;
+        let (input, prices) = Prices::deserialize(input)?;
+        let (input, variant_prices) = Prices::deserialize(input)?;
 
         Some((
             input,
@@ -56,6 +87,11 @@ impl<'a> ProductContainer<'a> {
                 products,
                 vendors,
                 extra_features,
+                config,
+                variants,
+                variant_features,
+                prices,
+                variant_prices,
             },
         ))
     }
@@ -67,11 +103,26 @@ impl<'a> Serializable for ProductContainer<'a> {
             products,
             vendors,
             extra_features,
+            config,
+            variants,
+            variant_features,
+            prices,
+            variant_prices,
         } = self;
+        config.serialize(output);
         vendors.serialize(output);
 
-        products.serialize(output);
+        products.len().serialize(output);
+        for product in products {
+            product.serialize(config, output);
+        }
         extra_features.serialize(output);
+        for variant in variants {
+            variant.serialize(output);
+        }
+        variant_features.serialize(output);
+        prices.serialize(output);
+        variant_prices.serialize(output);
     }
 }
 
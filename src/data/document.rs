@@ -0,0 +1,617 @@
+use ahash::{AHashMap, AHashSet};
+use serde_json::Value;
+
+use crate::{
+    config::{IndexConfig, StoragePolicy},
+    language::Language,
+    ngram::{HashExtractable, IndexedData},
+    preprocessor::Normalization,
+    serialize::{Deserializable, Serializable},
+};
+
+use super::{FeatureConflict, IndexReport, Outcome, ProductIssue, RawProduct};
+
+/// A text field of a schema, searched in the order the schema lists them
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TextField {
+    pub name: String,
+    #[serde(default)]
+    pub normalization: Normalization,
+    // How much of its text a search result can show, the field is indexed in full regardless
+    #[serde(default)]
+    pub storage: StoragePolicy,
+}
+
+/// The kind of a document, like a product, a blog post or a store location
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Schema {
+    pub name: String,
+    pub fields: Vec<TextField>,
+    // The attributes of its documents, which are stored but not searched.
+    // Attributes that aren't listed are added in the order they're found
+    #[serde(default)]
+    pub attributes: Vec<String>,
+}
+
+impl Schema {
+    /// The fields products are searched by, normalised the way `PreprocessConfig` does by default
+    /// and stored with the storage policies of the config
+    pub fn product(config: &IndexConfig) -> Schema {
+        let field = |name: &str, normalization, storage| TextField {
+            name: name.to_string(),
+            normalization,
+            storage,
+        };
+        Schema {
+            name: "product".to_string(),
+            fields: vec![
+                field("description", Normalization::HTML, config.description),
+                field("title", Normalization::PLAIN_TEXT, config.title),
+                field("vendor", Normalization::PLAIN_TEXT, StoragePolicy::FULL),
+            ],
+            attributes: vec!["image_url".to_string()],
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Option<usize> {
+        self.fields.iter().position(|field| field.name == name)
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<usize> {
+        self.attributes
+            .iter()
+            .position(|attribute| attribute == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Attribute {
+    String(String),
+    Float(f32),
+    Integer(u32),
+}
+
+impl Eq for Attribute {}
+
+impl Attribute {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Attribute::String(_) => "string",
+            Attribute::Float(_) => "float",
+            Attribute::Integer(_) => "integer",
+        }
+    }
+
+    // Whole numbers that fit are integers, like the features of products
+    fn from_json(value: &Value) -> Option<Attribute> {
+        Some(match value {
+            Value::String(string) => Attribute::String(string.clone()),
+            Value::Number(number) => match number.as_u64().map(u32::try_from) {
+                Some(Ok(integer)) => Attribute::Integer(integer),
+                #[allow(clippy::cast_possible_truncation)]
+                _ => Attribute::Float(number.as_f64()? as f32),
+            },
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Document {
+    pub id: String,
+    // The position of its schema in the container
+    pub schema: usize,
+    // The text of every field of its schema, empty if it wasn't given
+    pub fields: Vec<String>,
+    // The value of every attribute of its schema
+    pub attributes: Vec<Option<Attribute>>,
+    pub language: Option<Language>,
+    pub serialization_id: usize,
+}
+
+impl PartialOrd for Document {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Document {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.serialization_id.cmp(&other.serialization_id)
+    }
+}
+
+impl HashExtractable for Document {
+    type Inner = String;
+
+    fn extract(&self) -> &Self::Inner {
+        &self.id
+    }
+}
+
+impl IndexedData for Document {
+    type Container = DocumentContainer;
+
+    fn count(container: &DocumentContainer) -> usize {
+        container.documents.len()
+    }
+
+    fn serialization_id(&self) -> usize {
+        self.serialization_id
+    }
+}
+
+/// Documents of any schema, the counterpart of `ProductContainer` for indexes that aren't only products
+#[derive(Debug, PartialEq, Eq)]
+pub struct DocumentContainer {
+    pub schemas: Vec<Schema>,
+    pub documents: Vec<Document>,
+    pub config: IndexConfig,
+}
+
+impl DocumentContainer {
+    pub fn schema(&self, document: &Document) -> &Schema {
+        &self.schemas[document.schema]
+    }
+
+    pub fn field<'d>(&self, document: &'d Document, name: &str) -> Option<&'d str> {
+        let field = self.schema(document).field(name)?;
+        Some(&document.fields[field])
+    }
+
+    pub fn attribute<'d>(&self, document: &'d Document, name: &str) -> Option<&'d Attribute> {
+        let attribute = self.schema(document).attribute(name)?;
+        document.attributes.get(attribute)?.as_ref()
+    }
+}
+
+impl Serializable for Schema {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        let Schema {
+            name,
+            fields,
+            attributes,
+        } = self;
+        name.serialize(output);
+        fields.len().serialize(output);
+        for TextField {
+            name,
+            normalization,
+            storage,
+        } in fields
+        {
+            name.serialize(output);
+            normalization.serialize(output);
+            storage.serialize(output);
+        }
+        attributes.serialize(output);
+    }
+}
+
+impl Deserializable for Schema {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (mut input, name) = String::deserialize(input)?;
+        let (after_count, field_count) = usize::deserialize(input)?;
+        input = after_count;
+        let mut fields = Vec::with_capacity(field_count.min(input.len()));
+        for _ in 0..field_count {
+            let (after_name, name) = String::deserialize(input)?;
+            let (after_normalization, normalization) = Normalization::deserialize(after_name)?;
+            let (after_field, storage) = StoragePolicy::deserialize(after_normalization)?;
+            fields.push(TextField {
+                name,
+                normalization,
+                storage,
+            });
+            input = after_field;
+        }
+        let (input, attributes) = Deserializable::deserialize(input)?;
+        Some((
+            input,
+            Schema {
+                name,
+                fields,
+                attributes,
+            },
+        ))
+    }
+}
+
+impl Serializable for Attribute {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        match self {
+            Attribute::String(string) => {
+                0u8.serialize(output);
+                string.serialize(output);
+            }
+            Attribute::Float(float) => {
+                1u8.serialize(output);
+                float.serialize(output);
+            }
+            Attribute::Integer(integer) => {
+                2u8.serialize(output);
+                integer.serialize(output);
+            }
+        }
+    }
+}
+
+impl Deserializable for Attribute {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (input, kind) = u8::deserialize(input)?;
+        match kind {
+            0 => {
+                let (input, string) = String::deserialize(input)?;
+                Some((input, Attribute::String(string)))
+            }
+            1 => {
+                let (input, float) = f32::deserialize(input)?;
+                Some((input, Attribute::Float(float)))
+            }
+            2 => {
+                let (input, integer) = u32::deserialize(input)?;
+                Some((input, Attribute::Integer(integer)))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Document {
+    // Fields are stored as the storage policies of their schema say
+    pub fn serialize<Out: FnMut(u8)>(&self, schema_fields: &[TextField], output: &mut Out) {
+        let Document {
+            id,
+            schema,
+            fields,
+            attributes,
+            language,
+            ..
+        } = self;
+        id.serialize(output);
+        schema.serialize(output);
+        fields.len().serialize(output);
+        for (field, text) in schema_fields.iter().zip(fields) {
+            field.storage.apply(text).as_ref().serialize(output);
+        }
+        attributes.serialize(output);
+        language.serialize(output);
+    }
+
+    pub fn deserialize(input: &[u8], serialization_id: usize) -> Option<(&[u8], Self)> {
+        let (input, id) = String::deserialize(input)?;
+        let (input, schema) = usize::deserialize(input)?;
+        let (input, fields) = Deserializable::deserialize(input)?;
+        let (input, attributes) = Deserializable::deserialize(input)?;
+        let (input, language) = Deserializable::deserialize(input)?;
+        Some((
+            input,
+            Document {
+                id,
+                schema,
+                fields,
+                attributes,
+                language,
+                serialization_id,
+            },
+        ))
+    }
+}
+
+impl Serializable for DocumentContainer {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        let DocumentContainer {
+            schemas,
+            documents,
+            config,
+        } = self;
+        config.serialize(output);
+        schemas.serialize(output);
+        documents.len().serialize(output);
+        for document in documents {
+            document.serialize(&self.schema(document).fields, output);
+        }
+    }
+}
+
+impl Deserializable for DocumentContainer {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (input, config) = IndexConfig::deserialize(input)?;
+        let (input, schemas): (_, Vec<Schema>) = Deserializable::deserialize(input)?;
+        let (mut input, document_count) = usize::deserialize(input)?;
+        let mut documents = Vec::with_capacity(document_count.min(input.len()));
+        for id in 0..document_count {
+            let (next_input, document) = Document::deserialize(input, id)?;
+            if schemas.get(document.schema)?.fields.len() != document.fields.len() {
+                return None;
+            }
+            documents.push(document);
+            input = next_input;
+        }
+        Some((
+            input,
+            DocumentContainer {
+                schemas,
+                documents,
+                config,
+            },
+        ))
+    }
+}
+
+/// A document as it's given to the indexer, with its text fields and attributes by name
+#[derive(serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct RawDocument {
+    pub id: String,
+    // The name of its schema
+    pub schema: String,
+    #[serde(default)]
+    pub fields: AHashMap<String, String>,
+    // Strings and numbers, other values are left out
+    #[serde(default)]
+    pub attributes: AHashMap<String, Value>,
+    // A language code like "da", for sites in more than one language
+    #[serde(default)]
+    pub language: Option<String>,
+}
+
+/// The input of a document index, the schemas and the documents of any of them
+#[derive(serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct RawDocuments {
+    pub schemas: Vec<Schema>,
+    pub documents: Vec<RawDocument>,
+}
+
+impl RawDocument {
+    /// A product as a document of `Schema::product`.
+    ///
+    /// Only its text fields and first image are kept, so its tags, options, prices and variants
+    /// can't be filtered on or shown, the way a product index can
+    pub fn from_product(product: &RawProduct<'_>) -> RawDocument {
+        let fields = [
+            ("description", product.description.as_str()),
+            ("title", product.title.as_ref()),
+            ("vendor", product.vendor.as_ref()),
+        ];
+        let image_url = product.media.first().map(|media| media.url.to_string());
+        RawDocument {
+            id: product.id.to_string(),
+            schema: "product".to_string(),
+            fields: fields
+                .into_iter()
+                .map(|(name, text)| (name.to_string(), text.to_string()))
+                .collect(),
+            attributes: image_url
+                .map(|url| ("image_url".to_string(), Value::String(url)))
+                .into_iter()
+                .collect(),
+            language: product.language.as_ref().map(ToString::to_string),
+        }
+    }
+}
+
+/// Turns raw documents into a container, in the order they're given.
+/// Documents without an id, with an unknown schema, or with an id seen before are skipped,
+/// and attributes that aren't strings or numbers, or whose type differs from before, are left out
+pub fn optimize_documents(
+    mut schemas: Vec<Schema>,
+    input: Vec<RawDocument>,
+    config: IndexConfig,
+    report: &mut IndexReport,
+) -> DocumentContainer {
+    let mut seen_ids = AHashSet::new();
+    // The type of every attribute, by schema, so one attribute only ever holds one type
+    let mut types: Vec<AHashMap<String, &'static str>> = vec![AHashMap::new(); schemas.len()];
+    let mut documents = Vec::with_capacity(input.len());
+
+    for raw in input {
+        if raw.id.trim().is_empty() {
+            let issue = ProductIssue::MissingField {
+                field: "id".to_string(),
+            };
+            report.add(&raw.id, issue, Outcome::Skipped);
+            continue;
+        }
+        let Some(schema) = schemas.iter().position(|schema| schema.name == raw.schema) else {
+            let issue = ProductIssue::Malformed {
+                message: format!("there's no schema called \"{}\"", raw.schema),
+            };
+            report.add(&raw.id, issue, Outcome::Skipped);
+            continue;
+        };
+        if seen_ids.insert(raw.id.clone()) == false {
+            report.add(&raw.id, ProductIssue::DuplicateId, Outcome::Skipped);
+            continue;
+        }
+
+        let fields = schemas[schema]
+            .fields
+            .iter()
+            .map(|field| raw.fields.get(&field.name).cloned().unwrap_or_default())
+            .collect();
+
+        // Attributes are sorted, so the ones a schema doesn't list are added in the same order every time
+        let mut attributes: Vec<(&String, &Value)> = raw.attributes.iter().collect();
+        attributes.sort_by_key(|(name, _)| *name);
+        let mut values = vec![None; schemas[schema].attributes.len()];
+        for (name, value) in attributes {
+            let Some(attribute) = Attribute::from_json(value) else {
+                let issue = ProductIssue::Malformed {
+                    message: format!("the attribute \"{name}\" isn't a string or a number"),
+                };
+                report.add(&raw.id, issue, Outcome::Repaired);
+                continue;
+            };
+            let expected = *types[schema]
+                .entry(name.clone())
+                .or_insert(attribute.type_name());
+            if expected != attribute.type_name() {
+                let conflict = FeatureConflict {
+                    key: name.clone(),
+                    expected,
+                    found: attribute.type_name(),
+                };
+                report.add(&raw.id, conflict.into(), Outcome::Repaired);
+                continue;
+            }
+            let position = schemas[schema].attribute(name).unwrap_or_else(|| {
+                schemas[schema].attributes.push(name.clone());
+                values.push(None);
+                values.len() - 1
+            });
+            values[position] = Some(attribute);
+        }
+
+        documents.push(Document {
+            id: raw.id,
+            schema,
+            fields,
+            attributes: values,
+            language: raw.language.as_deref().and_then(Language::from_code),
+            serialization_id: documents.len(),
+        });
+    }
+
+    // Documents from before an attribute was added don't have it
+    for document in &mut documents {
+        let attribute_count = schemas[document.schema].attributes.len();
+        document.attributes.resize(attribute_count, None);
+    }
+    report.indexed += documents.len();
+
+    DocumentContainer {
+        schemas,
+        documents,
+        config,
+    }
+}
+
+#[test]
+fn test_optimize_documents() {
+    let schemas: Vec<Schema> = serde_json::from_str(
+        r#"[{"name": "article", "fields": [{"name": "title"},
+            {"name": "body", "normalization": {"strip_html": true}, "storage": {"strip_html": true}}]},
+        {"name": "store", "fields": [{"name": "name"}, {"name": "address"}],
+            "attributes": ["opening_hour"]}]"#,
+    )
+    .unwrap();
+    let documents: Vec<RawDocument> = serde_json::from_str(
+        r#"[{"id": "a-1", "schema": "article", "fields": {"title": "Hanging posters", "body": "<p>Use a frame</p>"},
+            "attributes": {"reading_time": 4}},
+        {"id": "s-1", "schema": "store", "fields": {"name": "Copenhagen"},
+            "attributes": {"opening_hour": 10, "phone": "+45 1234"}},
+        {"id": "s-2", "schema": "store", "fields": {"name": "Aarhus"},
+            "attributes": {"opening_hour": "late", "tags": ["x"]}},
+        {"id": "a-1", "schema": "article"},
+        {"id": "f-1", "schema": "faq"}]"#,
+    )
+    .unwrap();
+
+    let mut report = IndexReport::default();
+    let mut container = optimize_documents(schemas, documents, IndexConfig::default(), &mut report);
+
+    assert_eq!(container.documents.len(), 3);
+    assert_eq!(report.skipped, 2);
+    let [article, copenhagen, aarhus] = &container.documents[..] else {
+        panic!("expected three documents");
+    };
+    assert_eq!(container.field(article, "body"), Some("<p>Use a frame</p>"));
+    assert_eq!(
+        container.attribute(article, "reading_time"),
+        Some(&Attribute::Integer(4))
+    );
+    // A missing field is empty, and attributes the schema doesn't list are added to it
+    assert_eq!(container.field(copenhagen, "address"), Some(""));
+    assert_eq!(container.schemas[1].attributes, ["opening_hour", "phone"]);
+    assert_eq!(
+        container.attribute(copenhagen, "phone"),
+        Some(&Attribute::String("+45 1234".to_string()))
+    );
+    // A value of another type than before is left out
+    assert_eq!(container.attribute(aarhus, "opening_hour"), None);
+    assert_eq!(aarhus.attributes.len(), 2);
+
+    let mut bytes = Vec::new();
+    container.serialize(&mut |byte| bytes.push(byte));
+    let (rest, deserialized) = DocumentContainer::deserialize(&bytes).unwrap();
+    assert!(rest.is_empty());
+    // The body is indexed with its HTML, but stored without it
+    let body = deserialized.field(&deserialized.documents[0], "body");
+    assert_eq!(body, Some("Use a frame"));
+    container.documents[0].fields[1] = "Use a frame".to_string();
+    assert_eq!(deserialized, container);
+}
+
+#[test]
+fn test_document_index() {
+    use crate::{
+        build_document_index,
+        config::IndexConfig,
+        ngram::{GramIndex, GramNode, TypoTolerance},
+        rank_documents,
+        serialize::{deserialize_documents, serialize_documents},
+        testing::{test_products, TEST_ARENA},
+        SearchStats,
+    };
+    use colosseum::sync::Arena;
+
+    lazy_static::lazy_static! {
+        static ref NODE_ARENA: Arena<GramNode<'static, char>> = Arena::new();
+    }
+    let products = test_products();
+
+    // Products are one schema among the content pages of the site
+    let mut input: RawDocuments = serde_json::from_str(
+        r#"{"schemas": [{"name": "article", "fields": [{"name": "title"},
+            {"name": "body", "normalization": {"strip_html": true}}]}],
+        "documents": [{"id": "guide", "schema": "article", "fields": {"title": "Hanging posters",
+            "body": "<p>Use a <b>frame</b> and two nails</p>"}, "attributes": {"minutes": 3}}]}"#,
+    )
+    .unwrap();
+    input.schemas.push(Schema::product(&IndexConfig::default()));
+    input
+        .documents
+        .extend(products.iter().map(RawDocument::from_product));
+
+    let mut report = IndexReport::default();
+    let index: GramIndex<char, Document, 5> =
+        build_document_index(input, IndexConfig::default(), &TEST_ARENA, &mut report);
+    assert_eq!(report.indexed, products.len() + 1);
+
+    let buff = serialize_documents(&index);
+    let deserialized: GramIndex<char, Document, 5> =
+        deserialize_documents(&buff, &NODE_ARENA, &TEST_ARENA).unwrap();
+    // Descriptions are truncated when they're stored, so the index only equals itself once stored
+    assert!(serialize_documents(&deserialized) == buff);
+
+    let search = |query: &str| -> Vec<String> {
+        let tolerance = TypoTolerance::default();
+        let ranked = rank_documents(&deserialized, query, &tolerance, &SearchStats::default());
+        ranked
+            .results
+            .into_iter()
+            .map(|(d, _)| d.id.clone())
+            .collect()
+    };
+    assert_eq!(search("nails").first().map(String::as_str), Some("guide"));
+    // The query syntax of products works on documents too, with fields looked up in each schema
+    assert_eq!(search(r#""two nails""#), ["guide"]);
+    assert!(search("nails -frame").contains(&"guide".to_string()) == false);
+    assert_eq!(search("nails title:hanging"), ["guide"]);
+    assert!(search("nails vendor:hanging").is_empty());
+
+    // Products are found by their own fields, like in a product index
+    let product = &products[0];
+    assert!(search(&product.title).contains(&product.id.to_string()));
+    let container = deserialized.container;
+    let guide = container
+        .documents
+        .iter()
+        .find(|d| d.id == "guide")
+        .unwrap();
+    assert_eq!(container.schema(guide).name, "article");
+    assert_eq!(
+        container.attribute(guide, "minutes"),
+        Some(&Attribute::Integer(3))
+    );
+}
@@ -16,6 +16,14 @@ pub enum Feature {
 }
 
 impl Feature {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Feature::String(_) => "string",
+            Feature::Float(_) => "float",
+            Feature::Integer(_) => "integer",
+        }
+    }
+
     pub fn get(&self, index: usize) -> Option<FeatureValue<'_>> {
         match self {
             Feature::String(list) => {
@@ -34,6 +42,36 @@ impl Feature {
     }
 }
 
+/// A value of one type added to a feature that holds another, the value is left out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeatureConflict {
+    pub key: String,
+    pub expected: &'static str,
+    pub found: &'static str,
+}
+
+impl FeatureConflict {
+    fn new(key: &str, found: &'static str, feature: &Feature) -> Self {
+        FeatureConflict {
+            key: key.to_string(),
+            expected: feature.type_name(),
+            found,
+        }
+    }
+}
+
+impl std::fmt::Display for FeatureConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "feature \"{}\" holds {} values, but got a {} value",
+            self.key, self.expected, self.found
+        )
+    }
+}
+
+impl std::error::Error for FeatureConflict {}
+
 pub enum FeatureValue<'a> {
     String(&'a str),
     Float(f32),
@@ -61,8 +99,11 @@ impl FeatureSet {
     }
 
     pub fn get<'a>(&'a self, product: &Product<'_>, key: &str) -> Option<FeatureValue<'a>> {
-        #[allow(clippy::cast_possible_truncation)]
-        let id = product.serialization_id;
+        self.get_at(product.serialization_id, key)
+    }
+
+    // For feature sets that aren't about products, like the features of variants
+    pub fn get_at(&self, id: usize, key: &str) -> Option<FeatureValue<'_>> {
         Some(match self.0.get(key)? {
             Feature::String(list) => FeatureValue::String(list.get(id)?),
             Feature::Float(list) => FeatureValue::Float(*list.get(id)?),
@@ -74,41 +115,38 @@ impl FeatureSet {
         self.0.get(key)
     }
 
-    pub fn add_int(&mut self, key: &str, value: u32) {
+    pub fn add_int(&mut self, key: &str, value: u32) -> Result<(), FeatureConflict> {
         match self.0.get_mut(key) {
-            Some(v) => match v {
-                Feature::Integer(i) => i.push(value),
-                _ => panic!("Expected Integer feature"),
-            },
+            Some(Feature::Integer(list)) => list.push(value),
+            Some(other) => return Err(FeatureConflict::new(key, "integer", other)),
             None => {
                 self.0
                     .insert(key.to_string(), Feature::Integer(vec![value]));
             }
         };
+        Ok(())
     }
 
-    pub fn add_float(&mut self, key: &str, value: f32) {
+    pub fn add_float(&mut self, key: &str, value: f32) -> Result<(), FeatureConflict> {
         match self.0.get_mut(key) {
-            Some(v) => match v {
-                Feature::Float(i) => i.push(value),
-                _ => panic!("Expected Integer feature"),
-            },
+            Some(Feature::Float(list)) => list.push(value),
+            Some(other) => return Err(FeatureConflict::new(key, "float", other)),
             None => {
                 self.0.insert(key.to_string(), Feature::Float(vec![value]));
             }
         };
+        Ok(())
     }
 
-    pub fn add_string(&mut self, key: &str, value: String) {
+    pub fn add_string(&mut self, key: &str, value: String) -> Result<(), FeatureConflict> {
         match self.0.get_mut(key) {
-            Some(v) => match v {
-                Feature::String(i) => i.push(value),
-                _ => panic!("Expected Integer feature"),
-            },
+            Some(Feature::String(list)) => list.push(value),
+            Some(other) => return Err(FeatureConflict::new(key, "string", other)),
             None => {
                 self.0.insert(key.to_string(), Feature::String(vec![value]));
             }
         };
+        Ok(())
     }
 
     pub fn new_empty() -> FeatureSet {
@@ -1,10 +1,34 @@
+#[cfg(feature = "indexing")]
+mod adapters;
 mod container;
+mod document;
 mod features;
+mod price;
 mod product;
 mod raw_parser;
+mod reader;
+mod validation;
+mod variant;
 mod vendor;
 
+#[cfg(feature = "indexing")]
+pub use adapters::{read_csv, read_mapped, read_shopify_bulk, FieldMapping, OptionMapping};
 pub use container::{ProductContainer, SuperAlloc};
+pub use document::{
+    optimize_documents, Attribute, Document, DocumentContainer, RawDocument, RawDocuments, Schema,
+    TextField,
+};
 pub use features::*;
-pub use product::Product;
-pub use raw_parser::{optimize, RawProduct, RawProductOption};
+pub use price::{format_minor_units, major_units, parse_minor_units, PriceRange, Prices};
+pub use product::{Product, TruncatedFields};
+pub use raw_parser::{
+    optimize, optimize_with_config, optimize_with_report, ProductCollector, RawCollection,
+    RawProduct, RawProductOption, RawSelectedOption, RawVariant,
+};
+pub use reader::{read_products, InputFormat};
+pub use validation::{
+    parse_products, validate_products, Diagnostic, IndexReport, Outcome, ProductIssue,
+    ProductValidator, ValidationPolicy,
+};
+pub use variant::Variant;
+pub use vendor::Vendor;
@@ -0,0 +1,270 @@
+use ahash::AHashMap;
+
+use crate::serialize::{Deserializable, Serializable};
+
+// How many digits of an amount are after the decimal point, following ISO 4217
+fn minor_unit_digits(currency: &str) -> u32 {
+    match currency {
+        "BIF" | "CLP" | "DJF" | "GNF" | "ISK" | "JPY" | "KMF" | "KRW" | "PYG" | "RWF" | "UGX"
+        | "VND" | "VUV" | "XAF" | "XOF" | "XPF" => 0,
+        "BHD" | "IQD" | "JOD" | "KWD" | "LYD" | "OMR" | "TND" => 3,
+        _ => 2,
+    }
+}
+
+/// Parses an amount like "249.95" into minor units without going through a float.
+/// Extra decimals are rounded half up, so "0.125" is 13 cents
+pub fn parse_minor_units(amount: &str, currency: &str) -> Option<u64> {
+    let digits = minor_unit_digits(currency);
+    let (whole, fraction) = amount.trim().split_once('.').unwrap_or((amount.trim(), ""));
+    let is_number = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if whole.is_empty() || is_number(whole) == false || is_number(fraction) == false {
+        return None;
+    }
+
+    let mut units: u64 = whole.parse().ok()?;
+    let mut fraction = fraction.bytes().map(|b| u64::from(b - b'0'));
+    for _ in 0..digits {
+        units = units
+            .checked_mul(10)?
+            .checked_add(fraction.next().unwrap_or(0))?;
+    }
+    if fraction.next().is_some_and(|next| next >= 5) {
+        units = units.checked_add(1)?;
+    }
+    Some(units)
+}
+
+/// The amount in minor units written with its decimals, the way it was given when indexing
+pub fn format_minor_units(units: u64, currency: &str) -> String {
+    let digits = minor_unit_digits(currency);
+    if digits == 0 {
+        return units.to_string();
+    }
+    let scale = 10u64.pow(digits);
+    format!(
+        "{}.{:0width$}",
+        units / scale,
+        units % scale,
+        width = digits as usize
+    )
+}
+
+/// The amount in whole units, like 249.95, for comparing with numbers from JavaScript
+#[allow(clippy::cast_precision_loss)]
+pub fn major_units(units: u64, currency: &str) -> f64 {
+    units as f64 / f64::from(10u32.pow(minor_unit_digits(currency)))
+}
+
+/// The lowest and highest price of a product in minor units, like cents or øre
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriceRange {
+    pub min: u64,
+    pub max: u64,
+}
+
+impl PriceRange {
+    pub fn new(a: u64, b: u64) -> Self {
+        PriceRange {
+            min: a.min(b),
+            max: a.max(b),
+        }
+    }
+
+    pub fn single(amount: u64) -> Self {
+        PriceRange {
+            min: amount,
+            max: amount,
+        }
+    }
+
+    /// The range covering both ranges
+    #[must_use]
+    pub fn merge(self, other: PriceRange) -> PriceRange {
+        PriceRange {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+}
+
+/// The price lists of products or variants by currency, indexed by their serialization ids
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Prices {
+    // The currency of the main price of the products, used when no currency is asked for
+    default_currency: String,
+    lists: AHashMap<String, Vec<Option<PriceRange>>>,
+}
+
+impl Prices {
+    pub fn set_default_currency(&mut self, currency: &str) {
+        self.default_currency = currency.to_string();
+    }
+
+    pub fn default_currency(&self) -> &str {
+        &self.default_currency
+    }
+
+    pub fn currencies(&self) -> impl Iterator<Item = &str> {
+        self.lists.keys().map(String::as_str)
+    }
+
+    pub fn add(&mut self, id: usize, currency: &str, range: PriceRange) {
+        let list = self.lists.entry(currency.to_string()).or_default();
+        if list.len() <= id {
+            list.resize(id + 1, None);
+        }
+        list[id] = Some(range);
+    }
+
+    /// The price in the currency, or in the default currency if none is given
+    pub fn get(&self, id: usize, currency: Option<&str>) -> Option<PriceRange> {
+        let currency = currency.unwrap_or(&self.default_currency);
+        *self.lists.get(currency)?.get(id)?
+    }
+}
+
+impl Serializable for PriceRange {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        self.min.serialize(output);
+        // The difference is usually small, or 0 for single prices
+        (self.max - self.min).serialize(output);
+    }
+}
+
+impl Deserializable for PriceRange {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (input, min) = u64::deserialize(input)?;
+        let (input, difference) = u64::deserialize(input)?;
+        let max = min.checked_add(difference)?;
+        Some((input, PriceRange { min, max }))
+    }
+}
+
+impl Serializable for Prices {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        self.default_currency.serialize(output);
+        let mut lists: Vec<(&String, &Vec<Option<PriceRange>>)> = self.lists.iter().collect();
+        lists.sort_by_key(|(currency, _)| *currency);
+        lists.len().serialize(output);
+        for (currency, list) in lists {
+            currency.serialize(output);
+            list.serialize(output);
+        }
+    }
+}
+
+impl Deserializable for Prices {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (mut input, default_currency) = String::deserialize(input)?;
+        let (new_input, count) = usize::deserialize(input)?;
+        input = new_input;
+        let mut lists = AHashMap::with_capacity(count);
+        for _ in 0..count {
+            let (new_input, currency) = String::deserialize(input)?;
+            let (new_input, list) = Vec::deserialize(new_input)?;
+            lists.insert(currency, list);
+            input = new_input;
+        }
+        Some((
+            input,
+            Prices {
+                default_currency,
+                lists,
+            },
+        ))
+    }
+}
+
+#[test]
+fn test_prices() {
+    assert_eq!(parse_minor_units("249.95", "DKK"), Some(24995));
+    assert_eq!(parse_minor_units("249.9", "EUR"), Some(24990));
+    assert_eq!(parse_minor_units("249", "SEK"), Some(24900));
+    assert_eq!(parse_minor_units("0.125", "EUR"), Some(13));
+    assert_eq!(parse_minor_units("1500", "JPY"), Some(1500));
+    assert_eq!(parse_minor_units("12,50", "EUR"), None);
+    assert_eq!(parse_minor_units("-1.00", "EUR"), None);
+    assert_eq!(format_minor_units(24990, "EUR"), "249.90");
+    assert_eq!(format_minor_units(5, "KWD"), "0.005");
+
+    let mut prices = Prices::default();
+    prices.set_default_currency("DKK");
+    prices.add(0, "DKK", PriceRange { min: 100, max: 250 });
+    prices.add(2, "EUR", PriceRange::single(15));
+
+    let mut blob = Vec::new();
+    prices.serialize(&mut |b| blob.push(b));
+    let (_, prices) = Prices::deserialize(&blob).unwrap();
+
+    assert_eq!(prices.get(0, None), Some(PriceRange { min: 100, max: 250 }));
+    assert_eq!(prices.get(2, Some("EUR")), Some(PriceRange::single(15)));
+    assert_eq!(prices.get(1, Some("EUR")), None);
+    assert_eq!(prices.get(0, Some("SEK")), None);
+}
+
+#[test]
+fn test_prices_in_currencies() {
+    use crate::{
+        config::IndexConfig,
+        js_interactable::JsProduct,
+        testing::{build_test_index, test_products},
+    };
+
+    let mut products = test_products();
+    let cheap = "gid://shopify/Product/6837964669113";
+    let expensive = "gid://shopify/Product/6837965029561";
+    // Two products are also sold in euro
+    for (id, amount) in [(cheap, "49.95"), (expensive, "120")] {
+        let product = products.iter_mut().find(|p| p.id == id).unwrap();
+        let mut euro = product.price.clone();
+        euro.min.amount = amount.to_string();
+        euro.min.currency_code = "EUR".to_string();
+        euro.max.amount = "150.00".to_string();
+        euro.max.currency_code = "EUR".to_string();
+        product.price_lists.push(euro);
+    }
+    let built = build_test_index(products, IndexConfig::default());
+    let (container, classic) = (built.container, built.loaded_classic);
+
+    let id = |id: &str| {
+        container
+            .products
+            .iter()
+            .find(|p| p.id == id)
+            .unwrap()
+            .serialization_id
+    };
+    let prices = &container.prices;
+    assert_eq!(prices.default_currency(), "DKK");
+    assert_eq!(
+        prices.get(id(cheap), None),
+        Some(PriceRange {
+            min: 37500,
+            max: 87500
+        })
+    );
+    assert_eq!(
+        prices.get(id(cheap), Some("EUR")),
+        Some(PriceRange {
+            min: 4995,
+            max: 15000
+        })
+    );
+
+    // The "price" feature prices used to be is still given, in the default currency
+    let product = JsProduct::new(container, id(cheap), Vec::new());
+    assert_eq!(product.numeric_feature("price"), Some(375.0));
+
+    // Products with a euro price come first, the cheapest before the other
+    let order = classic
+        .order
+        .get_orders_in("Price low to high", "EUR")
+        .unwrap();
+    assert_eq!((order[id(cheap)], order[id(expensive)]), (0, 1));
+    let order = classic
+        .order
+        .get_orders_in("Price high to low", "EUR")
+        .unwrap();
+    assert_eq!((order[id(expensive)], order[id(cheap)]), (0, 1));
+}
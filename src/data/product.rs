@@ -1,19 +1,50 @@
-use std::sync::Arc;
+use std::{ops::Range, sync::Arc};
 
 use crate::{
-    ngram::HashExtractable,
+    config::{IndexConfig, ProductField},
+    language::Language,
+    ngram::{HashExtractable, IndexedData},
     serialize::{sequential_array, Deserializable, Serializable},
 };
 
-use super::vendor::{Vendor, VendorManager};
+use super::{
+    vendor::{Vendor, VendorManager},
+    ProductContainer,
+};
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
 pub struct Product<'a> {
     pub description: String,
     pub title: String,
     pub vendor: &'a Vendor,
     pub id: String,
     pub serialization_id: usize,
+    pub truncated: TruncatedFields,
+    // The language the product is written in, if it differs from the index's default
+    pub language: Option<Language>,
+    // The serialization ids of the product's variants, which are stored in the order of their products
+    pub variants: Range<usize>,
+}
+
+/// The fields that were shortened by their storage policy when the product was serialized
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TruncatedFields(u8);
+
+impl TruncatedFields {
+    fn bit(field: ProductField) -> u8 {
+        match field {
+            ProductField::Title => 1,
+            ProductField::Description => 1 << 1,
+        }
+    }
+
+    pub fn contains(self, field: ProductField) -> bool {
+        self.0 & Self::bit(field) != 0
+    }
+
+    pub fn insert(&mut self, field: ProductField) {
+        self.0 |= Self::bit(field);
+    }
 }
 
 impl Product<'_> {
@@ -32,37 +63,6 @@ impl Product<'_> {
     }
 }
 
-impl Eq for Product<'_> {}
-
-impl PartialEq for Product<'_> {
-    fn eq(&self, other: &Self) -> bool {
-        // Descriptions might be of different length after serialization, so we just need the beginning to match
-        match (self.description.len(), other.description.len()) {
-            (0, 1..) | (1.., 0) => return false,
-            (my_len, other_len) if my_len > other_len => {
-                if self.description.starts_with(&other.description) == false {
-                    return false;
-                }
-            }
-            (my_len, other_len) if my_len < other_len => {
-                if other.description.starts_with(&self.description) == false {
-                    return false;
-                }
-            }
-            _ => {
-                if self.description != other.description {
-                    return false;
-                }
-            }
-        }
-
-        self.title == other.title
-            && self.vendor == other.vendor
-            && self.id == other.id
-            && self.serialization_id == other.serialization_id
-    }
-}
-
 impl<'a> PartialOrd for Product<'a> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
@@ -84,38 +84,70 @@ impl<'a> HashExtractable for &'a Product<'a> {
     }
 }
 
-impl Serializable for Product<'_> {
-    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+impl<'a> IndexedData for Product<'a> {
+    type Container = ProductContainer<'a>;
+
+    fn count(container: &ProductContainer<'a>) -> usize {
+        container.products.len()
+    }
+
+    fn serialization_id(&self) -> usize {
+        self.serialization_id
+    }
+}
+
+impl<'a> Product<'a> {
+    // Fields are stored as the config's storage policies say
+    pub fn serialize<Out: FnMut(u8)>(&self, config: &IndexConfig, output: &mut Out) {
         let Product {
             description,
             title,
             vendor,
             id,
+            truncated,
+            language,
+            variants,
             ..
         } = self;
 
-        crate::serialize::serialize_string_with_limit(description, 100, output);
-        for field in [title, id] {
-            field.serialize(output);
+        let mut truncated = *truncated;
+        let (stored_title, title_truncated) = config.title.store(title);
+        let (stored_description, description_truncated) = config.description.store(description);
+        for (field, is_truncated) in [
+            (ProductField::Title, title_truncated),
+            (ProductField::Description, description_truncated),
+        ] {
+            if is_truncated {
+                truncated.insert(field);
+            }
         }
 
+        truncated.0.serialize(output);
+        stored_description.as_ref().serialize(output);
+        stored_title.as_ref().serialize(output);
+        id.serialize(output);
+
         // Vendor and tags are just saved as their id's
         vendor.id.serialize(output);
+        language.serialize(output);
+        variants.len().serialize(output);
     }
-}
 
-impl<'a> Product<'a> {
     pub fn deserialize<'i>(
         input: &'i [u8],
         serialization_id: usize,
+        first_variant: usize,
         vendors: &Arc<VendorManager<'a>>,
     ) -> Option<(&'i [u8], Self)> {
+        let (input, truncated) = u8::deserialize(input)?;
         let (input, description) = String::deserialize(input)?;
         let (input, title) = String::deserialize(input)?;
         let (input, id) = String::deserialize(input)?;
 
         let (input, vendor_id) = usize::deserialize(input)?;
         let vendor = *vendors.by_id.get(vendor_id)?;
+        let (input, language) = Deserializable::deserialize(input)?;
+        let (input, variant_count) = usize::deserialize(input)?;
 
         Some((
             input,
@@ -125,6 +157,9 @@ impl<'a> Product<'a> {
                 vendor,
                 id,
                 serialization_id,
+                truncated: TruncatedFields(truncated),
+                language,
+                variants: first_variant..first_variant + variant_count,
             },
         ))
     }
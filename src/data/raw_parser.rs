@@ -1,15 +1,23 @@
 use serde::Deserialize;
-use std::sync::Arc;
+use std::{borrow::Cow, sync::Arc};
 
 use ahash::AHashMap;
 
 use crate::{
-    classic_indexes::{CategoryIndex, ClassicIndexes, OrderIndex, TagIndex},
+    classic_indexes::{
+        CategoryIndex, ClassicIndexes, CollectionIndex, LookupIndex, OrderIndex, TagIndex,
+        Vocabulary,
+    },
+    config::IndexConfig,
     data::vendor::VendorManager,
+    language::Language,
     Product,
 };
 
-use super::{FeatureSet, FeatureValue, ProductContainer, SuperAlloc};
+use super::{
+    parse_minor_units, FeatureConflict, FeatureSet, IndexReport, Outcome, PriceRange, Prices,
+    ProductContainer, ProductValidator, SuperAlloc, TruncatedFields, ValidationPolicy, Variant,
+};
 
 #[derive(Deserialize, PartialEq, Eq, Debug, Clone)]
 pub struct CurrencyAmount {
@@ -18,99 +26,473 @@ pub struct CurrencyAmount {
     pub currency_code: String,
 }
 
+impl CurrencyAmount {
+    pub fn minor_units(&self) -> Option<u64> {
+        parse_minor_units(&self.amount, &self.currency_code)
+    }
+}
+
 #[derive(Deserialize, PartialEq, Eq, Debug, Clone)]
 pub struct ProductPrice {
     pub min: CurrencyAmount,
     pub max: CurrencyAmount,
 }
 
+impl ProductPrice {
+    // The currency and range, if both ends are in the same currency and can be read
+    fn range(&self) -> Option<(&str, PriceRange)> {
+        if self.min.currency_code != self.max.currency_code {
+            return None;
+        }
+        let range = PriceRange::new(self.min.minor_units()?, self.max.minor_units()?);
+        Some((&self.min.currency_code, range))
+    }
+}
+
 #[derive(Deserialize, PartialEq, Eq, Debug, Clone)]
 pub struct MediaItem<'a> {
-    pub url: &'a str,
+    #[serde(borrow)]
+    pub url: Cow<'a, str>,
+}
+
+/// A collection the product is in, with the product's place in the collection's manual sort order
+#[derive(Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct RawCollection<'a> {
+    #[serde(borrow)]
+    pub id: Cow<'a, str>,
+    #[serde(default, borrow)]
+    pub title: Cow<'a, str>,
+    #[serde(default, borrow)]
+    pub handle: Cow<'a, str>,
+    // Products without a position come after the ones with one
+    #[serde(default)]
+    pub position: Option<usize>,
+}
+
+impl RawCollection<'_> {
+    pub fn into_owned(self) -> RawCollection<'static> {
+        RawCollection {
+            id: owned(self.id),
+            title: owned(self.title),
+            handle: owned(self.handle),
+            position: self.position,
+        }
+    }
 }
 
+// Strings are borrowed from the input when they can be, and owned when they have escapes
+// or are read from a stream, see `read_products`
 #[derive(Deserialize, PartialEq, Eq, Debug, Clone)]
 pub struct RawProduct<'a> {
     pub description: String,
-    pub tags: Vec<&'a str>,
-    pub title: &'a str,
-    pub vendor: &'a str,
-    pub id: &'a str,
+    pub tags: Vec<Cow<'a, str>>,
+    #[serde(borrow)]
+    pub title: Cow<'a, str>,
+    #[serde(borrow)]
+    pub vendor: Cow<'a, str>,
+    #[serde(borrow)]
+    pub id: Cow<'a, str>,
+    #[serde(borrow)]
     pub options: Vec<RawProductOption<'a>>,
     pub price: ProductPrice,
+    // The prices in the other currencies the product is sold in
+    #[serde(rename = "priceLists", default)]
+    pub price_lists: Vec<ProductPrice>,
+    // Left out of the product in bulk exports, where media are on their own lines
+    #[serde(default, borrow)]
     pub media: Vec<MediaItem<'a>>,
+    // A language code like "da", for shops selling in more than one language
+    #[serde(default, borrow)]
+    pub language: Option<Cow<'a, str>>,
+    #[serde(default, borrow)]
+    pub variants: Vec<RawVariant<'a>>,
+    #[serde(default, borrow)]
+    pub collections: Vec<RawCollection<'a>>,
+}
+
+impl RawProduct<'_> {
+    /// Copies the borrowed strings, so the input can be dropped
+    pub fn into_owned(self) -> RawProduct<'static> {
+        RawProduct {
+            description: self.description,
+            tags: self.tags.into_iter().map(owned).collect(),
+            title: owned(self.title),
+            vendor: owned(self.vendor),
+            id: owned(self.id),
+            options: self
+                .options
+                .into_iter()
+                .map(RawProductOption::into_owned)
+                .collect(),
+            price: self.price,
+            price_lists: self.price_lists,
+            media: self.media.into_iter().map(MediaItem::into_owned).collect(),
+            language: self.language.map(owned),
+            variants: self
+                .variants
+                .into_iter()
+                .map(RawVariant::into_owned)
+                .collect(),
+            collections: self
+                .collections
+                .into_iter()
+                .map(RawCollection::into_owned)
+                .collect(),
+        }
+    }
+}
+
+fn owned(string: Cow<'_, str>) -> Cow<'static, str> {
+    Cow::Owned(string.into_owned())
+}
+
+impl MediaItem<'_> {
+    pub fn into_owned(self) -> MediaItem<'static> {
+        MediaItem {
+            url: owned(self.url),
+        }
+    }
+}
+
+#[derive(Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct RawVariant<'a> {
+    #[serde(borrow)]
+    pub id: Cow<'a, str>,
+    #[serde(default, borrow)]
+    pub title: Cow<'a, str>,
+    #[serde(default, borrow)]
+    pub sku: Option<Cow<'a, str>>,
+    #[serde(default, borrow)]
+    pub barcode: Option<Cow<'a, str>>,
+    #[serde(rename = "availableForSale", default = "available_by_default")]
+    pub available_for_sale: bool,
+    // Variants without their own price cost the product's minimum price
+    #[serde(default)]
+    pub price: Option<CurrencyAmount>,
+    #[serde(rename = "priceLists", default)]
+    pub price_lists: Vec<CurrencyAmount>,
+    #[serde(default, borrow)]
+    pub media: Vec<MediaItem<'a>>,
+    #[serde(rename = "selectedOptions", default, borrow)]
+    pub selected_options: Vec<RawSelectedOption<'a>>,
+}
+
+fn available_by_default() -> bool {
+    true
+}
+
+/// The value a variant has for one of its product's options
+#[derive(Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct RawSelectedOption<'a> {
+    #[serde(borrow)]
+    pub name: Cow<'a, str>,
+    #[serde(borrow)]
+    pub value: Cow<'a, str>,
+}
+
+impl<'a> RawVariant<'a> {
+    // The SKU and barcode, when they're filled in
+    fn codes(&self) -> impl Iterator<Item = Cow<'a, str>> + '_ {
+        [&self.sku, &self.barcode]
+            .into_iter()
+            .flatten()
+            .filter(|code| code.trim().is_empty() == false)
+            .cloned()
+    }
+
+    pub fn into_owned(self) -> RawVariant<'static> {
+        RawVariant {
+            id: owned(self.id),
+            title: owned(self.title),
+            sku: self.sku.map(owned),
+            barcode: self.barcode.map(owned),
+            available_for_sale: self.available_for_sale,
+            price: self.price,
+            price_lists: self.price_lists,
+            media: self.media.into_iter().map(MediaItem::into_owned).collect(),
+            selected_options: self
+                .selected_options
+                .into_iter()
+                .map(|option| RawSelectedOption {
+                    name: owned(option.name),
+                    value: owned(option.value),
+                })
+                .collect(),
+        }
+    }
 }
 
 #[derive(Deserialize, PartialEq, Eq, Debug, Clone)]
 pub struct RawProductOption<'a> {
-    pub name: &'a str,
-    pub values: Vec<&'a str>,
+    #[serde(borrow)]
+    pub name: Cow<'a, str>,
+    pub values: Vec<Cow<'a, str>>,
+}
+
+impl RawProductOption<'_> {
+    pub fn into_owned(self) -> RawProductOption<'static> {
+        RawProductOption {
+            name: owned(self.name),
+            values: self.values.into_iter().map(owned).collect(),
+        }
+    }
 }
 
 pub struct IntermediateRawProduct<'a> {
-    pub title: &'a str,
+    pub title: Cow<'a, str>,
     pub description: String,
-    pub tags: Vec<&'a str>,
-    pub vendor: &'a str,
-    pub id: &'a str,
+    pub tags: Vec<Cow<'a, str>>,
+    pub vendor: Cow<'a, str>,
+    pub id: Cow<'a, str>,
     pub options: Vec<RawProductOption<'a>>,
-    pub other_string: AHashMap<&'a str, &'a str>,
-    pub other_numeric: AHashMap<&'a str, f32>,
+    pub other_string: AHashMap<&'static str, Cow<'a, str>>,
+    // The currency of the main price, even if it can't be read
+    pub currency: String,
+    // The main price comes first
+    pub prices: Vec<(String, PriceRange)>,
+    pub language: Option<Language>,
+    pub variants: Vec<RawVariant<'a>>,
+    pub collections: Vec<RawCollection<'a>>,
 }
 
-fn to_intermediate(input: Vec<RawProduct<'_>>) -> impl Iterator<Item = IntermediateRawProduct<'_>> {
-    input.into_iter().map(|raw| {
-        let RawProduct {
-            description,
-            tags,
-            title,
-            vendor,
-            id,
-            options,
-            mut media,
-            price,
-        } = raw;
+// Keeps what the index needs of a product, so the rest of it can be dropped as soon as it's read
+fn to_intermediate(raw: RawProduct<'_>) -> IntermediateRawProduct<'_> {
+    let RawProduct {
+        description,
+        tags,
+        title,
+        vendor,
+        id,
+        options,
+        mut media,
+        price,
+        price_lists,
+        language,
+        variants,
+        collections,
+    } = raw;
+
+    let mut other_string = AHashMap::with_capacity(1);
+
+    if !media.is_empty() {
+        other_string.insert("image_url", media.swap_remove(0).url);
+    }
 
-        let mut other_string = AHashMap::with_capacity(1);
+    // Prices that can't be read are left out, rather than guessed
+    let prices = std::iter::once(&price)
+        .chain(&price_lists)
+        .filter_map(ProductPrice::range)
+        .map(|(currency, range)| (currency.to_string(), range))
+        .collect();
+
+    IntermediateRawProduct {
+        title,
+        description,
+        tags,
+        vendor,
+        id,
+        options,
+        other_string,
+        currency: price.min.currency_code,
+        prices,
+        language: language.as_deref().and_then(Language::from_code),
+        variants,
+        collections,
+    }
+}
+
+pub fn optimize<'a>(
+    input: impl IntoIterator<Item = RawProduct<'a>>,
+    super_alloc: &'static SuperAlloc,
+) -> (&'static ProductContainer<'static>, ClassicIndexes<'static>) {
+    optimize_with_config(input, IndexConfig::default(), super_alloc)
+}
 
-        if !media.is_empty() {
-            other_string.insert("image_url", media.swap_remove(0).url);
+// Adds the variants of a product, with their SKU and image as features.
+// Returns the features that couldn't be added
+fn add_variants<'a>(
+    raw_variants: Vec<RawVariant<'a>>,
+    product: usize,
+    features: &mut FeatureSet,
+    prices: &mut Prices,
+    variants: &mut Vec<Variant>,
+    variant_options: &mut Vec<Vec<RawSelectedOption<'a>>>,
+) -> Vec<FeatureConflict> {
+    let mut conflicts = Vec::new();
+    for raw in raw_variants {
+        let id = variants.len();
+        for price in raw.price.iter().chain(&raw.price_lists) {
+            if let Some(amount) = price.minor_units() {
+                let range = PriceRange::single(amount);
+                prices.add(id, &price.currency_code, range);
+            }
         }
+        let image_url = raw.media.first().map_or("", |media| &media.url);
+        let added = [
+            features.add_string("sku", raw.sku.unwrap_or_default().to_string()),
+            features.add_string("image_url", image_url.to_string()),
+        ];
+        conflicts.extend(added.into_iter().filter_map(Result::err));
 
-        let mut other_numeric = AHashMap::with_capacity(1);
+        variants.push(Variant {
+            id: raw.id.into_owned(),
+            title: raw.title.into_owned(),
+            available: raw.available_for_sale,
+            product,
+            serialization_id: id,
+        });
+        variant_options.push(raw.selected_options);
+    }
+    conflicts
+}
 
-        other_numeric.insert("price", price.min.amount.parse().unwrap());
+fn borrowed<'l>(lists: &'l [Vec<Cow<'_, str>>]) -> Vec<Vec<&'l str>> {
+    let list = |list: &'l Vec<Cow<'_, str>>| list.iter().map(AsRef::as_ref).collect();
+    lists.iter().map(list).collect()
+}
 
-        IntermediateRawProduct {
-            title,
-            description,
-            tags,
-            vendor,
-            id,
-            options,
-            other_string,
-            other_numeric,
+// The words of the products' texts and tags, for spelling suggestions
+fn product_vocabulary(
+    out: &ProductContainer<'_>,
+    tags_for_product: &[Vec<Cow<'_, str>>],
+) -> Vocabulary {
+    // The fields are cleaned up the way they are for the gram index, so no HTML ends up in the words
+    let preprocess = &out.config.preprocess;
+    let texts = out.products.iter().zip(tags_for_product).map(|(p, tags)| {
+        let fields = [
+            preprocess.title.apply(&p.title),
+            preprocess.description.apply(&p.description),
+            preprocess.vendor.apply(&p.vendor.name),
+        ];
+        fields
+            .into_iter()
+            .chain(tags.iter().map(|tag| Cow::Borrowed(tag.as_ref())))
+            .collect::<Vec<Cow<'_, str>>>()
+    });
+    Vocabulary::index(texts, &out.config.normalization)
+}
+
+// Orders by price in every currency, products without a price in the currency go last
+fn add_price_orders(order: &mut OrderIndex, out: &ProductContainer<'_>) {
+    for currency in out.prices.currencies() {
+        let price =
+            |product: &Product<'_>| out.prices.get(product.serialization_id, Some(currency));
+        let low_to_high = order.order_of(
+            out,
+            &|product, _| price(product).map_or(u64::MAX, |price| price.min),
+            &Ord::cmp,
+        );
+        let high_to_low = order.order_of(
+            out,
+            &|product, _| price(product).map_or(0, |price| price.min),
+            &|a, b| b.cmp(a),
+        );
+        order.add_in_currency(currency, "Price low to high", low_to_high);
+        order.add_in_currency(currency, "Price high to low", high_to_low);
+    }
+}
+
+pub fn optimize_with_config<'a>(
+    input: impl IntoIterator<Item = RawProduct<'a>>,
+    config: IndexConfig,
+    super_alloc: &'static SuperAlloc,
+) -> (&'static ProductContainer<'static>, ClassicIndexes<'static>) {
+    optimize_with_report(input, config, super_alloc, &mut IndexReport::default())
+}
+
+/// Like `optimize_with_config`, adding the features that couldn't be added to the report
+pub fn optimize_with_report<'a>(
+    input: impl IntoIterator<Item = RawProduct<'a>>,
+    config: IndexConfig,
+    super_alloc: &'static SuperAlloc,
+    report: &mut IndexReport,
+) -> (&'static ProductContainer<'static>, ClassicIndexes<'static>) {
+    let mut collector = ProductCollector::new();
+    for product in input {
+        collector.add(product, report);
+    }
+    collector.finish(config, super_alloc, report)
+}
+
+/// Takes the products one at a time as they're read, and keeps only what the index needs of each,
+/// so a catalogue is never held in memory as raw products
+#[derive(Default)]
+pub struct ProductCollector<'a> {
+    products: Vec<IntermediateRawProduct<'a>>,
+    // Products are only kept if they pass, when given
+    validator: Option<ProductValidator>,
+}
+
+impl<'a> ProductCollector<'a> {
+    pub fn new() -> ProductCollector<'a> {
+        ProductCollector::default()
+    }
+
+    /// Checks every product by the policy before keeping it, see `ProductValidator`
+    pub fn validated(policy: ValidationPolicy) -> ProductCollector<'a> {
+        ProductCollector {
+            products: Vec::new(),
+            validator: Some(ProductValidator::new(policy)),
         }
-    })
+    }
+
+    pub fn add(&mut self, product: RawProduct<'a>, report: &mut IndexReport) {
+        let product = match &mut self.validator {
+            Some(validator) => validator.check(product, report),
+            None => Some(product),
+        };
+        self.products.extend(product.map(to_intermediate));
+    }
+
+    pub fn len(&self) -> usize {
+        self.products.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.products.is_empty()
+    }
+
+    /// Gives the products their serialization ids in the order they were added, and builds the container and the classic indexes
+    pub fn finish(
+        self,
+        config: IndexConfig,
+        super_alloc: &'static SuperAlloc,
+        report: &mut IndexReport,
+    ) -> (&'static ProductContainer<'static>, ClassicIndexes<'static>) {
+        // Serialization ids follow the input order, so the same catalogue read the same way
+        // gives the same ids
+        build_container(self.products, config, super_alloc, report)
+    }
 }
 
-pub fn optimize(
-    input: Vec<RawProduct<'_>>,
+fn build_container(
+    input: Vec<IntermediateRawProduct<'_>>,
+    config: IndexConfig,
     super_alloc: &'static SuperAlloc,
+    report: &mut IndexReport,
 ) -> (&'static ProductContainer<'static>, ClassicIndexes<'static>) {
     let mut vendors = VendorManager::new(super_alloc);
 
     // We insert all the tags/vendors
     for product in &input {
-        vendors.insert(product.vendor);
+        vendors.insert(&product.vendor);
     }
 
     let vendors: Arc<VendorManager<'static>> = Arc::new(vendors);
     let mut products: Vec<Product<'static>> = Vec::with_capacity(input.len());
-    let out = ProductContainer::new(Vec::new(), vendors, FeatureSet::new_empty());
+    let out = ProductContainer::new(Vec::new(), vendors, FeatureSet::new_empty(), config);
     let out = super_alloc.alloc_mut(out);
+    // Searches without a currency use the one of the first product's main price
+    if let Some(first) = input.first() {
+        out.prices.set_default_currency(&first.currency);
+        out.variant_prices.set_default_currency(&first.currency);
+    }
     let mut options_list = Vec::with_capacity(input.len());
     let mut tags_for_product = Vec::new();
+    let mut collections_for_product = Vec::with_capacity(input.len());
+    let mut codes_for_product = Vec::with_capacity(input.len());
+    let mut variants = Vec::new();
+    let mut variant_options = Vec::new();
 
     for (
         i,
@@ -122,70 +504,143 @@ pub fn optimize(
             id,
             options,
             other_string,
-            other_numeric,
+            currency: _,
+            prices,
+            language,
+            variants: raw_variants,
+            collections,
         },
-    ) in to_intermediate(input).enumerate()
+    ) in input.into_iter().enumerate()
     {
-        let vendor = super_alloc.alloc(vendor.to_string());
-        let my_vendor = out.vendors.get(vendor).unwrap();
+        let my_vendor = out.vendors.get(&vendor).unwrap();
         tags_for_product.push(tags);
+        collections_for_product.push(collections);
+        codes_for_product.push(raw_variants.iter().flat_map(RawVariant::codes).collect());
 
         options_list.push(options);
 
         let p = Product {
             description,
             vendor: my_vendor,
-            title: title.to_string(),
+            title: title.into_owned(),
             id: id.to_string(),
             serialization_id: i,
+            truncated: TruncatedFields::default(),
+            language,
+            variants: variants.len()..variants.len() + raw_variants.len(),
         };
+        for (currency, range) in &prices {
+            out.prices.add(i, currency, *range);
+        }
 
-        // We add the data from the "other"
+        // We add the data from the "other", values of the wrong type are left out
+        let mut conflicts = Vec::new();
         for (key, value) in other_string {
             let key = super_alloc.alloc(key.to_string());
-            out.extra_features.add_string(key, value.to_string());
-        }
-        for (key, value) in other_numeric {
-            let key = super_alloc.alloc(key.to_string());
-            out.extra_features.add_float(key, value);
+            conflicts.extend(out.extra_features.add_string(key, value.into_owned()).err());
         }
 
         products.push(p);
+
+        conflicts.extend(add_variants(
+            raw_variants,
+            i,
+            &mut out.variant_features,
+            &mut out.variant_prices,
+            &mut variants,
+            &mut variant_options,
+        ));
+        for conflict in conflicts {
+            report.add(&id, conflict.into(), Outcome::Repaired);
+        }
     }
 
+    report.indexed = products.len();
     out.products = products;
+    out.variants = variants;
     // We make the products read only
     let out = &*out;
 
-    let tag_index = TagIndex::index(tags_for_product, out);
+    let tag_index = TagIndex::index(borrowed(&tags_for_product), out);
 
     let mut order = OrderIndex::new();
     // Alphabetical
     order.add(out, &|product, _| &product.title, "Alphabetical".to_owned());
-    // Price
-    order.add(
-        out,
-        &|product, extra| match extra.get(product, "price") {
-            Some(FeatureValue::Float(v)) => v,
-            _ => panic!("Only expected price as float"),
-        },
-        "Price low to high".to_owned(),
-    );
-    order.add_custom_cmp(
+    add_price_orders(&mut order, out);
+
+    let categories = CategoryIndex::index(options_list, variant_options, out);
+    let lookup = LookupIndex::index(borrowed(&codes_for_product), out);
+    let collections = CollectionIndex::index(collections_for_product, out);
+    let spelling = product_vocabulary(out, &tags_for_product);
+
+    (
         out,
-        &|product, extra| match extra.get(product, "price") {
-            Some(FeatureValue::Float(v)) => v,
-            _ => panic!("Only expected price as float"),
-        },
-        &|a, b| {
-            a.partial_cmp(b)
-                .unwrap_or(std::cmp::Ordering::Equal)
-                .reverse()
-        },
-        "Price high to low".to_owned(),
-    );
+        ClassicIndexes::new(categories, tag_index, order, lookup, collections, spelling),
+    )
+}
+
+#[test]
+fn test_vocabulary_of_preprocessed_text() {
+    use crate::testing::{test_products, TEST_ARENA};
+
+    let mut products = test_products();
+    products[0].description = r#"<p class="lead">Abstrakt <b>kunst</b></p>"#.to_string();
+    let (_, classic) = optimize(products, &TEST_ARENA);
+    // Descriptions are HTML, whose markup isn't words of the catalogue
+    assert!(classic.spelling.frequency("abstrakt") > 0);
+    assert_eq!(classic.spelling.frequency("class"), 0);
+    assert_eq!(classic.spelling.frequency("lead"), 0);
+}
+
+#[test]
+fn test_collected_products() {
+    use crate::{
+        data::{read_products, InputFormat, ProductIssue},
+        index_and_serialize_collected, index_and_serialize_with_n,
+        serialize::IndexSections,
+        testing::TEST_ARENA,
+    };
+
+    let file = std::fs::read_to_string("./test.json").unwrap();
+    let config = IndexConfig::default();
+
+    // Streamed from the input one product at a time, with the first one again at the end
+    let mut report = IndexReport::default();
+    let mut problems = IndexReport::default();
+    let mut collector = ProductCollector::validated(config.validation);
+    let mut ids = Vec::new();
+    let mut first = None;
+    read_products(file.as_bytes(), InputFormat::Json, &mut report, |product| {
+        ids.push(product.id.to_string());
+        first.get_or_insert_with(|| product.clone());
+        collector.add(product, &mut problems);
+    })
+    .unwrap();
+    collector.add(first.unwrap(), &mut problems);
+    report.merge(problems);
+    let (streamed, report) =
+        index_and_serialize_collected::<5>(collector, config.clone(), &TEST_ARENA, report).unwrap();
+    assert_eq!(report.diagnostics.len(), 1);
+    assert_eq!(report.diagnostics[0].product, ids[0]);
+    assert_eq!(report.diagnostics[0].issue, ProductIssue::DuplicateId);
 
-    let categories = CategoryIndex::index(options_list, out);
+    // Serialization ids follow the input order
+    let sections = IndexSections::parse(&streamed).unwrap();
+    let container = sections.products(&TEST_ARENA).unwrap();
+    let serialized: Vec<&str> = container.products.iter().map(|p| p.id.as_str()).collect();
+    assert_eq!(serialized, ids);
 
-    (out, ClassicIndexes::new(categories, tag_index, order))
+    // The same products collected at once make the same index
+    let mut products = Vec::new();
+    read_products(
+        file.as_bytes(),
+        InputFormat::Json,
+        &mut IndexReport::default(),
+        |product| products.push(product),
+    )
+    .unwrap();
+    let (collected, _) =
+        index_and_serialize_with_n::<5>(products, config, &TEST_ARENA, IndexReport::default())
+            .unwrap();
+    assert!(streamed == collected);
 }
@@ -0,0 +1,204 @@
+use std::{fmt, io::BufRead};
+
+use serde::{
+    de::{MapAccess, SeqAccess, Visitor},
+    Deserializer,
+};
+use serde_json::value::RawValue;
+
+use super::{IndexReport, Outcome, ProductIssue, RawProduct};
+
+// The fields of a product, telling a product per line apart from an object of products
+const PRODUCT_FIELDS: [&[u8]; 12] = [
+    b"description",
+    b"tags",
+    b"title",
+    b"vendor",
+    b"id",
+    b"options",
+    b"price",
+    b"priceLists",
+    b"media",
+    b"language",
+    b"variants",
+    b"collections",
+];
+
+/// How the products are laid out in the input
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InputFormat {
+    // Decided from the start of the input
+    #[default]
+    Auto,
+    // An object with the products as values, or an array of products
+    Json,
+    // One product per line, as newline delimited JSON
+    Lines,
+}
+
+impl InputFormat {
+    fn detect(reader: &mut impl BufRead) -> std::io::Result<InputFormat> {
+        loop {
+            let buffer = reader.fill_buf()?;
+            if buffer.is_empty() {
+                return Ok(InputFormat::Json);
+            }
+            if let Some(start) = buffer.iter().position(|b| b.is_ascii_whitespace() == false) {
+                reader.consume(start);
+                break;
+            }
+            let end = buffer.len();
+            reader.consume(end);
+        }
+
+        // An object whose first key is a product field is the first of many lines
+        let buffer = reader.fill_buf()?;
+        let first_key = buffer
+            .strip_prefix(b"{")
+            .and_then(|rest| rest.trim_ascii_start().strip_prefix(b"\""))
+            .and_then(|rest| rest.split(|b| *b == b'"').next());
+        if first_key.is_some_and(|key| PRODUCT_FIELDS.contains(&key)) {
+            return Ok(InputFormat::Lines);
+        }
+
+        // Products of other shapes are on lines if the first line is a whole object followed by another
+        let mut lines = buffer.splitn(2, |b| *b == b'\n');
+        let first_line = lines.next().unwrap_or_default();
+        let followed = lines
+            .next()
+            .is_some_and(|rest| rest.trim_ascii_start().starts_with(b"{"));
+        Ok(
+            if followed && serde_json::from_slice::<&RawValue>(first_line).is_ok() {
+                InputFormat::Lines
+            } else {
+                InputFormat::Json
+            },
+        )
+    }
+}
+
+// Visits an object or array of products, holding only one of them in memory at a time
+struct Products<F>(F);
+
+impl<'de, F: FnMut(&str, &str)> Visitor<'de> for Products<F> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an object or array of products")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(mut self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            let value: Box<RawValue> = map.next_value()?;
+            (self.0)(&key, value.get());
+        }
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> Result<(), A::Error> {
+        let mut position = 0;
+        while let Some(value) = seq.next_element::<Box<RawValue>>()? {
+            (self.0)(&position.to_string(), value.get());
+            position += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the products one at a time in the order they're given, without keeping the input.
+/// Products that can't be read are skipped like in `parse_products`, and are reported by
+/// their key, their position in an array, or their line
+pub fn read_products<R: BufRead>(
+    reader: R,
+    format: InputFormat,
+    report: &mut IndexReport,
+    mut add: impl FnMut(RawProduct<'static>),
+) -> Result<(), serde_json::Error> {
+    read_values(reader, format, |key, value| {
+        match serde_json::from_str::<RawProduct>(value) {
+            Ok(product) => add(product.into_owned()),
+            Err(e) => report.add(key, ProductIssue::unreadable(&e), Outcome::Skipped),
+        }
+    })
+}
+
+// Gives the JSON of every value with its key, position or line, for the adapters to read
+pub(super) fn read_values<R: BufRead>(
+    mut reader: R,
+    format: InputFormat,
+    mut read: impl FnMut(&str, &str),
+) -> Result<(), serde_json::Error> {
+    let format = match format {
+        InputFormat::Auto => InputFormat::detect(&mut reader).map_err(serde_json::Error::io)?,
+        format => format,
+    };
+
+    if format == InputFormat::Lines {
+        for (number, line) in reader.lines().enumerate() {
+            let line = line.map_err(serde_json::Error::io)?;
+            if line.trim().is_empty() == false {
+                read(&(number + 1).to_string(), &line);
+            }
+        }
+        return Ok(());
+    }
+
+    let mut deserializer = serde_json::Deserializer::from_reader(reader);
+    deserializer.deserialize_any(Products(&mut read))?;
+    deserializer.end()
+}
+
+#[test]
+fn test_read_products() {
+    let product = |id: &str| {
+        format!(
+            r#"{{"description": "", "tags": ["Kunst"], "title": "Poster \"{id}\"", "vendor": "Art",
+            "id": "{id}", "options": [], "media": [], "price": {{
+                "min": {{"amount": "75.0", "currencyCode": "DKK"}},
+                "max": {{"amount": "100.0", "currencyCode": "DKK"}}}}}}"#
+        )
+        .replace('\n', " ")
+    };
+    let inputs = [
+        format!(
+            r#"{{"c": {}, "a": {}, "bad": {{"id": "3"}}, "b": {}}}"#,
+            product("3"),
+            product("1"),
+            product("2")
+        ),
+        format!(
+            "[{}, {}, {{\"id\": \"3\"}}, {}]",
+            product("3"),
+            product("1"),
+            product("2")
+        ),
+        format!(
+            "{}\n{}\n{{\"id\": \"3\"}}\n\n{}\n",
+            product("3"),
+            product("1"),
+            product("2")
+        ),
+    ];
+
+    for input in inputs {
+        let mut report = IndexReport::default();
+        let mut products = Vec::new();
+        read_products(
+            input.as_bytes(),
+            InputFormat::Auto,
+            &mut report,
+            |product| products.push(product),
+        )
+        .unwrap();
+
+        // The input order is kept, and escaped strings can be read
+        let ids: Vec<&str> = products.iter().map(|p| p.id.as_ref()).collect();
+        assert_eq!(ids, ["3", "1", "2"]);
+        assert_eq!(products[0].title, "Poster \"3\"");
+        assert_eq!(report.skipped, 1);
+    }
+
+    let mut report = IndexReport::default();
+    let truncated = read_products(&b"[{\"id\": "[..], InputFormat::Json, &mut report, |_| {});
+    assert!(truncated.is_err());
+}
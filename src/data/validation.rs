@@ -0,0 +1,335 @@
+use ahash::AHashSet;
+use serde_json::value::RawValue;
+
+use super::{
+    raw_parser::{CurrencyAmount, ProductPrice},
+    FeatureConflict, RawProduct,
+};
+
+/// What happens to a product with a problem
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationPolicy {
+    // Leave out what can't be read, like a bad price, and skip products that can't be indexed at all
+    #[default]
+    Repair,
+    Skip,
+    // Check every product like `Skip`, and fail the build with all the problems if there are any
+    Fail,
+}
+
+/// A problem found with one raw product
+#[derive(serde::Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ProductIssue {
+    Malformed {
+        message: String,
+    },
+    MissingField {
+        field: String,
+    },
+    BadPrice {
+        amount: String,
+        currency: String,
+    },
+    FeatureConflict {
+        key: String,
+        expected: String,
+        found: String,
+    },
+    DuplicateId,
+}
+
+impl ProductIssue {
+    // Products with these can't be indexed whatever the policy
+    fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            ProductIssue::Malformed { .. }
+                | ProductIssue::MissingField { .. }
+                | ProductIssue::DuplicateId
+        )
+    }
+
+    // Why a product couldn't be read from the input
+    pub(super) fn unreadable(e: &serde_json::Error) -> ProductIssue {
+        let message = e.to_string();
+        // Serde says "missing field `title` at line 1 column 2"
+        match message.strip_prefix("missing field `") {
+            Some(rest) => ProductIssue::MissingField {
+                field: rest.split('`').next().unwrap_or_default().to_string(),
+            },
+            None => ProductIssue::Malformed { message },
+        }
+    }
+
+    fn bad_price(amount: &CurrencyAmount) -> ProductIssue {
+        ProductIssue::BadPrice {
+            amount: amount.amount.clone(),
+            currency: amount.currency_code.clone(),
+        }
+    }
+}
+
+impl From<FeatureConflict> for ProductIssue {
+    fn from(conflict: FeatureConflict) -> Self {
+        ProductIssue::FeatureConflict {
+            key: conflict.key,
+            expected: conflict.expected.to_string(),
+            found: conflict.found.to_string(),
+        }
+    }
+}
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Repaired,
+    Skipped,
+}
+
+#[derive(serde::Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    // The product's id, or its key in the input if it couldn't be read
+    pub product: String,
+    pub issue: ProductIssue,
+    pub outcome: Outcome,
+}
+
+/// What happened to the products of a build, returned next to the index
+#[derive(serde::Serialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct IndexReport {
+    pub indexed: usize,
+    pub skipped: usize,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl IndexReport {
+    pub fn add(&mut self, product: &str, issue: ProductIssue, outcome: Outcome) {
+        if outcome == Outcome::Skipped {
+            self.skipped += 1;
+        }
+        self.diagnostics.push(Diagnostic {
+            product: product.to_string(),
+            issue,
+            outcome,
+        });
+    }
+
+    /// Adds the problems of another part of the build, found after the ones already in this report
+    pub fn merge(&mut self, other: IndexReport) {
+        self.indexed += other.indexed;
+        self.skipped += other.skipped;
+        self.diagnostics.extend(other.diagnostics);
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+impl std::fmt::Display for IndexReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "indexed {} products, skipped {}, with {} problems",
+            self.indexed,
+            self.skipped,
+            self.diagnostics.len()
+        )
+    }
+}
+
+impl std::error::Error for IndexReport {}
+
+// The values of a JSON object with their keys, in the order they're given
+struct Entries<'a>(Vec<(&'a str, &'a RawValue)>);
+
+impl<'de> serde::Deserialize<'de> for Entries<'de> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct EntriesVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for EntriesVisitor {
+            type Value = Entries<'de>;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("an object of products")
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> Result<Entries<'de>, A::Error> {
+                let mut entries = Vec::with_capacity(map.size_hint().unwrap_or_default());
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(Entries(entries))
+            }
+        }
+
+        deserializer.deserialize_map(EntriesVisitor)
+    }
+}
+
+/// Reads the products of a JSON object one by one, so a malformed product only skips itself.
+/// They're kept in the order they're given, so the first of the products with the same id is the one indexed
+pub fn parse_products<'a>(
+    input: &'a str,
+    report: &mut IndexReport,
+) -> Result<Vec<RawProduct<'a>>, serde_json::Error> {
+    let Entries(raw) = serde_json::from_str(input)?;
+    let mut products = Vec::with_capacity(raw.len());
+    for (key, value) in raw {
+        match serde_json::from_str::<RawProduct>(value.get()) {
+            Ok(product) => products.push(product),
+            Err(e) => report.add(key, ProductIssue::unreadable(&e), Outcome::Skipped),
+        }
+    }
+    Ok(products)
+}
+
+// The problems with the prices of a product and its variants
+fn price_issues(product: &RawProduct<'_>) -> Vec<ProductIssue> {
+    let mut issues = Vec::new();
+    let prices = std::iter::once(&product.price).chain(&product.price_lists);
+    for ProductPrice { min, max } in prices {
+        for amount in [min, max] {
+            if amount.minor_units().is_none() {
+                issues.push(ProductIssue::bad_price(amount));
+            }
+        }
+        if min.currency_code != max.currency_code {
+            issues.push(ProductIssue::bad_price(max));
+        }
+    }
+    for variant in &product.variants {
+        for amount in variant.price.iter().chain(&variant.price_lists) {
+            if amount.minor_units().is_none() {
+                issues.push(ProductIssue::bad_price(amount));
+            }
+        }
+    }
+    issues
+}
+
+/// Checks products one at a time, as they're read, keeping the ones the policy allows.
+/// Unreadable prices are left out of the index when repairing, see `to_intermediate`
+#[derive(Debug, Default)]
+pub struct ProductValidator {
+    policy: ValidationPolicy,
+    seen_ids: AHashSet<String>,
+}
+
+impl ProductValidator {
+    pub fn new(policy: ValidationPolicy) -> ProductValidator {
+        ProductValidator {
+            policy,
+            seen_ids: AHashSet::new(),
+        }
+    }
+
+    /// The product if the policy allows it, with its problems added to the report
+    pub fn check<'a>(
+        &mut self,
+        product: RawProduct<'a>,
+        report: &mut IndexReport,
+    ) -> Option<RawProduct<'a>> {
+        let mut issues = Vec::new();
+        if product.id.trim().is_empty() {
+            issues.push(ProductIssue::MissingField {
+                field: "id".to_string(),
+            });
+        } else if self.seen_ids.insert(product.id.to_string()) == false {
+            issues.push(ProductIssue::DuplicateId);
+        }
+        issues.extend(price_issues(&product));
+        if issues.is_empty() {
+            return Some(product);
+        }
+
+        let skip =
+            self.policy != ValidationPolicy::Repair || issues.iter().any(ProductIssue::is_fatal);
+        let outcome = if skip {
+            report.skipped += 1;
+            Outcome::Skipped
+        } else {
+            Outcome::Repaired
+        };
+        for issue in issues {
+            report.diagnostics.push(Diagnostic {
+                product: product.id.to_string(),
+                issue,
+                outcome,
+            });
+        }
+        (skip == false).then_some(product)
+    }
+}
+
+/// Checks every product, keeping the ones the policy allows, see `ProductValidator`
+pub fn validate_products<'a>(
+    products: impl IntoIterator<Item = RawProduct<'a>>,
+    policy: ValidationPolicy,
+    report: &mut IndexReport,
+) -> Vec<RawProduct<'a>> {
+    let mut validator = ProductValidator::new(policy);
+    products
+        .into_iter()
+        .filter_map(|product| validator.check(product, report))
+        .collect()
+}
+
+#[test]
+fn test_validate_products() {
+    let product = |id: &str, price: &str| {
+        format!(
+            r#"{{"description": "", "tags": [], "title": "Poster", "vendor": "Art", "id": "{id}",
+            "options": [], "media": [], "price": {{
+                "min": {{"amount": "{price}", "currencyCode": "DKK"}},
+                "max": {{"amount": "100.0", "currencyCode": "DKK"}}}}}}"#
+        )
+    };
+    let input = format!(
+        r#"{{"a": {}, "b": {}, "c": {}, "d": {{"id": "4", "title": 5}}, "e": {{"id": "5"}}}}"#,
+        product("1", "75.0"),
+        product("2", "seventy"),
+        product("1", "80.0"),
+    );
+
+    let mut report = IndexReport::default();
+    let products = parse_products(&input, &mut report).unwrap();
+    let ids: Vec<&str> = products.iter().map(|p| p.id.as_ref()).collect();
+    assert_eq!(ids, ["1", "2", "1"]);
+    assert_eq!(report.skipped, 2);
+    let missing = report
+        .diagnostics
+        .iter()
+        .find(|d| d.product == "e")
+        .unwrap();
+    assert_eq!(
+        missing.issue,
+        ProductIssue::MissingField {
+            field: "description".to_string()
+        }
+    );
+
+    // The unreadable price is left out, and only one of the products with the same id is kept
+    let mut repaired = report.clone();
+    let valid = validate_products(products.clone(), ValidationPolicy::Repair, &mut repaired);
+    assert_eq!(valid.len(), 2);
+    assert_eq!(valid[0].price.min.amount, "75.0");
+    assert_eq!(repaired.skipped, 3);
+    assert!(repaired.diagnostics.contains(&Diagnostic {
+        product: "2".to_string(),
+        issue: ProductIssue::BadPrice {
+            amount: "seventy".to_string(),
+            currency: "DKK".to_string()
+        },
+        outcome: Outcome::Repaired,
+    }));
+
+    let valid = validate_products(products, ValidationPolicy::Skip, &mut report);
+    assert_eq!(valid.len(), 1);
+    assert_eq!(report.skipped, 4);
+}
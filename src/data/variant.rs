@@ -0,0 +1,136 @@
+use crate::serialize::{Deserializable, Serializable};
+
+/// A purchasable version of a product, like one size of a poster.
+///
+/// Variants have their own features, like price and SKU, in `ProductContainer::variant_features`,
+/// and category options know which variants have them, so filters can skip sold out variants.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Variant {
+    pub id: String,
+    pub title: String,
+    pub available: bool,
+    // The serialization id of the product this is a variant of
+    pub product: usize,
+    pub serialization_id: usize,
+}
+
+impl Serializable for Variant {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        self.id.serialize(output);
+        self.title.serialize(output);
+        self.available.serialize(output);
+    }
+}
+
+impl Variant {
+    pub fn deserialize(
+        input: &[u8],
+        product: usize,
+        serialization_id: usize,
+    ) -> Option<(&[u8], Variant)> {
+        let (input, id) = String::deserialize(input)?;
+        let (input, title) = String::deserialize(input)?;
+        let (input, available) = bool::deserialize(input)?;
+        Some((
+            input,
+            Variant {
+                id,
+                title,
+                available,
+                product,
+                serialization_id,
+            },
+        ))
+    }
+}
+
+#[test]
+fn test_variants() {
+    use crate::{
+        config::IndexConfig,
+        data::FeatureValue,
+        js_interactable::CategoryHandler,
+        testing::{build_test_index, test_products},
+    };
+    use std::sync::Arc;
+
+    let mut products = test_products();
+    let poster = "gid://shopify/Product/6837964669113";
+    // The largest size is sold out
+    for product in products.iter_mut().filter(|p| p.id == poster) {
+        for variant in product.variants.iter_mut().filter(|v| v.title == "70x100") {
+            variant.available_for_sale = false;
+        }
+    }
+    // Without variants, the sizes are the product's own and all of them have to match
+    let two_sizes = products
+        .iter_mut()
+        .find(|p| p.options.iter().any(|o| o.values == ["30x40", "50x70"]))
+        .unwrap();
+    two_sizes.variants.clear();
+    let two_sizes = two_sizes.id.to_string();
+    let built = build_test_index(products, IndexConfig::default());
+    let (container, classic) = (built.container, built.loaded_classic);
+
+    let product = container.products.iter().find(|p| p.id == poster).unwrap();
+    let titles: Vec<&str> = product
+        .variants
+        .clone()
+        .map(|id| container.variants[id].title.as_str())
+        .collect();
+    assert_eq!(titles, vec!["30x40", "50x70", "70x100"]);
+    let sku = container
+        .variant_features
+        .get_at(product.variants.start, "sku");
+    assert!(matches!(
+        sku,
+        Some(FeatureValue::String("P-CA-3040-POSTE-0769-S"))
+    ));
+
+    let mut categories = CategoryHandler::new(Arc::new(classic));
+    let mut sizes = categories.iter();
+    let mut size = loop {
+        let options = sizes.next_item().unwrap();
+        if options.name() == "Size" {
+            break options;
+        }
+    };
+    let mut option = |name: &str| loop {
+        let option = size.next_item().unwrap();
+        if option.get_name() == name {
+            break option;
+        }
+    };
+    let (small, large) = (option("30x40"), option("70x100"));
+
+    categories.toggle(&small);
+    let matched = categories.matching_variants(product, &container.variants);
+    assert_eq!(matched, Some(vec![product.variants.start]));
+
+    // Either size matches, as a variant only has one of them
+    categories.toggle(&large);
+    let matched = categories.matching_variants(product, &container.variants);
+    assert_eq!(matched, Some(vec![product.variants.start]));
+    let two_sizes = container
+        .products
+        .iter()
+        .find(|p| p.id == two_sizes)
+        .unwrap();
+    assert_eq!(
+        categories.matching_variants(two_sizes, &container.variants),
+        None
+    );
+    categories.toggle(&large);
+    assert_eq!(
+        categories.matching_variants(two_sizes, &container.variants),
+        Some(Vec::new())
+    );
+    categories.toggle(&large);
+    categories.toggle(&small);
+
+    // Only sold out variants have the size, so the product doesn't match
+    assert_eq!(
+        categories.matching_variants(product, &container.variants),
+        None
+    );
+}
@@ -0,0 +1,129 @@
+/// How the results of the indexes of a federated search are put together
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Merge {
+    // Every index's results after the ones of the index before it, for a section per index
+    #[default]
+    Grouped,
+    // All results by their score, relative to the most a result of the query could have in its index
+    Interleaved,
+}
+
+/// An index to search, by the name it was loaded with, and how many of its results to keep
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FederatedIndex {
+    pub name: String,
+    pub limit: usize,
+}
+
+/// A search across several indexes, given as JSON like
+/// `{"indexes": [{"name": "products", "limit": 5}, {"name": "articles", "limit": 3}], "merge": "interleaved"}`
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FederatedQuery {
+    pub indexes: Vec<FederatedIndex>,
+    #[serde(default)]
+    pub merge: Merge,
+}
+
+/// A result of a federated search
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+    // The position of its index in the query
+    pub index: usize,
+    pub serialization_id: usize,
+    // The confidence relative to the most a result of the query could have in its index, from 0 to 1
+    pub score: f32,
+}
+
+/// The ranked results of one index, as serialization ids with their confidence
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct IndexResults {
+    pub results: Vec<(usize, f32)>,
+    // The confidence of a result matching the whole query, which depends on the query and the index's config
+    pub max_confidence: f32,
+}
+
+impl FederatedQuery {
+    /// Puts together the ranked results of every index of the query.
+    ///
+    /// Confidences depend on the synonyms and analysis of an index, so they're normalised by the most a result
+    /// of the query could have in each index. An index whose best result only matches half the query doesn't
+    /// get to outrank one that matches all of it
+    pub fn merge(&self, results: Vec<IndexResults>) -> Vec<Hit> {
+        let mut hits = Vec::new();
+        for (index, (results, FederatedIndex { limit, .. })) in
+            results.into_iter().zip(&self.indexes).enumerate()
+        {
+            let IndexResults {
+                results,
+                max_confidence,
+            } = results;
+            let normalize = |confidence: f32| {
+                if max_confidence > 0.0 {
+                    (confidence / max_confidence).min(1.0)
+                } else {
+                    0.0
+                }
+            };
+            hits.extend(
+                results
+                    .into_iter()
+                    .take(*limit)
+                    .map(|(serialization_id, confidence)| Hit {
+                        index,
+                        serialization_id,
+                        score: normalize(confidence),
+                    }),
+            );
+        }
+
+        // The sort is stable, so equal scores keep the order of the indexes
+        if self.merge == Merge::Interleaved {
+            hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        }
+        hits
+    }
+}
+
+#[test]
+fn test_merge() {
+    let query: FederatedQuery = serde_json::from_str(
+        r#"{"indexes": [{"name": "products", "limit": 2}, {"name": "articles", "limit": 5}]}"#,
+    )
+    .unwrap();
+    let results = || {
+        vec![
+            IndexResults {
+                results: vec![(4, 30.0), (1, 15.0), (7, 3.0)],
+                max_confidence: 30.0,
+            },
+            IndexResults {
+                results: vec![(2, 0.9), (0, 0.6)],
+                max_confidence: 1.2,
+            },
+        ]
+    };
+
+    let ids = |hits: Vec<Hit>| -> Vec<(usize, usize)> {
+        hits.iter()
+            .map(|hit| (hit.index, hit.serialization_id))
+            .collect()
+    };
+    let grouped = query.merge(results());
+    assert_eq!(ids(grouped.clone()), [(0, 4), (0, 1), (1, 2), (1, 0)]);
+    // Scores are relative to the most each index's results could have, however different their confidences
+    let close = |a: f32, b: f32| (a - b).abs() < 1e-6;
+    assert!(close(grouped[0].score, 1.0));
+    assert!(close(grouped[1].score, 0.5));
+    assert!(close(grouped[2].score, 0.75));
+    assert!(close(grouped[3].score, 0.5));
+
+    let interleaved = FederatedQuery {
+        merge: Merge::Interleaved,
+        ..query
+    };
+    assert_eq!(
+        ids(interleaved.merge(results())),
+        [(0, 4), (1, 2), (0, 1), (1, 0)]
+    );
+}
@@ -1,6 +1,9 @@
 use std::sync::Arc;
 
-use crate::{classic_indexes::ClassicIndexes, data::Product};
+use crate::{
+    classic_indexes::ClassicIndexes,
+    data::{Product, Variant},
+};
 use ahash::AHashMap;
 use wasm_bindgen::prelude::*;
 
@@ -98,15 +101,82 @@ impl CategoryHandler {
         }
     }
 
-    pub fn is_valid(&self, product: &Product<'_>) -> bool {
-        for (category, option) in self.active.keys() {
-            let category = &self.handle.categories[*category];
-            let option = &category.options[*option];
-            if option.contains(product) == false {
-                return false;
+    /// Activates an option by its names, for use outside of JavaScript. False if there is no such option
+    pub fn activate(&mut self, category: &str, option: &str) -> bool {
+        let categories = &self.handle.categories;
+        let found = categories
+            .0
+            .iter()
+            .enumerate()
+            .find_map(|(category_id, c)| {
+                let option_id = c.options.iter().position(|o| o.name == option)?;
+                (c.name == category).then_some((category_id, option_id))
+            });
+        match found {
+            Some(keys) => {
+                self.active.insert(keys, ());
+                true
             }
+            None => false,
+        }
+    }
+
+    pub fn is_active(&self, category: &str, option: &str) -> bool {
+        self.active.keys().any(|(category_id, option_id)| {
+            let active = &self.handle.categories[*category_id];
+            active.name == category && active.options[*option_id].name == option
+        })
+    }
+
+    pub fn is_valid(&self, product: &Product<'_>, variants: &[Variant]) -> bool {
+        self.matching_variants(product, variants).is_some()
+    }
+
+    /// The serialization ids of the variants that match the active options, or `None` if the product doesn't.
+    ///
+    /// A product needs every active option, like a tag filter. Categories set on the product's variants only match
+    /// available variants, so a sold out size doesn't match its filter. The list is empty if no such category is active.
+    pub fn matching_variants(
+        &self,
+        product: &Product<'_>,
+        variants: &[Variant],
+    ) -> Option<Vec<usize>> {
+        let mut per_variant: AHashMap<usize, Vec<usize>> = AHashMap::new();
+        for (category_id, option_id) in self.active.keys() {
+            let category = &self.handle.categories[*category_id];
+            if category.options[*option_id].contains(product) == false {
+                return None;
+            }
+            if category.has_variants_of(product) {
+                per_variant
+                    .entry(*category_id)
+                    .or_default()
+                    .push(*option_id);
+            }
+        }
+        if per_variant.is_empty() {
+            return Some(Vec::new());
+        }
+
+        // A variant needs one of the active options of every category, as it only has one value for each
+        let matching: Vec<usize> = product
+            .variants
+            .clone()
+            .filter(|id| variants.get(*id).is_some_and(|v| v.available))
+            .filter(|id| {
+                per_variant.iter().all(|(category_id, options)| {
+                    let category = &self.handle.categories[*category_id];
+                    options
+                        .iter()
+                        .any(|option| category.options[*option].contains_variant(*id))
+                })
+            })
+            .collect();
+        if matching.is_empty() {
+            None
+        } else {
+            Some(matching)
         }
-        true
     }
 }
 
@@ -174,6 +244,14 @@ impl ExportCategoryOption {
 }
 
 impl ExportCategoryOption {
+    pub fn new(name: String, cat_id: usize, option_id: usize) -> ExportCategoryOption {
+        ExportCategoryOption {
+            name,
+            cat_id,
+            option_id,
+        }
+    }
+
     pub fn keys(&self) -> (usize, usize) {
         let ExportCategoryOption {
             cat_id, option_id, ..
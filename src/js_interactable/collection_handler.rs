@@ -0,0 +1,42 @@
+use std::sync::Arc;
+
+use wasm_bindgen::prelude::*;
+
+use crate::classic_indexes::{ClassicIndexes, Collection};
+
+#[wasm_bindgen]
+#[derive(Clone)]
+pub struct JsCollection {
+    handle: Arc<ClassicIndexes<'static>>,
+    index: usize,
+}
+
+impl JsCollection {
+    pub fn new(handle: Arc<ClassicIndexes<'static>>, index: usize) -> JsCollection {
+        JsCollection { handle, index }
+    }
+
+    fn collection(&self) -> &Collection<'static> {
+        self.handle.collections.get(self.index).unwrap()
+    }
+}
+
+#[wasm_bindgen]
+impl JsCollection {
+    pub fn get_id(&self) -> String {
+        self.collection().id.clone()
+    }
+
+    pub fn get_title(&self) -> String {
+        self.collection().title.clone()
+    }
+
+    // What to give `search` to search within the collection
+    pub fn get_handle(&self) -> String {
+        self.collection().handle.clone()
+    }
+
+    pub fn product_count(&self) -> usize {
+        self.collection().len()
+    }
+}
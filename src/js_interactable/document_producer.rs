@@ -0,0 +1,80 @@
+use wasm_bindgen::prelude::*;
+
+use crate::data::{Attribute, Document, DocumentContainer};
+
+#[wasm_bindgen]
+pub struct DocumentProducer {
+    container: &'static DocumentContainer,
+    to_export: Vec<usize>,
+    index: usize,
+}
+
+impl DocumentProducer {
+    pub fn new(container: &'static DocumentContainer, to_export: Vec<usize>) -> Self {
+        Self {
+            container,
+            to_export,
+            index: 0,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl DocumentProducer {
+    pub fn next_document(&mut self) -> Option<JsDocument> {
+        let serialization_id = *self.to_export.get(self.index)?;
+        self.container.documents.get(serialization_id)?;
+        self.index += 1;
+        Some(JsDocument::new(self.container, serialization_id))
+    }
+}
+
+#[wasm_bindgen]
+pub struct JsDocument {
+    container: &'static DocumentContainer,
+    serialization_id: usize,
+}
+
+impl JsDocument {
+    pub fn new(container: &'static DocumentContainer, serialization_id: usize) -> Self {
+        JsDocument {
+            container,
+            serialization_id,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl JsDocument {
+    fn document(&self) -> &Document {
+        &self.container.documents[self.serialization_id]
+    }
+
+    pub fn get_id(&self) -> String {
+        self.document().id.clone()
+    }
+
+    // The name of the document's schema, like "product" or "article"
+    pub fn get_schema(&self) -> String {
+        self.container.schema(self.document()).name.clone()
+    }
+
+    pub fn get_field(&self, name: &str) -> Option<String> {
+        Some(self.container.field(self.document(), name)?.to_string())
+    }
+
+    pub fn numeric_attribute(&self, key: &str) -> Option<f64> {
+        match self.container.attribute(self.document(), key)? {
+            Attribute::Float(f) => Some(f64::from(*f)),
+            Attribute::Integer(i) => Some(f64::from(*i)),
+            Attribute::String(_) => None,
+        }
+    }
+
+    pub fn string_attribute(&self, key: &str) -> Option<String> {
+        match self.container.attribute(self.document(), key)? {
+            Attribute::String(string) => Some(string.clone()),
+            _ => None,
+        }
+    }
+}
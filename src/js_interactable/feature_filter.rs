@@ -1,29 +1,60 @@
 use wasm_bindgen::prelude::*;
 
-use crate::data::{FeatureSet, Product};
+use crate::data::{
+    major_units, parse_minor_units, FeatureSet, FeatureValue, Prices, Product, ProductContainer,
+};
 
 pub struct FeatureFilter {
     data: FilterData,
     feature: String,
 }
 
+// Values are taken out of JavaScript when the filter is parsed, so filters also work outside of it
 #[derive(Debug)]
 pub enum FilterData {
-    Range { from: JsValue, to: JsValue },
-    Exact(JsValue),
+    Range { from: Option<f64>, to: Option<f64> },
+    // Values no feature can have, like booleans, are `None` and match nothing
+    Exact(Option<FilterValue>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterValue {
+    Number(f64),
+    Text(String),
+}
+
+impl FilterValue {
+    fn from_js(value: &JsValue) -> Option<FilterValue> {
+        match (value.as_f64(), value.as_string()) {
+            (Some(number), _) => Some(FilterValue::Number(number)),
+            (None, Some(text)) => Some(FilterValue::Text(text)),
+            (None, None) => None,
+        }
+    }
+
+    // Like the strict equality of JavaScript, which exact filters used to be compared with
+    #[allow(clippy::float_cmp)]
+    fn matches(&self, found: &FeatureValue<'_>) -> bool {
+        match (self, found) {
+            (FilterValue::Number(n), FeatureValue::Float(found)) => f64::from(*found) == *n,
+            (FilterValue::Number(n), FeatureValue::Integer(found)) => f64::from(*found) == *n,
+            (FilterValue::Text(text), FeatureValue::String(found)) => text == found,
+            _ => false,
+        }
+    }
 }
 
 impl FeatureFilter {
-    pub fn new_range(from: JsValue, to: JsValue, feature: String) -> Self {
+    pub fn new_range(from: Option<f64>, to: Option<f64>, feature: String) -> Self {
         Self {
             data: FilterData::Range { from, to },
             feature,
         }
     }
 
-    pub fn new_exactly(exactly: JsValue, feature: String) -> Self {
+    pub fn new_exactly(exactly: FilterValue, feature: String) -> Self {
         Self {
-            data: FilterData::Exact(exactly),
+            data: FilterData::Exact(Some(exactly)),
             feature,
         }
     }
@@ -41,59 +72,84 @@ impl FeatureFilter {
 
             if let Some(from) = get_value(&values, "from") {
                 if let Some(to) = get_value(&values, "to") {
-                    out.push(FeatureFilter {
-                        data: FilterData::Range { from, to },
+                    out.push(FeatureFilter::new_range(
+                        from.as_f64(),
+                        to.as_f64(),
                         feature,
-                    });
+                    ));
                     continue;
                 }
             }
             let exact = get_value(&values, "exact")?;
             out.push(FeatureFilter {
                 feature,
-                data: FilterData::Exact(exact),
+                data: FilterData::Exact(FilterValue::from_js(&exact)),
             });
         }
         Some(out)
     }
 
-    pub fn filter<'a>(
-        &'a self,
-        iter: Box<dyn Iterator<Item = &'a Product<'a>> + 'a>,
-        feature: &'a FeatureSet,
-    ) -> Box<dyn Iterator<Item = &'a Product> + 'a> {
+    /// If the product's feature passes the filter. A product without the feature only passes a range without bounds
+    pub fn matches(&self, product: &Product<'_>, features: &FeatureSet) -> bool {
+        if let FilterData::Range {
+            from: None,
+            to: None,
+        } = self.data
+        {
+            return true;
+        }
+        let Some(found) = features.get(product, &self.feature) else {
+            return false;
+        };
         match &self.data {
-            FilterData::Range { from, to } => match (from.as_f64(), to.as_f64()) {
-                (Some(from), Some(to)) => Box::new(iter.filter(move |p| {
-                    if let Some(found) = feature.get_js(p, &self.feature).and_then(|j| j.as_f64()) {
-                        from <= found && found <= to
-                    } else {
-                        false
-                    }
-                })),
-                (Some(from), None) => Box::new(iter.filter(move |p| {
-                    if let Some(found) = feature.get_js(p, &self.feature).and_then(|j| j.as_f64()) {
-                        from <= found
-                    } else {
-                        false
-                    }
-                })),
-                (None, Some(to)) => Box::new(iter.filter(move |p| {
-                    if let Some(found) = feature.get_js(p, &self.feature).and_then(|j| j.as_f64()) {
-                        found <= to
-                    } else {
-                        false
-                    }
-                })),
-                (None, None) => Box::new(iter),
-            },
-            FilterData::Exact(exact) => Box::new(iter.filter(move |p| {
-                if let Some(data) = feature.get_js(p, &self.feature) {
-                    &data == exact
-                } else {
-                    false
-                }
-            })),
+            FilterData::Range { from, to } => {
+                let found = match found {
+                    FeatureValue::Float(found) => f64::from(found),
+                    FeatureValue::Integer(found) => f64::from(found),
+                    FeatureValue::String(_) => return false,
+                };
+                from.is_none_or(|from| from <= found) && to.is_none_or(|to| found <= to)
+            }
+            FilterData::Exact(exact) => exact.as_ref().is_some_and(|exact| exact.matches(&found)),
+        }
+    }
+
+    // Prices aren't features, as they depend on the currency
+    pub fn is_price(&self) -> bool {
+        self.feature == "price"
+    }
+
+    /// If the lowest price in the currency passes the filter, the values are given in whole units like 249.95
+    pub fn matches_price(&self, product: &Product<'_>, prices: &Prices, currency: &str) -> bool {
+        let Some(lowest) = prices
+            .get(product.serialization_id, Some(currency))
+            .map(|range| range.min)
+        else {
+            return false;
+        };
+        match &self.data {
+            FilterData::Range { from, to } => {
+                let found = major_units(lowest, currency);
+                from.unwrap_or(f64::NEG_INFINITY) <= found && found <= to.unwrap_or(f64::INFINITY)
+            }
+            FilterData::Exact(Some(FilterValue::Number(exact))) => {
+                parse_minor_units(&exact.to_string(), currency) == Some(lowest)
+            }
+            FilterData::Exact(_) => false,
+        }
+    }
+
+    /// If the product passes the filter, prices being filtered in the currency
+    pub fn passes(
+        &self,
+        product: &Product<'_>,
+        container: &ProductContainer<'_>,
+        currency: &str,
+    ) -> bool {
+        if self.is_price() {
+            self.matches_price(product, &container.prices, currency)
+        } else {
+            self.matches(product, &container.extra_features)
         }
     }
 }
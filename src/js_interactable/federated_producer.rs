@@ -0,0 +1,122 @@
+use std::sync::Arc;
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    classic_indexes::ClassicIndexes,
+    data::{DocumentContainer, ProductContainer},
+    federated::Hit,
+};
+
+use super::{JsCollection, JsDocument, JsProduct};
+
+/// What an index of a federated search holds
+#[derive(Clone)]
+pub enum FederatedSource {
+    Products(&'static ProductContainer<'static>),
+    Collections(Arc<ClassicIndexes<'static>>),
+    Documents(&'static DocumentContainer),
+    // An index that wasn't loaded, which has no results
+    Missing,
+}
+
+#[wasm_bindgen]
+pub struct FederatedProducer {
+    // The name and contents of every index, in the order of the query
+    sources: Vec<(String, FederatedSource)>,
+    hits: Vec<Hit>,
+    index: usize,
+    partial: bool,
+    missing: Vec<String>,
+}
+
+impl FederatedProducer {
+    pub fn new(
+        sources: Vec<(String, FederatedSource)>,
+        hits: Vec<Hit>,
+        partial: bool,
+        missing: Vec<String>,
+    ) -> Self {
+        Self {
+            sources,
+            hits,
+            index: 0,
+            partial,
+            missing,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl FederatedProducer {
+    // True if the product index was searched before all the shards it needed were loaded,
+    // or if an index of the query wasn't loaded
+    pub fn is_partial(&self) -> bool {
+        self.partial || self.missing.is_empty() == false
+    }
+
+    // The names of the indexes of the query that weren't loaded, and so weren't searched
+    pub fn get_missing_indexes(&self) -> Vec<JsValue> {
+        self.missing.iter().map(JsValue::from).collect()
+    }
+
+    pub fn next_hit(&mut self) -> Option<FederatedHit> {
+        let hit = *self.hits.get(self.index)?;
+        let (name, source) = self.sources.get(hit.index)?;
+        self.index += 1;
+        Some(FederatedHit {
+            index: name.clone(),
+            source: source.clone(),
+            serialization_id: hit.serialization_id,
+            score: hit.score,
+        })
+    }
+}
+
+#[wasm_bindgen]
+pub struct FederatedHit {
+    index: String,
+    source: FederatedSource,
+    serialization_id: usize,
+    score: f32,
+}
+
+#[wasm_bindgen]
+impl FederatedHit {
+    // The name of the index it was found in
+    pub fn get_index(&self) -> String {
+        self.index.clone()
+    }
+
+    // From 0 to 1, relative to the best result of its index
+    pub fn get_score(&self) -> f32 {
+        self.score
+    }
+
+    pub fn get_product(&self) -> Option<JsProduct> {
+        match self.source {
+            FederatedSource::Products(container) => {
+                Some(JsProduct::new(container, self.serialization_id, Vec::new()))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn get_collection(&self) -> Option<JsCollection> {
+        match &self.source {
+            FederatedSource::Collections(classic) => {
+                Some(JsCollection::new(classic.clone(), self.serialization_id))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn get_document(&self) -> Option<JsDocument> {
+        match self.source {
+            FederatedSource::Documents(container) => {
+                Some(JsDocument::new(container, self.serialization_id))
+            }
+            _ => None,
+        }
+    }
+}
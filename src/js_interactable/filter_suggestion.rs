@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    classic_indexes::{ClassicIndexes, FilterSuggestion, FilterTarget},
+    data::ProductContainer,
+};
+
+use super::{ExportCategoryOption, JSTag};
+
+#[wasm_bindgen]
+pub struct JsFilterSuggestion {
+    handle: Arc<ClassicIndexes<'static>>,
+    container: &'static ProductContainer<'static>,
+    suggestion: FilterSuggestion,
+    // The words of the query it covers
+    input: String,
+}
+
+impl JsFilterSuggestion {
+    pub fn new(
+        handle: Arc<ClassicIndexes<'static>>,
+        container: &'static ProductContainer<'static>,
+        suggestion: FilterSuggestion,
+        query: &str,
+    ) -> JsFilterSuggestion {
+        let input = query[suggestion.span.clone()].to_string();
+        JsFilterSuggestion {
+            handle,
+            container,
+            suggestion,
+            input,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl JsFilterSuggestion {
+    // "tag", "category" or "vendor"
+    pub fn get_kind(&self) -> String {
+        match self.suggestion.target {
+            FilterTarget::Tag(_) => "tag",
+            FilterTarget::CategoryOption { .. } => "category",
+            FilterTarget::Vendor(_) => "vendor",
+        }
+        .to_string()
+    }
+
+    // The name of the tag, option or vendor
+    pub fn get_name(&self) -> String {
+        match self.suggestion.target {
+            FilterTarget::Tag(id) => self.handle.tags.get(id).unwrap().name.clone(),
+            FilterTarget::CategoryOption { category, option } => self.handle.categories[category]
+                .options[option]
+                .name
+                .clone(),
+            FilterTarget::Vendor(id) => self.container.vendors.by_id[id].name.clone(),
+        }
+    }
+
+    pub fn get_score(&self) -> f32 {
+        self.suggestion.score
+    }
+
+    pub fn get_input(&self) -> String {
+        self.input.clone()
+    }
+
+    // The position of the first word of the query it covers, and how many it covers,
+    // so the words can be taken out of the query once the filter is applied
+    pub fn get_first_word(&self) -> usize {
+        self.suggestion.words.start
+    }
+
+    pub fn get_word_count(&self) -> usize {
+        self.suggestion.words.len()
+    }
+
+    pub fn get_tag(&self) -> Option<JSTag> {
+        match self.suggestion.target {
+            FilterTarget::Tag(id) => Some(JSTag::new(self.handle.clone(), id)),
+            _ => None,
+        }
+    }
+
+    // To toggle on a `CategoryHandler`
+    pub fn get_category_option(&self) -> Option<ExportCategoryOption> {
+        match self.suggestion.target {
+            FilterTarget::CategoryOption { category, option } => {
+                Some(ExportCategoryOption::new(self.get_name(), category, option))
+            }
+            _ => None,
+        }
+    }
+}
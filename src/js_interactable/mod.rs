@@ -1,9 +1,17 @@
 mod category_handler;
+mod collection_handler;
+mod document_producer;
 mod feature_filter;
+mod federated_producer;
+mod filter_suggestion;
 mod product_producer;
 mod tag_handler;
 
 pub use category_handler::*;
+pub use collection_handler::JsCollection;
+pub use document_producer::{DocumentProducer, JsDocument};
 pub use feature_filter::*;
-pub use product_producer::ProductProducer;
+pub use federated_producer::{FederatedHit, FederatedProducer, FederatedSource};
+pub use filter_suggestion::JsFilterSuggestion;
+pub use product_producer::{JsPriceRange, JsProduct, JsVariant, ProductProducer};
 pub use tag_handler::*;
@@ -1,26 +1,95 @@
+use ahash::AHashMap;
 use wasm_bindgen::prelude::*;
 
-use crate::data::{FeatureValue, Product, ProductContainer};
+use crate::{
+    config::ProductField,
+    data::{
+        format_minor_units, major_units, FeatureSet, FeatureValue, PriceRange, Prices, Product,
+        ProductContainer, Variant,
+    },
+};
+
+fn numeric(features: &FeatureSet, id: usize, key: &str) -> Option<f64> {
+    match features.get_at(id, key)? {
+        FeatureValue::Float(f) => Some(f64::from(f)),
+        FeatureValue::Integer(i) => Some(f64::from(i)),
+        FeatureValue::String(_) => None,
+    }
+}
+
+// Prices used to be the "price" feature, its lowest price in the default currency in whole units like 249.95
+fn price_feature(prices: &Prices, id: usize) -> Option<f64> {
+    let range = prices.get(id, None)?;
+    Some(major_units(range.min, prices.default_currency()))
+}
+
+fn price(prices: &Prices, id: usize, currency: Option<String>) -> Option<JsPriceRange> {
+    let currency = currency.unwrap_or_else(|| prices.default_currency().to_string());
+    let range = prices.get(id, Some(&currency))?;
+    Some(JsPriceRange { currency, range })
+}
+
+fn string(features: &FeatureSet, id: usize, key: &str) -> Option<String> {
+    match features.get_at(id, key)? {
+        FeatureValue::String(string) => Some(string.to_string()),
+        _ => None,
+    }
+}
 
 #[wasm_bindgen]
 pub struct ProductProducer {
     container: &'static ProductContainer<'static>,
     to_export: Vec<usize>,
     index: usize,
+    partial: bool,
+    explored_nodes: usize,
+    // The variants that matched the category filters, by product
+    matched_variants: AHashMap<usize, Vec<usize>>,
 }
 
 impl ProductProducer {
-    pub fn new(container: &'static ProductContainer<'static>, to_export: Vec<usize>) -> Self {
+    pub fn new(
+        container: &'static ProductContainer<'static>,
+        to_export: Vec<usize>,
+        partial: bool,
+        explored_nodes: usize,
+        matched_variants: AHashMap<usize, Vec<usize>>,
+    ) -> Self {
         Self {
             container,
             to_export,
             index: 0,
+            partial,
+            explored_nodes,
+            matched_variants,
         }
     }
 }
 
 #[wasm_bindgen]
 impl ProductProducer {
+    // True if the search ran before all the shards it needed were loaded
+    pub fn is_partial(&self) -> bool {
+        self.partial
+    }
+
+    // How many gram tree nodes the search looked at, to see what a typo tolerance costs
+    pub fn explored_nodes(&self) -> usize {
+        self.explored_nodes
+    }
+
+    // The lowest and highest price of all the results, for things like a price slider
+    pub fn price_range(&self, currency: Option<String>) -> Option<JsPriceRange> {
+        let prices = &self.container.prices;
+        let currency = currency.unwrap_or_else(|| prices.default_currency().to_string());
+        let range = self
+            .to_export
+            .iter()
+            .filter_map(|id| prices.get(*id, Some(&currency)))
+            .reduce(PriceRange::merge)?;
+        Some(JsPriceRange { currency, range })
+    }
+
     pub fn next_product(&mut self) -> Option<JsProduct> {
         let next_id = *self.to_export.get(self.index)?;
         let container = self.container;
@@ -28,10 +97,8 @@ impl ProductProducer {
         let _ = container.products.get(next_id)?;
         self.index += 1;
 
-        Some(JsProduct {
-            container,
-            serialization_id: next_id,
-        })
+        let matched_variants = self.matched_variants.remove(&next_id).unwrap_or_default();
+        Some(JsProduct::new(container, next_id, matched_variants))
     }
 }
 
@@ -39,6 +106,21 @@ impl ProductProducer {
 pub struct JsProduct {
     container: &'static ProductContainer<'static>,
     serialization_id: usize,
+    matched_variants: Vec<usize>,
+}
+
+impl JsProduct {
+    pub fn new(
+        container: &'static ProductContainer<'static>,
+        serialization_id: usize,
+        matched_variants: Vec<usize>,
+    ) -> Self {
+        JsProduct {
+            container,
+            serialization_id,
+            matched_variants,
+        }
+    }
 }
 
 #[wasm_bindgen]
@@ -47,22 +129,40 @@ impl JsProduct {
         &self.container.products[self.serialization_id]
     }
 
+    // "price" gives the lowest price in the default currency, see `price` for the others
     pub fn numeric_feature(&self, key: &str) -> Option<f64> {
-        let features = &self.container.extra_features;
-        match features.get(self.product(), key)? {
-            FeatureValue::Float(f) => Some(f64::from(f)),
-            FeatureValue::Integer(i) => Some(f64::from(i)),
-            FeatureValue::String(_) => None,
+        if key == "price" {
+            return price_feature(&self.container.prices, self.serialization_id);
         }
+        numeric(&self.container.extra_features, self.serialization_id, key)
     }
 
     pub fn string_feature(&self, key: &str) -> Option<String> {
-        let features = &self.container.extra_features;
-        if let Some(FeatureValue::String(string)) = features.get(self.product(), key) {
-            Some(string.to_string())
-        } else {
-            None
-        }
+        string(&self.container.extra_features, self.serialization_id, key)
+    }
+
+    // The price in the currency, or in the index's default currency
+    pub fn price(&self, currency: Option<String>) -> Option<JsPriceRange> {
+        price(&self.container.prices, self.serialization_id, currency)
+    }
+
+    pub fn variant_count(&self) -> usize {
+        self.product().variants.len()
+    }
+
+    pub fn get_variant(&self, index: usize) -> Option<JsVariant> {
+        let serialization_id = self.product().variants.clone().nth(index)?;
+        Some(JsVariant {
+            container: self.container,
+            serialization_id,
+        })
+    }
+
+    // The positions of the variants that matched the active category filters, for `get_variant`.
+    // Empty if no filter was set on the variants
+    pub fn matched_variants(&self) -> Vec<usize> {
+        let first = self.product().variants.start;
+        self.matched_variants.iter().map(|id| id - first).collect()
     }
 
     pub fn get_title(&self) -> String {
@@ -76,4 +176,96 @@ impl JsProduct {
     pub fn get_id(&self) -> String {
         self.product().id.clone()
     }
+
+    // True if the field was shortened when the index was built, so the full text needs to be fetched
+    pub fn is_truncated(&self, field: &str) -> bool {
+        ProductField::from_name(field).is_some_and(|field| self.product().truncated.contains(field))
+    }
+
+    // False if the field is only indexed, and the stored text is empty
+    pub fn is_stored(&self, field: &str) -> bool {
+        ProductField::from_name(field)
+            .is_some_and(|field| self.container.config.policy(field).is_stored())
+    }
+}
+
+#[wasm_bindgen]
+pub struct JsVariant {
+    container: &'static ProductContainer<'static>,
+    serialization_id: usize,
+}
+
+#[wasm_bindgen]
+impl JsVariant {
+    fn variant(&self) -> &Variant {
+        &self.container.variants[self.serialization_id]
+    }
+
+    pub fn get_id(&self) -> String {
+        self.variant().id.clone()
+    }
+
+    pub fn get_title(&self) -> String {
+        self.variant().title.clone()
+    }
+
+    pub fn is_available(&self) -> bool {
+        self.variant().available
+    }
+
+    // Variants without their own price in the currency have the product's price
+    pub fn price(&self, currency: Option<String>) -> Option<JsPriceRange> {
+        price(
+            &self.container.variant_prices,
+            self.serialization_id,
+            currency.clone(),
+        )
+        .or_else(|| price(&self.container.prices, self.variant().product, currency))
+    }
+
+    // Variants have the sku and image_url features, and "price" like products
+    pub fn numeric_feature(&self, key: &str) -> Option<f64> {
+        if key == "price" {
+            return price_feature(&self.container.variant_prices, self.serialization_id)
+                .or_else(|| price_feature(&self.container.prices, self.variant().product));
+        }
+        numeric(&self.container.variant_features, self.serialization_id, key)
+    }
+
+    pub fn string_feature(&self, key: &str) -> Option<String> {
+        string(&self.container.variant_features, self.serialization_id, key)
+    }
+}
+
+/// A price in minor units, with the currency it is in
+#[wasm_bindgen]
+pub struct JsPriceRange {
+    currency: String,
+    range: PriceRange,
+}
+
+#[wasm_bindgen]
+impl JsPriceRange {
+    pub fn get_currency(&self) -> String {
+        self.currency.clone()
+    }
+
+    // The amounts are written like "249.95", so they can be shown without rounding errors
+    pub fn get_min(&self) -> String {
+        format_minor_units(self.range.min, &self.currency)
+    }
+
+    pub fn get_max(&self) -> String {
+        format_minor_units(self.range.max, &self.currency)
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn min_minor_units(&self) -> f64 {
+        self.range.min as f64
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn max_minor_units(&self) -> f64 {
+        self.range.max as f64
+    }
 }
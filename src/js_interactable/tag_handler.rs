@@ -94,6 +94,25 @@ impl TagHandler {
         }
     }
 
+    /// Activates a tag by its name, for use outside of JavaScript. False if there is no such tag
+    pub fn activate(&mut self, name: &str) -> bool {
+        let found = self.handle.tags.iter().find(|tag| tag.name == name);
+        match found {
+            Some(tag) => {
+                self.active.insert(tag.get_id(), ());
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_active(&self, name: &str) -> bool {
+        self.active
+            .keys()
+            .filter_map(|id| self.handle.tags.get(*id))
+            .any(|tag| tag.name == name)
+    }
+
     pub fn is_valid(&self, product: &Product<'_>) -> bool {
         for id in self.active.keys() {
             let tag = self.handle.tags.get(*id).unwrap();
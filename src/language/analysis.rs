@@ -0,0 +1,223 @@
+use std::borrow::Cow;
+
+use crate::serialize::{Deserializable, Serializable};
+
+use super::Language;
+
+/// Token level analysis of the indexed text and queries: stop word removal and stemming.
+///
+/// The analysis is stored in the index, so queries are analysed the same way the products were.
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct AnalysisConfig {
+    // The language of products without a language attribute. No language turns the analysis off.
+    // Queries are analysed in it and in the languages of the fields below
+    pub language: Option<Language>,
+    // Fields written in one language for every product, like English titles in a Danish shop
+    pub title_language: Option<Language>,
+    pub description_language: Option<Language>,
+    pub stemming: bool,
+    pub stop_words: bool,
+}
+
+impl Default for AnalysisConfig {
+    fn default() -> Self {
+        AnalysisConfig {
+            language: None,
+            title_language: None,
+            description_language: None,
+            stemming: true,
+            stop_words: true,
+        }
+    }
+}
+
+// Words are runs of letters and digits, everything between them becomes a single space
+fn tokens(input: &str) -> impl Iterator<Item = &str> {
+    input
+        .split(|c: char| c.is_alphanumeric() == false)
+        .filter(|token| token.is_empty() == false)
+}
+
+impl AnalysisConfig {
+    pub fn analyze<'s>(&self, input: &'s str, language: Option<Language>) -> Cow<'s, str> {
+        let Some(language) = language else {
+            return Cow::Borrowed(input);
+        };
+
+        let words: Vec<String> = tokens(input)
+            .filter(|token| self.stop_words == false || language.is_stop_word(token) == false)
+            .map(|token| self.stem(token, language))
+            .collect();
+        Cow::Owned(words.join(" "))
+    }
+
+    /// Queries made only of stop words keep them, so searching for them still finds something
+    pub fn analyze_query<'s>(&self, query: &'s str, language: Option<Language>) -> Cow<'s, str> {
+        let Some(language) = language else {
+            return Cow::Borrowed(query);
+        };
+
+        let only_stop_words = tokens(query).all(|token| language.is_stop_word(token));
+        if only_stop_words {
+            let words: Vec<String> = tokens(query)
+                .map(|token| self.stem(token, language))
+                .collect();
+            return Cow::Owned(words.join(" "));
+        }
+        self.analyze(query, Some(language))
+    }
+
+    /// The languages the fields were indexed in, the index's own first
+    pub fn query_languages(&self) -> Vec<Option<Language>> {
+        let mut languages = vec![self.language];
+        for language in [self.title_language, self.description_language] {
+            if language.is_some() && languages.contains(&language) == false {
+                languages.push(language);
+            }
+        }
+        languages
+    }
+
+    /// The query analysed in every language the fields were indexed in, without repeats
+    pub fn analyze_query_variants(&self, query: &str) -> Vec<String> {
+        let mut variants: Vec<String> = Vec::new();
+        for language in self.query_languages() {
+            let analyzed = self.analyze_query(query, language);
+            if variants.iter().any(|variant| *variant == analyzed) == false {
+                variants.push(analyzed.into_owned());
+            }
+        }
+        variants
+    }
+
+    fn stem(&self, token: &str, language: Language) -> String {
+        if self.stemming {
+            language.stem(token)
+        } else {
+            token.to_string()
+        }
+    }
+}
+
+impl Serializable for AnalysisConfig {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        let AnalysisConfig {
+            language,
+            title_language,
+            description_language,
+            stemming,
+            stop_words,
+        } = self;
+        language.serialize(output);
+        title_language.serialize(output);
+        description_language.serialize(output);
+        stemming.serialize(output);
+        stop_words.serialize(output);
+    }
+}
+
+impl Deserializable for AnalysisConfig {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (input, language) = Deserializable::deserialize(input)?;
+        let (input, title_language) = Deserializable::deserialize(input)?;
+        let (input, description_language) = Deserializable::deserialize(input)?;
+        let (input, stemming) = bool::deserialize(input)?;
+        let (input, stop_words) = bool::deserialize(input)?;
+        Some((
+            input,
+            AnalysisConfig {
+                language,
+                title_language,
+                description_language,
+                stemming,
+                stop_words,
+            },
+        ))
+    }
+}
+
+#[test]
+fn test_analysis() {
+    let analysis = AnalysisConfig {
+        language: Some(Language::Danish),
+        ..AnalysisConfig::default()
+    };
+
+    assert_eq!(
+        analysis.analyze("kunsten og plakaterne", Some(Language::Danish)),
+        "kunst plakat"
+    );
+    assert_eq!(
+        analysis.analyze("the shirts, in blue", Some(Language::English)),
+        "shirt blue"
+    );
+    assert_eq!(analysis.analyze("The shirts", None), "The shirts");
+    assert_eq!(
+        analysis.analyze_query("kunsten", analysis.language),
+        "kunst"
+    );
+    assert_eq!(analysis.analyze_query("og", analysis.language), "og");
+
+    // Titles in English are searched for with the query analysed as English too
+    let analysis = AnalysisConfig {
+        title_language: Some(Language::English),
+        description_language: Some(Language::Danish),
+        ..analysis
+    };
+    assert_eq!(
+        analysis.query_languages(),
+        [Some(Language::Danish), Some(Language::English)]
+    );
+    assert_eq!(
+        analysis.analyze_query_variants("plakaterne shirts"),
+        ["plakat shirt", "plakatern shirt"]
+    );
+    assert_eq!(analysis.analyze_query_variants("kunst"), ["kunst"]);
+}
+
+#[test]
+fn test_mixed_language_search() {
+    use crate::{
+        config::IndexConfig,
+        ngram::SearchStats,
+        testing::{build_test_index, test_products},
+        LoadedIndex,
+    };
+
+    // A Danish shop with English titles
+    let mut products = test_products();
+    products[0].title = "Wonderful paintings".into();
+    let id = products[0].id.to_string();
+    let config = IndexConfig {
+        analysis: AnalysisConfig {
+            language: Some(Language::Danish),
+            title_language: Some(Language::English),
+            ..AnalysisConfig::default()
+        },
+        ..IndexConfig::default()
+    };
+    let analysis = &config.analysis;
+    assert_eq!(
+        analysis.analyze_query("wonderful paintings", Some(Language::English)),
+        "wonder paint"
+    );
+    assert!(analysis.analyze_query("wonderful paintings", analysis.language) != "wonder paint");
+
+    let tolerance = config.typo_tolerance;
+    let built = build_test_index(products, config.clone());
+    let (index, classic) = (LoadedIndex::Eager(built.index), built.classic);
+    let ids = |query: &str| -> Vec<String> {
+        let ranked = index.rank(query, &classic, &tolerance, &SearchStats::default());
+        ranked
+            .results
+            .iter()
+            .map(|(product, _)| product.id.to_string())
+            .collect()
+    };
+    // The title is found with the query analysed as English, the words as the phrase too
+    assert!(ids("paintings").contains(&id));
+    assert_eq!(ids("\"wonderful paintings\""), [id.clone()]);
+    // While the Danish descriptions are still found by the query analysed as Danish
+    assert!(ids("plakaterne").is_empty() == false);
+}
@@ -0,0 +1,89 @@
+mod analysis;
+pub mod stem;
+mod stop_words;
+mod synonyms;
+
+pub use analysis::AnalysisConfig;
+pub use synonyms::{SynonymRule, Synonyms};
+
+use crate::serialize::{Deserializable, Serializable};
+
+/// The languages the text pipeline knows the rules of
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Language {
+    #[serde(rename = "da")]
+    Danish,
+    #[serde(rename = "en")]
+    English,
+    #[serde(rename = "de")]
+    German,
+}
+
+impl Language {
+    pub const ALL: [Language; 3] = [Language::Danish, Language::English, Language::German];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::Danish => "da",
+            Language::English => "en",
+            Language::German => "de",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Language> {
+        Language::ALL
+            .into_iter()
+            .find(|language| language.code() == code)
+    }
+
+    /// Letters that are part of the alphabet rather than an accented form of another letter,
+    /// so they're kept when accents are stripped
+    pub fn distinct_letters(self) -> &'static [char] {
+        match self {
+            Language::Danish => &['æ', 'ø', 'å', 'Æ', 'Ø', 'Å'],
+            Language::English => &[],
+            Language::German => &['ä', 'ö', 'ü', 'ß', 'Ä', 'Ö', 'Ü'],
+        }
+    }
+
+    pub fn stem(self, word: &str) -> String {
+        match self {
+            Language::Danish => stem::danish(word),
+            Language::English => stem::english(word),
+            Language::German => stem::german(word),
+        }
+    }
+
+    pub fn is_stop_word(self, word: &str) -> bool {
+        let stop_words = match self {
+            Language::Danish => stop_words::DANISH,
+            Language::English => stop_words::ENGLISH,
+            Language::German => stop_words::GERMAN,
+        };
+        stop_words.contains(&word)
+    }
+}
+
+impl Serializable for Language {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        let id: u8 = match self {
+            Language::Danish => 0,
+            Language::English => 1,
+            Language::German => 2,
+        };
+        id.serialize(output);
+    }
+}
+
+impl Deserializable for Language {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (input, id) = u8::deserialize(input)?;
+        let language = match id {
+            0 => Language::Danish,
+            1 => Language::English,
+            2 => Language::German,
+            _ => return None,
+        };
+        Some((input, language))
+    }
+}
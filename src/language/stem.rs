@@ -0,0 +1,564 @@
+/*
+Stemmers following the Snowball algorithms for Danish, English (Porter2) and German.
+See https://snowballstem.org/algorithms/ for the rules, the comments below refer to their step names.
+
+The rules are written for lower case words, so they're run after the text is normalised.
+*/
+
+// A word being stemmed, with the regions the rules refer to
+struct Word {
+    chars: Vec<char>,
+    r1: usize,
+    r2: usize,
+}
+
+// The region after the first non-vowel following a vowel, starting from `start`
+fn region_after(chars: &[char], start: usize, is_vowel: fn(char) -> bool) -> usize {
+    let mut seen_vowel = false;
+    for (i, char) in chars.iter().enumerate().skip(start) {
+        if is_vowel(*char) {
+            seen_vowel = true;
+        } else if seen_vowel {
+            return i + 1;
+        }
+    }
+    chars.len()
+}
+
+impl Word {
+    fn new(word: &str, is_vowel: fn(char) -> bool) -> Word {
+        let chars: Vec<char> = word.chars().collect();
+        let r1 = region_after(&chars, 0, is_vowel);
+        let r2 = region_after(&chars, r1, is_vowel);
+        Word { chars, r1, r2 }
+    }
+
+    fn set_regions(&mut self, r1: usize, is_vowel: fn(char) -> bool) {
+        self.r1 = r1;
+        self.r2 = region_after(&self.chars, r1, is_vowel);
+    }
+
+    fn len(&self) -> usize {
+        self.chars.len()
+    }
+
+    fn ends_with(&self, suffix: &str) -> bool {
+        let len = suffix.chars().count();
+        len <= self.len()
+            && self.chars[self.len() - len..]
+                .iter()
+                .copied()
+                .eq(suffix.chars())
+    }
+
+    // Where the suffix starts, the word has to end with it
+    fn start_of(&self, suffix: &str) -> usize {
+        self.len() - suffix.chars().count()
+    }
+
+    fn in_r1(&self, suffix: &str) -> bool {
+        self.start_of(suffix) >= self.r1
+    }
+
+    fn in_r2(&self, suffix: &str) -> bool {
+        self.start_of(suffix) >= self.r2
+    }
+
+    fn char_before(&self, suffix: &str) -> Option<char> {
+        let start = self.start_of(suffix);
+        start.checked_sub(1).map(|i| self.chars[i])
+    }
+
+    // Checks what's before the suffix, without the suffix itself
+    fn ends_with_before(&self, suffix: &str, before: &str) -> bool {
+        let start = self.start_of(suffix);
+        let len = before.chars().count();
+        len <= start
+            && self.chars[start - len..start]
+                .iter()
+                .copied()
+                .eq(before.chars())
+    }
+
+    fn longest_suffix<'s>(&self, suffixes: &[&'s str]) -> Option<&'s str> {
+        suffixes
+            .iter()
+            .filter(|suffix| self.ends_with(suffix))
+            .max_by_key(|suffix| suffix.chars().count())
+            .copied()
+    }
+
+    // Like `longest_suffix`, but only suffixes that start at or after `limit` are considered
+    fn longest_suffix_from<'s>(&self, suffixes: &[&'s str], limit: usize) -> Option<&'s str> {
+        suffixes
+            .iter()
+            .filter(|suffix| self.ends_with(suffix) && self.start_of(suffix) >= limit)
+            .max_by_key(|suffix| suffix.chars().count())
+            .copied()
+    }
+
+    fn remove(&mut self, suffix: &str) {
+        let start = self.start_of(suffix);
+        self.chars.truncate(start);
+    }
+
+    fn replace(&mut self, suffix: &str, with: &str) {
+        self.remove(suffix);
+        self.chars.extend(with.chars());
+    }
+
+    fn into_string(self) -> String {
+        self.chars.into_iter().collect()
+    }
+}
+
+fn is_danish_vowel(char: char) -> bool {
+    matches!(char, 'a' | 'e' | 'i' | 'o' | 'u' | 'y' | 'æ' | 'å' | 'ø')
+}
+
+const DANISH_MAIN_SUFFIXES: [&str; 32] = [
+    "hed", "ethed", "ered", "e", "erede", "ende", "erende", "ene", "erne", "ere", "en", "heden",
+    "eren", "er", "heder", "erer", "heds", "es", "endes", "erendes", "enes", "ernes", "eres",
+    "ens", "hedens", "erens", "ers", "ets", "erets", "et", "eret", "s",
+];
+
+fn danish_consonant_pair(word: &mut Word) {
+    if word
+        .longest_suffix_from(&["gd", "dt", "gt", "kt"], word.r1)
+        .is_some()
+    {
+        word.chars.pop();
+    }
+}
+
+pub fn danish(input: &str) -> String {
+    let mut word = Word::new(input, is_danish_vowel);
+    word.r1 = word.r1.max(3);
+
+    // Main suffix
+    match word.longest_suffix_from(&DANISH_MAIN_SUFFIXES, word.r1) {
+        Some("s") => {
+            let valid_s_ending = word
+                .char_before("s")
+                .is_some_and(|char| "abcdfghjklmnoprtvyzå".contains(char));
+            if valid_s_ending {
+                word.remove("s");
+            }
+        }
+        Some(suffix) => word.remove(suffix),
+        None => {}
+    }
+
+    danish_consonant_pair(&mut word);
+
+    // Other suffix
+    if word.ends_with("igst") {
+        word.remove("st");
+    }
+    match word.longest_suffix_from(&["ig", "lig", "elig", "els", "løst"], word.r1) {
+        Some("løst") => word.replace("løst", "løs"),
+        Some(suffix) => {
+            word.remove(suffix);
+            danish_consonant_pair(&mut word);
+        }
+        None => {}
+    }
+
+    // Undouble
+    if let [.., before, last] = word.chars[..] {
+        if word.len() > word.r1 && is_danish_vowel(last) == false && before == last {
+            word.chars.pop();
+        }
+    }
+
+    word.into_string()
+}
+
+fn is_english_vowel(char: char) -> bool {
+    matches!(char, 'a' | 'e' | 'i' | 'o' | 'u' | 'y')
+}
+
+const ENGLISH_EXCEPTIONS: [(&str, &str); 18] = [
+    ("skis", "ski"),
+    ("skies", "sky"),
+    ("dying", "die"),
+    ("lying", "lie"),
+    ("tying", "tie"),
+    ("idly", "idl"),
+    ("gently", "gentl"),
+    ("ugly", "ugli"),
+    ("early", "earli"),
+    ("only", "onli"),
+    ("singly", "singl"),
+    ("sky", "sky"),
+    ("news", "news"),
+    ("howe", "howe"),
+    ("atlas", "atlas"),
+    ("cosmos", "cosmos"),
+    ("bias", "bias"),
+    ("andes", "andes"),
+];
+
+const ENGLISH_INVARIANT_AFTER_1A: [&str; 8] = [
+    "inning", "outing", "canning", "herring", "earring", "proceed", "exceed", "succeed",
+];
+
+// A vowel followed by a non-vowel other than w, x or Y, and preceded by a non-vowel,
+// or a vowel at the beginning of the word followed by a non-vowel
+fn ends_in_short_syllable(chars: &[char]) -> bool {
+    match chars {
+        [first, second] => is_english_vowel(*first) && is_english_vowel(*second) == false,
+        [.., a, b, c] => {
+            is_english_vowel(*a) == false
+                && is_english_vowel(*b)
+                && is_english_vowel(*c) == false
+                && matches!(c, 'w' | 'x' | 'Y') == false
+        }
+        _ => false,
+    }
+}
+
+fn is_short_english_word(word: &Word) -> bool {
+    word.r1 >= word.len() && ends_in_short_syllable(&word.chars)
+}
+
+const ENGLISH_STEP_2: [(&str, &str); 24] = [
+    ("tional", "tion"),
+    ("enci", "ence"),
+    ("anci", "ance"),
+    ("abli", "able"),
+    ("entli", "ent"),
+    ("izer", "ize"),
+    ("ization", "ize"),
+    ("ational", "ate"),
+    ("ation", "ate"),
+    ("ator", "ate"),
+    ("alism", "al"),
+    ("aliti", "al"),
+    ("alli", "al"),
+    ("fulness", "ful"),
+    ("ousli", "ous"),
+    ("ousness", "ous"),
+    ("iveness", "ive"),
+    ("iviti", "ive"),
+    ("biliti", "ble"),
+    ("bli", "ble"),
+    ("ogi", "og"),
+    ("fulli", "ful"),
+    ("lessli", "less"),
+    ("li", ""),
+];
+
+const ENGLISH_STEP_3: [(&str, &str); 9] = [
+    ("tional", "tion"),
+    ("ational", "ate"),
+    ("alize", "al"),
+    ("icate", "ic"),
+    ("iciti", "ic"),
+    ("ical", "ic"),
+    ("ful", ""),
+    ("ness", ""),
+    ("ative", ""),
+];
+
+const ENGLISH_STEP_4: [&str; 18] = [
+    "al", "ance", "ence", "er", "ic", "able", "ible", "ant", "ement", "ment", "ent", "ism", "ate",
+    "iti", "ous", "ive", "ize", "ion",
+];
+
+// Finds the longest suffix of a replacement table
+fn longest_replacement(
+    word: &Word,
+    table: &[(&'static str, &'static str)],
+) -> Option<(&'static str, &'static str)> {
+    let suffixes: Vec<&str> = table.iter().map(|(suffix, _)| *suffix).collect();
+    let suffix = word.longest_suffix(&suffixes)?;
+    table.iter().find(|(s, _)| *s == suffix).copied()
+}
+
+fn english_regions(word: &mut Word) {
+    // A y at the start or after a vowel is a consonant
+    for i in 0..word.len() {
+        let after_vowel = i == 0 || is_english_vowel(word.chars[i - 1]);
+        if word.chars[i] == 'y' && after_vowel {
+            word.chars[i] = 'Y';
+        }
+    }
+    let r1 = ["gener", "commun", "arsen"]
+        .into_iter()
+        .find(|prefix| {
+            word.chars
+                .iter()
+                .copied()
+                .take(prefix.len())
+                .eq(prefix.chars())
+        })
+        .map_or_else(|| region_after(&word.chars, 0, is_english_vowel), str::len);
+    word.set_regions(r1, is_english_vowel);
+}
+
+fn english_step_1a(word: &mut Word) {
+    if let Some(suffix) = word.longest_suffix(&["'s'", "'s", "'"]) {
+        word.remove(suffix);
+    }
+
+    match word.longest_suffix(&["sses", "ied", "ies", "us", "ss", "s"]) {
+        Some("sses") => word.replace("sses", "ss"),
+        Some(suffix @ ("ied" | "ies")) => {
+            let replacement = if word.start_of(suffix) > 1 { "i" } else { "ie" };
+            word.replace(suffix, replacement);
+        }
+        Some("s") => {
+            let start = word.start_of("s");
+            if start >= 2 && word.chars[..start - 1].iter().any(|c| is_english_vowel(*c)) {
+                word.remove("s");
+            }
+        }
+        _ => {}
+    }
+}
+
+fn english_step_1b(word: &mut Word) {
+    match word.longest_suffix(&["eed", "eedly", "ed", "edly", "ing", "ingly"]) {
+        Some(suffix @ ("eed" | "eedly")) if word.in_r1(suffix) => word.replace(suffix, "ee"),
+        Some("eed" | "eedly") | None => {}
+        Some(suffix) => {
+            let start = word.start_of(suffix);
+            if word.chars[..start].iter().any(|c| is_english_vowel(*c)) == false {
+                return;
+            }
+            word.remove(suffix);
+            if word.ends_with("at") || word.ends_with("bl") || word.ends_with("iz") {
+                word.chars.push('e');
+            } else if ["bb", "dd", "ff", "gg", "mm", "nn", "pp", "rr", "tt"]
+                .iter()
+                .any(|double| word.ends_with(double))
+            {
+                word.chars.pop();
+            } else if is_short_english_word(word) {
+                word.chars.push('e');
+            }
+        }
+    }
+
+    // Step 1c
+    let len = word.len();
+    if len > 2
+        && matches!(word.chars[len - 1], 'y' | 'Y')
+        && is_english_vowel(word.chars[len - 2]) == false
+    {
+        word.chars[len - 1] = 'i';
+    }
+}
+
+fn english_steps_2_to_5(word: &mut Word) {
+    if let Some((suffix, replacement)) = longest_replacement(word, &ENGLISH_STEP_2) {
+        let allowed = match suffix {
+            "ogi" => word.char_before(suffix) == Some('l'),
+            "li" => word
+                .char_before(suffix)
+                .is_some_and(|c| "cdeghkmnrt".contains(c)),
+            _ => true,
+        };
+        if word.in_r1(suffix) && allowed {
+            word.replace(suffix, replacement);
+        }
+    }
+
+    if let Some((suffix, replacement)) = longest_replacement(word, &ENGLISH_STEP_3) {
+        let allowed = suffix != "ative" || word.in_r2(suffix);
+        if word.in_r1(suffix) && allowed {
+            word.replace(suffix, replacement);
+        }
+    }
+
+    if let Some(suffix) = word.longest_suffix(&ENGLISH_STEP_4) {
+        let allowed = suffix != "ion" || matches!(word.char_before(suffix), Some('s' | 't'));
+        if word.in_r2(suffix) && allowed {
+            word.remove(suffix);
+        }
+    }
+
+    // Step 5
+    if word.ends_with("e") {
+        let start = word.start_of("e");
+        let after_short_syllable = ends_in_short_syllable(&word.chars[..start]);
+        if word.in_r2("e") || (word.in_r1("e") && after_short_syllable == false) {
+            word.remove("e");
+        }
+    } else if word.ends_with("l") && word.in_r2("l") && word.char_before("l") == Some('l') {
+        word.remove("l");
+    }
+}
+
+pub fn english(input: &str) -> String {
+    if input.chars().count() <= 2 {
+        return input.to_string();
+    }
+    if let Some((_, stem)) = ENGLISH_EXCEPTIONS.iter().find(|(word, _)| *word == input) {
+        return (*stem).to_string();
+    }
+
+    let mut word = Word::new(input.trim_start_matches('\''), is_english_vowel);
+    english_regions(&mut word);
+
+    english_step_1a(&mut word);
+    let step_1a: String = word.chars.iter().collect();
+    if ENGLISH_INVARIANT_AFTER_1A.contains(&step_1a.as_str()) {
+        return step_1a;
+    }
+    english_step_1b(&mut word);
+    english_steps_2_to_5(&mut word);
+
+    word.into_string().replace('Y', "y")
+}
+
+fn is_german_vowel(char: char) -> bool {
+    matches!(char, 'a' | 'e' | 'i' | 'o' | 'u' | 'y' | 'ä' | 'ö' | 'ü')
+}
+
+fn german_steps_1_and_2(word: &mut Word) {
+    match word.longest_suffix(&["em", "ern", "er", "e", "en", "es", "s"]) {
+        Some(suffix @ ("em" | "ern" | "er")) if word.in_r1(suffix) => word.remove(suffix),
+        Some(suffix @ ("e" | "en" | "es")) if word.in_r1(suffix) => {
+            word.remove(suffix);
+            if word.ends_with("niss") {
+                word.chars.pop();
+            }
+        }
+        Some("s") if word.in_r1("s") => {
+            let valid_s_ending = word
+                .char_before("s")
+                .is_some_and(|c| "bdfghklmnrt".contains(c));
+            if valid_s_ending {
+                word.remove("s");
+            }
+        }
+        _ => {}
+    }
+
+    match word.longest_suffix(&["en", "er", "est", "st"]) {
+        Some("st") => {
+            let valid_st_ending = word
+                .char_before("st")
+                .is_some_and(|c| "bdfghklmnt".contains(c));
+            // The ending itself has to be preceded by atleast 3 letters
+            if word.in_r1("st") && valid_st_ending && word.start_of("st") > 3 {
+                word.remove("st");
+            }
+        }
+        Some(suffix) if word.in_r1(suffix) => word.remove(suffix),
+        _ => {}
+    }
+}
+
+fn german_step_3(word: &mut Word) {
+    match word.longest_suffix(&["end", "ung", "ig", "ik", "isch", "lich", "heit", "keit"]) {
+        Some(suffix @ ("end" | "ung")) if word.in_r2(suffix) => {
+            word.remove(suffix);
+            if word.ends_with("ig") && word.in_r2("ig") && word.ends_with_before("ig", "e") == false
+            {
+                word.remove("ig");
+            }
+        }
+        Some(suffix @ ("ig" | "ik" | "isch"))
+            if word.in_r2(suffix) && word.char_before(suffix) != Some('e') =>
+        {
+            word.remove(suffix);
+        }
+        Some(suffix @ ("lich" | "heit")) if word.in_r2(suffix) => {
+            word.remove(suffix);
+            if let Some(before) = word.longest_suffix(&["er", "en"]) {
+                if word.in_r1(before) {
+                    word.remove(before);
+                }
+            }
+        }
+        Some("keit") if word.in_r2("keit") => {
+            word.remove("keit");
+            if let Some(before) = word.longest_suffix(&["lich", "ig"]) {
+                if word.in_r2(before) {
+                    word.remove(before);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+pub fn german(input: &str) -> String {
+    let mut chars: Vec<char> = input.replace('ß', "ss").chars().collect();
+    // A u or y between vowels is a consonant
+    for i in 1..chars.len().saturating_sub(1) {
+        if is_german_vowel(chars[i - 1]) && is_german_vowel(chars[i + 1]) {
+            chars[i] = match chars[i] {
+                'u' => 'U',
+                'y' => 'Y',
+                other => other,
+            };
+        }
+    }
+
+    let mut word = Word::new(&chars.iter().collect::<String>(), is_german_vowel);
+    word.r1 = word.r1.max(3);
+
+    german_steps_1_and_2(&mut word);
+    german_step_3(&mut word);
+
+    word.chars
+        .into_iter()
+        .map(|char| match char {
+            'U' | 'ü' => 'u',
+            'Y' => 'y',
+            'ä' => 'a',
+            'ö' => 'o',
+            other => other,
+        })
+        .collect()
+}
+
+#[test]
+fn test_stemmers() {
+    for (input, expected) in [
+        ("kunsten", "kunst"),
+        ("kunst", "kunst"),
+        ("huset", "hus"),
+        ("bilerne", "bil"),
+        ("hestene", "hest"),
+        ("indtryk", "indtryk"),
+        ("drenge", "dreng"),
+    ] {
+        assert_eq!(danish(input), expected, "Danish {input}");
+    }
+
+    for (input, expected) in [
+        ("shirts", "shirt"),
+        ("shirt", "shirt"),
+        ("running", "run"),
+        ("caresses", "caress"),
+        ("ponies", "poni"),
+        ("ties", "tie"),
+        ("hopping", "hop"),
+        ("hoped", "hope"),
+        ("generously", "generous"),
+        ("happiness", "happi"),
+        ("consolation", "consol"),
+        ("knightly", "knight"),
+        ("skies", "sky"),
+        ("cry", "cri"),
+        ("posters", "poster"),
+    ] {
+        assert_eq!(english(input), expected, "English {input}");
+    }
+
+    for (input, expected) in [
+        ("häuser", "haus"),
+        ("katzen", "katz"),
+        ("laufen", "lauf"),
+        ("kunst", "kunst"),
+        ("bilder", "bild"),
+        ("freundlichkeit", "freundlich"),
+    ] {
+        assert_eq!(german(input), expected, "German {input}");
+    }
+}
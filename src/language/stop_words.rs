@@ -0,0 +1,35 @@
+// Based on the Snowball stop word lists, without the words that are also common in product names
+
+pub const DANISH: &[&str] = &[
+    "og", "i", "jeg", "det", "at", "en", "den", "til", "er", "som", "på", "de", "med", "han", "af",
+    "for", "ikke", "der", "var", "mig", "sig", "men", "et", "har", "om", "vi", "min", "havde",
+    "ham", "hun", "nu", "over", "da", "fra", "du", "ud", "sin", "dem", "os", "op", "man", "hans",
+    "hvor", "eller", "hvad", "skal", "selv", "her", "alle", "vil", "blev", "kunne", "ind", "når",
+    "være", "dog", "noget", "ville", "jo", "deres", "efter", "ned", "skulle", "denne", "end",
+    "dette", "mit", "også", "under", "have", "dig", "anden", "hende", "mine", "alt", "meget",
+    "sit", "sine", "vor", "mod", "disse", "hvis", "din", "nogle", "hos", "blive", "mange", "ad",
+    "bliver", "hendes", "været", "thi", "jer", "sådan",
+];
+
+pub const ENGLISH: &[&str] = &[
+    "i", "me", "my", "myself", "we", "our", "ours", "you", "your", "yours", "he", "him", "his",
+    "she", "her", "hers", "it", "its", "they", "them", "their", "what", "which", "who", "whom",
+    "this", "that", "these", "those", "am", "is", "are", "was", "were", "be", "been", "being",
+    "have", "has", "had", "having", "do", "does", "did", "doing", "would", "should", "could", "a",
+    "an", "the", "and", "but", "if", "or", "because", "as", "until", "while", "of", "at", "by",
+    "for", "with", "about", "against", "between", "into", "through", "during", "before", "after",
+    "above", "below", "to", "from", "up", "down", "in", "out", "on", "off", "over", "under",
+    "again", "further", "then", "once", "here", "there", "when", "where", "why", "how", "all",
+    "any", "both", "each", "few", "more", "most", "other", "some", "such", "no", "nor", "not",
+    "only", "own", "same", "so", "than", "too", "very",
+];
+
+pub const GERMAN: &[&str] = &[
+    "aber", "alle", "als", "also", "am", "an", "auch", "auf", "aus", "bei", "bin", "bis", "bist",
+    "da", "damit", "dann", "das", "dass", "dem", "den", "denn", "der", "des", "dich", "die", "dir",
+    "doch", "du", "durch", "ein", "eine", "einem", "einen", "einer", "eines", "er", "es", "euch",
+    "für", "hat", "hatte", "ich", "ihr", "im", "in", "ist", "ja", "kein", "man", "mich", "mir",
+    "mit", "nach", "nicht", "noch", "nun", "nur", "ob", "oder", "ohne", "sehr", "sein", "sich",
+    "sie", "sind", "so", "um", "und", "uns", "unter", "vom", "von", "vor", "war", "waren", "was",
+    "weil", "wenn", "wie", "wir", "wird", "zu", "zum", "zur", "über",
+];
@@ -0,0 +1,240 @@
+use crate::{
+    config::finite_f32,
+    normalize::TextNormalization,
+    serialize::{Deserializable, Serializable},
+};
+
+/// One line of a synonym dictionary.
+///
+/// Written like `sofa, couch` for terms that mean the same, and `tee, tshirt => t-shirt`
+/// for terms that should only be searched as something else, like common misspellings.
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub enum SynonymRule {
+    TwoWay(Vec<String>),
+    OneWay { from: Vec<String>, to: Vec<String> },
+}
+
+fn terms(input: &str) -> Vec<String> {
+    input
+        .split(',')
+        .map(str::trim)
+        .filter(|term| term.is_empty() == false)
+        .map(str::to_string)
+        .collect()
+}
+
+impl SynonymRule {
+    pub fn parse(line: &str) -> Option<SynonymRule> {
+        let rule = match line.split_once("=>") {
+            Some((from, to)) => SynonymRule::OneWay {
+                from: terms(from),
+                to: terms(to),
+            },
+            None => SynonymRule::TwoWay(terms(line)),
+        };
+        match &rule {
+            SynonymRule::TwoWay(terms) if terms.len() < 2 => None,
+            SynonymRule::OneWay { from, to } if from.is_empty() || to.is_empty() => None,
+            _ => Some(rule),
+        }
+    }
+
+    // Every term the rule matches in a query, with the terms it's replaced by
+    fn replacements(&self) -> impl Iterator<Item = (&str, impl Iterator<Item = &str>)> {
+        let (from, to) = match self {
+            SynonymRule::TwoWay(terms) => (terms, terms),
+            SynonymRule::OneWay { from, to } => (from, to),
+        };
+        from.iter().map(move |term| {
+            let others = to
+                .iter()
+                .filter(move |other| *other != term)
+                .map(String::as_str);
+            (term.as_str(), others)
+        })
+    }
+}
+
+impl TryFrom<String> for SynonymRule {
+    type Error = String;
+
+    fn try_from(line: String) -> Result<Self, Self::Error> {
+        SynonymRule::parse(&line).ok_or_else(|| format!("Invalid synonym rule: {line}"))
+    }
+}
+
+/// A synonym dictionary supplied at index time, used to rewrite queries into alternatives.
+///
+/// The alternatives are searched next to the query itself, with their results down-weighted.
+#[derive(serde::Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Synonyms {
+    pub rules: Vec<SynonymRule>,
+    // How much a result found through a synonym counts compared to the query as typed
+    #[serde(deserialize_with = "finite_f32")]
+    pub weight: f32,
+    pub max_alternatives: usize,
+}
+
+// The weight is never NaN, it's rejected when the dictionary is read
+impl Eq for Synonyms {}
+
+impl Default for Synonyms {
+    fn default() -> Self {
+        Synonyms {
+            rules: Vec::new(),
+            weight: 0.8,
+            max_alternatives: 8,
+        }
+    }
+}
+
+impl Synonyms {
+    /// Parses a dictionary with a rule per line, skipping empty lines and `#` comments
+    pub fn parse(input: &str) -> Option<Vec<SynonymRule>> {
+        input
+            .lines()
+            .map(str::trim)
+            .filter(|line| line.is_empty() == false && line.starts_with('#') == false)
+            .map(SynonymRule::parse)
+            .collect()
+    }
+
+    /// The query rewritten with every synonym that matches a run of its words.
+    ///
+    /// The terms are normalised like the query already is, so they match regardless of case or accents.
+    pub fn expand(&self, query: &str, normalization: &TextNormalization) -> Vec<String> {
+        let words: Vec<&str> = query.split_whitespace().collect();
+        let mut alternatives: Vec<String> = Vec::new();
+
+        for rule in &self.rules {
+            for (term, replacements) in rule.replacements() {
+                let term = normalization.normalize(term);
+                let term_words: Vec<&str> = term.split_whitespace().collect();
+                if term_words.is_empty() || term_words.len() > words.len() {
+                    continue;
+                }
+                let Some(start) = words
+                    .windows(term_words.len())
+                    .position(|window| window == term_words.as_slice())
+                else {
+                    continue;
+                };
+
+                for replacement in replacements {
+                    let replacement = normalization.normalize(replacement);
+                    let mut rewritten = words[..start].to_vec();
+                    rewritten.push(&replacement);
+                    rewritten.extend_from_slice(&words[start + term_words.len()..]);
+                    let rewritten = rewritten.join(" ");
+
+                    if alternatives.len() >= self.max_alternatives {
+                        return alternatives;
+                    }
+                    if rewritten != query && alternatives.contains(&rewritten) == false {
+                        alternatives.push(rewritten);
+                    }
+                }
+            }
+        }
+        alternatives
+    }
+}
+
+impl Serializable for SynonymRule {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        match self {
+            SynonymRule::TwoWay(terms) => {
+                0u8.serialize(output);
+                terms.serialize(output);
+            }
+            SynonymRule::OneWay { from, to } => {
+                1u8.serialize(output);
+                from.serialize(output);
+                to.serialize(output);
+            }
+        }
+    }
+}
+
+impl Deserializable for SynonymRule {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (input, id) = u8::deserialize(input)?;
+        match id {
+            0 => {
+                let (input, terms) = Vec::deserialize(input)?;
+                Some((input, SynonymRule::TwoWay(terms)))
+            }
+            1 => {
+                let (input, from) = Vec::deserialize(input)?;
+                let (input, to) = Vec::deserialize(input)?;
+                Some((input, SynonymRule::OneWay { from, to }))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Serializable for Synonyms {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        (&self.rules).serialize(output);
+        self.weight.serialize(output);
+        self.max_alternatives.serialize(output);
+    }
+}
+
+impl Deserializable for Synonyms {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (input, rules) = Vec::deserialize(input)?;
+        let (input, weight) = f32::deserialize(input).filter(|(_, w)| w.is_finite())?;
+        let (input, max_alternatives) = usize::deserialize(input)?;
+        Some((
+            input,
+            Synonyms {
+                rules,
+                weight,
+                max_alternatives,
+            },
+        ))
+    }
+}
+
+#[test]
+fn test_synonyms() {
+    let synonyms = Synonyms {
+        rules: Synonyms::parse(
+            "# Furniture\n\
+             sofa, couch\n\
+             \n\
+             Tee, tshirt => t-shirt",
+        )
+        .unwrap(),
+        ..Synonyms::default()
+    };
+    let normalization = TextNormalization::default();
+
+    assert_eq!(
+        synonyms.expand("blue sofa", &normalization),
+        vec!["blue couch"]
+    );
+    assert_eq!(synonyms.expand("couch", &normalization), vec!["sofa"]);
+    assert_eq!(
+        synonyms.expand("tee red", &normalization),
+        vec!["t-shirt red"]
+    );
+    // One way rules don't go back
+    assert!(synonyms.expand("t-shirt", &normalization).is_empty());
+    assert!(synonyms.expand("chair", &normalization).is_empty());
+
+    assert_eq!(SynonymRule::parse("sofa"), None);
+    assert_eq!(SynonymRule::parse("=> couch"), None);
+
+    let mut bytes = Vec::new();
+    synonyms.serialize(&mut |byte| bytes.push(byte));
+    let (_, deserialized) = Synonyms::deserialize(&bytes).unwrap();
+    assert_eq!(deserialized, synonyms);
+    // The weight has to be finite, for the dictionary to equal itself
+    assert!(serde_json::from_str::<Synonyms>(r#"{"weight": 0.5}"#).is_ok());
+    assert!(serde_json::from_str::<Synonyms>(r#"{"weight": 1e39}"#).is_err());
+}
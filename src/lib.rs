@@ -6,33 +6,538 @@
 #![allow(clippy::missing_errors_doc)]
 #![allow(clippy::module_name_repetitions)]
 
-use std::sync::{Arc, Mutex};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    sync::{Arc, Mutex},
+};
 
-use classic_indexes::ClassicIndexes;
+use classic_indexes::{ClassicIndexes, COLLECTION_DEFAULT};
 use colosseum::sync::Arena;
-use data::{Product, SuperAlloc};
-use ngram::{GramIndex, GramNode};
-use serialize::{deserialize_all, serialize_all};
+use data::{Document, Product, ProductContainer, SuperAlloc, Variant};
+use language::AnalysisConfig;
+use ngram::{
+    GramIndex, GramNode, GramSource, LazyGramIndex, ResultRanker, SearchStats, ShardedGramIndex,
+    TypoTolerance,
+};
+use serialize::{
+    deserialize_all, deserialize_documents, serialize_all, serialize_documents, serialize_sharded,
+    IndexSections, Section, ShardedBlobs,
+};
 use wasm_bindgen::prelude::*;
 
 pub mod classic_indexes;
+pub mod config;
 pub mod data;
+pub mod federated;
 pub mod js_interactable;
+pub mod language;
+pub mod merchandising;
 pub mod ngram;
+pub mod normalize;
 pub mod preprocessor;
+pub mod query;
 mod serde_array;
 pub mod serialize;
+#[cfg(test)]
+mod testing;
 
-const NGRAM_INDEX_SIZE: usize = 5;
+pub const NGRAM_INDEX_SIZE: usize = 5;
 type Index = GramIndex<'static, char, Product<'static>, NGRAM_INDEX_SIZE>;
+type LazyIndex = LazyGramIndex<'static, char, NGRAM_INDEX_SIZE>;
+type ShardedIndex = ShardedGramIndex<'static, char, NGRAM_INDEX_SIZE>;
+type DocumentIndex = GramIndex<'static, char, Document, NGRAM_INDEX_SIZE>;
+
+/// A query after parsing, with the products it found ranked by confidence
+pub struct RankedQuery<'r> {
+    // The normalised text of the query, which merchandising rules are matched against
+    pub normalized: String,
+    pub results: Vec<(&'r Product<'static>, f32)>,
+    // Shards the query needed that weren't loaded, so the results may be partial
+    pub missing_shards: Vec<usize>,
+    // The exact parts of the query, with the phrases and excluded terms analysed like the indexed text
+    pub exact: ParsedQuery,
+    // The confidence of a product matching the whole query, see `max_confidence`
+    pub max_confidence: f32,
+}
+
+/// How the results of a search are filtered and ordered
+pub struct SearchOptions<'s> {
+    pub categories: &'s CategoryHandler,
+    pub tags: &'s TagHandler,
+    pub features: &'s [FeatureFilter],
+    pub order: Option<&'s str>,
+    // Prices are in it, or in the index's default currency
+    pub currency: Option<&'s str>,
+    // The handle or id of the collection the results are in
+    pub collection: Option<&'s str>,
+    pub tolerance: &'s TypoTolerance,
+}
+
+/// The products a search found, in the order they're shown
+pub struct SearchResults {
+    // Serialization ids
+    pub ids: Vec<usize>,
+    // Pinned products the query didn't find have none
+    pub confidences: ahash::AHashMap<usize, f32>,
+    // The variants that matched the category filters, for the products that have any
+    pub matched_variants: ahash::AHashMap<usize, Vec<usize>>,
+    // Shards the query needed that weren't loaded, so the results may be partial
+    pub missing_shards: Vec<usize>,
+}
+
+// The index can either be fully deserialized, searched in place in the blob it was loaded from, or split into shards loaded on demand
+pub enum LoadedIndex<const N: usize = NGRAM_INDEX_SIZE> {
+    Eager(GramIndex<'static, char, Product<'static>, N>),
+    Lazy(LazyGramIndex<'static, char, N>),
+    Sharded(ShardedGramIndex<'static, char, N>),
+}
+
+impl<const N: usize> LoadedIndex<N> {
+    pub fn search<I: Iterator<Item = char>>(&self, input: I) -> Vec<(&Product<'static>, f32)> {
+        match self {
+            LoadedIndex::Eager(index) => index.search(input),
+            LoadedIndex::Lazy(index) => index.search(input),
+            LoadedIndex::Sharded(index) => index.search(input),
+        }
+    }
+
+    pub fn search_with<I: Iterator<Item = (char, u8)>>(
+        &self,
+        input: I,
+        tolerance: &TypoTolerance,
+        stats: &SearchStats,
+    ) -> Vec<(&Product<'static>, f32)> {
+        match self {
+            LoadedIndex::Eager(index) => index.search_with(input, tolerance, stats),
+            LoadedIndex::Lazy(index) => index.search_with(input, tolerance, stats),
+            LoadedIndex::Sharded(index) => index.search_with(input, tolerance, stats),
+        }
+    }
+
+    /// Searches every query, with the confidences of each one's results multiplied by its weight
+    pub fn search_weighted(
+        &self,
+        queries: &[(String, f32)],
+        tolerance: &TypoTolerance,
+        stats: &SearchStats,
+    ) -> Vec<(&Product<'static>, f32)> {
+        let mut ranker = ResultRanker::new();
+        for (query, weight) in queries {
+            let query = tolerance.apply(query, N);
+            for (product, confidence) in self.search_with(query.into_iter(), tolerance, stats) {
+                ranker.add(product, confidence * weight);
+            }
+        }
+        ranker.export_data_by_confidence()
+    }
+
+    /// Keeps the results that contain every phrase exactly, and none of the excluded terms.
+    ///
+    /// Common grams are pruned from the index, so phrases are also looked for in the text the product was indexed
+    /// with. Grams still find the phrases in the parts of texts that weren't stored
+    pub fn retain_exact(
+        &self,
+        results: &mut Vec<(&Product<'static>, f32)>,
+        phrases: &[String],
+        excluded: &[String],
+    ) {
+        let config = &self.product_container().config;
+        retain_phrases(
+            results,
+            phrases,
+            excluded,
+            &config.analysis,
+            |variant| {
+                let matches = self.exact_matches(variant.chars()).into_iter();
+                matches.map(|product| product.serialization_id).collect()
+            },
+            |product| product.serialization_id,
+            |product| preprocessor::index_feed(product, config).grams.collect(),
+        );
+    }
+
+    pub fn exact_matches<I: Iterator<Item = char>>(&self, input: I) -> Vec<&Product<'static>> {
+        match self {
+            LoadedIndex::Eager(index) => index.exact_matches(input),
+            LoadedIndex::Lazy(index) => index.exact_matches(input),
+            LoadedIndex::Sharded(index) => index.exact_matches(input),
+        }
+    }
+
+    // The shards a query needs that aren't loaded, always empty for unsharded indexes
+    pub fn missing_shards<I: Iterator<Item = char>>(&self, input: I) -> Vec<usize> {
+        match self {
+            LoadedIndex::Eager(_) | LoadedIndex::Lazy(_) => Vec::new(),
+            LoadedIndex::Sharded(index) => index.missing_shards(input),
+        }
+    }
+
+    /// Parses, normalises and expands the query, and ranks the products that match it.
+    /// The results still have to be filtered by category, tag and feature
+    pub fn rank(
+        &self,
+        input: &str,
+        classic: &ClassicIndexes<'_>,
+        tolerance: &TypoTolerance,
+        stats: &SearchStats,
+    ) -> RankedQuery<'_> {
+        // Quoted phrases, exclusions and field restrictions are taken out before the fuzzy search
+        let parsed = ParsedQuery::parse(input);
+
+        // Queries are normalised and analysed the same way the indexed text was
+        let config = &self.product_container().config;
+        let normalized = config.normalization.normalize(&parsed.text);
+        // The exact parts are analysed when they're matched, in each language of the fields
+        let exact = |text: &String| config.normalization.normalize(text);
+        let exact = ParsedQuery {
+            phrases: parsed.phrases.iter().map(exact).collect(),
+            excluded: parsed.excluded.iter().map(exact).collect(),
+            ..parsed
+        };
+        let queries = expand_query(config, &normalized);
+        let max_confidence = if exact.text.is_empty() {
+            1.0
+        } else {
+            max_confidence(&queries, tolerance, N)
+        };
+
+        // Searching a sharded index before all its shards are loaded only gives partial results
+        let mut missing_shards: Vec<usize> = queries
+            .iter()
+            .map(|(query, _)| query.clone())
+            .chain(
+                exact
+                    .phrases
+                    .iter()
+                    .chain(&exact.excluded)
+                    .flat_map(|query| config.analysis.analyze_query_variants(query)),
+            )
+            .flat_map(|query| self.missing_shards(query.chars()))
+            .collect();
+        missing_shards.sort_unstable();
+        missing_shards.dedup();
+
+        let mut results = if exact.text.is_empty() {
+            // Without any text, the exact parts of the query find the products on their own
+            let products = &self.product_container().products;
+            if exact.phrases.is_empty() && exact.fields.is_empty() {
+                Vec::new()
+            } else {
+                products.iter().map(|product| (product, 1.0)).collect()
+            }
+        } else {
+            self.search_weighted(&queries, tolerance, stats)
+        };
+
+        if exact.is_plain() == false {
+            self.retain_exact(&mut results, &exact.phrases, &exact.excluded);
+            results
+                .retain(|(product, _)| exact.fields_match(product, classic, &config.normalization));
+        }
+
+        RankedQuery {
+            normalized,
+            results,
+            missing_shards,
+            exact,
+            max_confidence,
+        }
+    }
+
+    /// If the product passes the exact parts of a ranked query, like its results do
+    pub fn matches_exact(
+        &self,
+        product: &Product<'_>,
+        exact: &ParsedQuery,
+        classic: &ClassicIndexes<'_>,
+    ) -> bool {
+        if exact.is_plain() {
+            return true;
+        }
+        // The index's own product, which the results borrow
+        let product = &self.product_container().products[product.serialization_id];
+        let mut results = vec![(product, 1.0)];
+        self.retain_exact(&mut results, &exact.phrases, &exact.excluded);
+        let config = &self.product_container().config;
+        results.is_empty() == false && exact.fields_match(product, classic, &config.normalization)
+    }
+
+    /// Ranks the products matching the query, and filters, orders and merchandises them the way they're shown.
+    /// `None` if the order or the collection isn't in the index
+    pub fn search_filtered(
+        &self,
+        input: &str,
+        classic: &ClassicIndexes<'static>,
+        options: &SearchOptions<'_>,
+        stats: &SearchStats,
+    ) -> Option<SearchResults> {
+        let RankedQuery {
+            normalized,
+            mut results,
+            missing_shards,
+            exact,
+            ..
+        } = self.rank(input, classic, options.tolerance, stats);
+
+        // A collection is given by its handle or id, and an empty query lists all of it, for a collection page
+        let collection = match options.collection {
+            Some(collection) => Some(classic.collections.find(collection)?.1),
+            None => None,
+        };
+        if let Some(collection) = collection {
+            if normalized.trim().is_empty() {
+                results = collection.products().map(|p| (p, 1.0)).collect();
+            } else {
+                results.retain(|(p, _)| collection.contains(p));
+            }
+        }
+
+        let container = self.product_container();
+        let config = &container.config;
+        let SearchOptions {
+            categories, tags, ..
+        } = options;
+        let rules =
+            config.rules.matching(
+                &normalized,
+                &config.normalization,
+                |context| match context {
+                    RuleContext::Tag(name) => tags.is_active(name),
+                    RuleContext::Category { category, option } => {
+                        categories.is_active(category, option)
+                    }
+                },
+            );
+        rules.boost(&mut results, classic);
+
+        let confidences = results
+            .iter()
+            .map(|(p, confidence)| (p.serialization_id, *confidence))
+            .collect();
+        let (results, matched_variants) =
+            filter_categories(results, categories, tags, &container.variants);
+
+        // Prices are filtered and ordered in the asked currency, or the index's default one
+        let currency = options
+            .currency
+            .unwrap_or_else(|| container.prices.default_currency());
+        let mut ids = filter_features(results, options.features, container, currency);
+
+        match (options.order, collection) {
+            (Some(order), Some(collection)) if order == COLLECTION_DEFAULT => {
+                let order = classic
+                    .order
+                    .collection_order(collection, container.products.len());
+                ids.sort_by_cached_key(|v| order[*v]);
+            }
+            (Some(order), _) if order != COLLECTION_DEFAULT => {
+                let order = classic.order.get_orders_in(order, currency)?;
+                ids.sort_by_cached_key(|v| order[*v]);
+            }
+            // Outside of a collection, its own order is the order of relevance
+            _ => {}
+        }
+
+        // Merchandising goes last, so pins keep their positions whatever the order.
+        // Pinned products the query didn't find still have to pass every filter of the results
+        if rules.is_empty() == false {
+            rules.arrange(&mut ids, &container.products, classic, |p| {
+                categories.is_valid(p, &container.variants)
+                    && tags.is_valid(p)
+                    && collection.is_none_or(|collection| collection.contains(p))
+                    && options
+                        .features
+                        .iter()
+                        .all(|filter| filter.passes(p, container, currency))
+                    && self.matches_exact(p, &exact, classic)
+            });
+        }
+
+        Some(SearchResults {
+            ids,
+            confidences,
+            matched_variants,
+            missing_shards,
+        })
+    }
+
+    pub fn product_container(&self) -> &'static ProductContainer<'static> {
+        match self {
+            LoadedIndex::Eager(index) => index.container,
+            LoadedIndex::Lazy(index) => index.product_container,
+            LoadedIndex::Sharded(index) => index.product_container,
+        }
+    }
+}
+
+// Synonyms are searched next to the normalised query, but count for less.
+// Each is analysed in every language the fields were indexed in, which share its weight
+fn expand_query(config: &IndexConfig, normalized: &str) -> Vec<(String, f32)> {
+    let alternatives = std::iter::once((normalized.to_string(), 1.0)).chain(
+        config
+            .synonyms
+            .expand(normalized, &config.normalization)
+            .into_iter()
+            .map(|alternative| (alternative, config.synonyms.weight)),
+    );
+    let languages = config.analysis.query_languages();
+    let mut queries: Vec<(String, f32)> = Vec::new();
+    for (query, weight) in alternatives {
+        for language in &languages {
+            let analyzed = config.analysis.analyze_query(&query, *language);
+            #[allow(clippy::cast_precision_loss)]
+            let weight = weight / languages.len() as f32;
+            // Languages that analyse the query the same search it once
+            match queries.iter_mut().find(|(query, _)| *query == analyzed) {
+                Some((_, total)) => *total += weight,
+                None => queries.push((analyzed.into_owned(), weight)),
+            }
+        }
+    }
+    queries
+}
+
+// The confidence of a result matching every gram window of the queries exactly, the most any result can have.
+// Results of different indexes are compared relative to it
+fn max_confidence(queries: &[(String, f32)], tolerance: &TypoTolerance, n: usize) -> f32 {
+    #[allow(clippy::cast_precision_loss)]
+    queries
+        .iter()
+        .map(|(query, weight)| tolerance.apply(query, n).len() as f32 * weight)
+        .sum()
+}
+
+// Keeps the results that contain every phrase exactly and none of the excluded terms, for products and documents.
+// `matches` gives the serialization ids of what the grams find a text in, `text` what a result was indexed with
+fn retain_phrases<T>(
+    results: &mut Vec<(&T, f32)>,
+    phrases: &[String],
+    excluded: &[String],
+    analysis: &AnalysisConfig,
+    matches: impl Fn(&str) -> ahash::AHashSet<usize>,
+    serialization_id: impl Fn(&T) -> usize,
+    text: impl Fn(&T) -> String,
+) {
+    // A phrase is in a result if it is analysed like any of the result's fields was
+    let variants = |query: &String| -> Vec<(ahash::AHashSet<usize>, String)> {
+        let variants = analysis.analyze_query_variants(query).into_iter();
+        variants
+            .map(|variant| (matches(&variant), variant))
+            .collect()
+    };
+    let required: Vec<_> = phrases.iter().map(variants).collect();
+    let excluded: Vec<_> = excluded.iter().map(variants).collect();
+    results.retain(|(result, _)| {
+        let indexed = std::cell::OnceCell::new();
+        let contains = |variants: &Vec<(ahash::AHashSet<usize>, String)>| {
+            variants.iter().any(|(ids, variant)| {
+                ids.contains(&serialization_id(result))
+                    || indexed
+                        .get_or_init(|| text(result))
+                        .contains(variant.as_str())
+            })
+        };
+        required.iter().all(contains) && excluded.iter().any(contains) == false
+    });
+}
+
+/// The documents that match a query, best first
+pub struct RankedDocuments<'r> {
+    pub results: Vec<(&'r Document, f32)>,
+    // The confidence of a document matching the whole query, to compare results across indexes
+    pub max_confidence: f32,
+}
+
+/// Ranks the documents that match a query, which is parsed, normalised and expanded like a product query.
+/// Phrases, exclusions and `field:value` restrictions work like they do for products, with fields looked up
+/// by name in each document's schema. Filters, orders and merchandising rules only exist for products
+pub fn rank_documents<'i, const N: usize>(
+    index: &'i GramIndex<'static, char, Document, N>,
+    input: &str,
+    tolerance: &TypoTolerance,
+    stats: &SearchStats,
+) -> RankedDocuments<'i> {
+    let parsed = ParsedQuery::parse(input);
+    let container = index.container;
+    let config = &container.config;
+    let exact = |text: &String| config.normalization.normalize(text);
+    let exact = ParsedQuery {
+        phrases: parsed.phrases.iter().map(exact).collect(),
+        excluded: parsed.excluded.iter().map(exact).collect(),
+        ..parsed
+    };
+
+    let queries = expand_query(config, &config.normalization.normalize(&exact.text));
+    let (mut results, max_confidence) = if exact.text.is_empty() {
+        // Without any text, the exact parts of the query find the documents on their own
+        let documents = if exact.phrases.is_empty() && exact.fields.is_empty() {
+            Vec::new()
+        } else {
+            container.documents.iter().map(|d| (d, 1.0)).collect()
+        };
+        (documents, 1.0)
+    } else {
+        let mut ranker = ResultRanker::new();
+        for (query, weight) in &queries {
+            let query = tolerance.apply(query, N);
+            for (document, confidence) in index.search_with(query.into_iter(), tolerance, stats) {
+                ranker.add(document, confidence * weight);
+            }
+        }
+        (
+            ranker.export_data_by_confidence(),
+            max_confidence(&queries, tolerance, N),
+        )
+    };
+
+    if exact.is_plain() == false {
+        retain_phrases(
+            &mut results,
+            &exact.phrases,
+            &exact.excluded,
+            &config.analysis,
+            |variant| {
+                let matches = index.exact_matches(variant.chars()).into_iter();
+                matches.map(|document| document.serialization_id).collect()
+            },
+            |document| document.serialization_id,
+            |document| {
+                let schema = container.schema(document);
+                preprocessor::document_feed(document, schema, config)
+                    .grams
+                    .collect()
+            },
+        );
+        results.retain(|(document, _)| {
+            exact.document_fields_match(document, container, &config.normalization)
+        });
+    }
+
+    RankedDocuments {
+        results,
+        max_confidence,
+    }
+}
 
 lazy_static::lazy_static! {
-    static ref SHARED_INDEX: Mutex<Option<Arc<Index>>> = Mutex::new(None);
+    static ref SHARED_INDEX: Mutex<Option<Arc<LoadedIndex>>> = Mutex::new(None);
     static ref SHARED_CLASSIC_INDEX: Mutex<Option<Arc<ClassicIndexes<'static>>>> = Mutex::new(None);
+    // The blob the current index was loaded from, if it was loaded so patches can be applied to it
+    static ref SHARED_BLOB: Mutex<Option<Cow<'static, [u8]>>> = Mutex::new(None);
+    // Indexes of documents by the name they were loaded with, searched on their own or next to the products
+    static ref SHARED_DOCUMENTS: Mutex<ahash::AHashMap<String, Arc<DocumentIndex>>> =
+        Mutex::new(ahash::AHashMap::new());
     static ref NODE_ARENA: Arena<GramNode<'static, char>> = Arena::new();
     static ref SUPER_ARENA: SuperAlloc = SuperAlloc::new();
 }
 
+thread_local! {
+    // Called with a shard index when a search needs a shard that isn't loaded
+    static SHARD_FETCHER: RefCell<Option<js_sys::Function>> = const { RefCell::new(None) };
+    static REQUESTED_SHARDS: RefCell<ahash::AHashSet<usize>> = RefCell::new(ahash::AHashSet::new());
+}
+
 // #[wasm_bindgen]
 // struct SearchEngine<'engine> {
 //     ngram: Arc<GramIndex<'engine, char, Product<'engine>, NGRAM_INDEX_SIZE>>,
@@ -45,61 +550,455 @@ pub fn init_panic_hook() {
     console_error_panic_hook::set_once();
 }
 
+fn load(index: LoadedIndex, classic: ClassicIndexes<'static>, blob: Option<Cow<'static, [u8]>>) {
+    SHARED_INDEX.lock().unwrap().replace(Arc::new(index));
+    SHARED_CLASSIC_INDEX
+        .lock()
+        .unwrap()
+        .replace(Arc::new(classic));
+    *SHARED_BLOB.lock().unwrap() = blob;
+}
+
 #[wasm_bindgen]
 pub fn initialize(input: &[u8]) -> bool {
     init_panic_hook();
 
-    let (index, classic) = deserialize_all(input, &NODE_ARENA, &SUPER_ARENA).unwrap();
+    let (index, classic): (Index, _) = deserialize_all(input, &NODE_ARENA, &SUPER_ARENA).unwrap();
 
-    SHARED_INDEX.lock().unwrap().replace(Arc::new(index));
-    SHARED_CLASSIC_INDEX
+    load(LoadedIndex::Eager(index), classic, None);
+
+    true
+}
+
+// Like initialize, but a copy of the blob is kept so `apply_patch` can upgrade the index
+#[wasm_bindgen]
+pub fn initialize_patchable(input: &[u8]) -> bool {
+    init_panic_hook();
+
+    let (index, classic): (Index, _) = deserialize_all(input, &NODE_ARENA, &SUPER_ARENA).unwrap();
+
+    load(
+        LoadedIndex::Eager(index),
+        classic,
+        Some(Cow::Owned(input.to_vec())),
+    );
+
+    true
+}
+
+// Loads an index built by `index_documents` under a name like "articles", replacing one loaded with the same name.
+// It's searched with `search_documents`, or with the products through `federated_search`, so it can't be named
+// like the product index or its collections
+#[wasm_bindgen]
+pub fn initialize_documents(name: &str, input: &[u8]) -> bool {
+    init_panic_hook();
+
+    if name == PRODUCTS || name == COLLECTIONS {
+        return false;
+    }
+    let Some(index) = deserialize_documents(input, &NODE_ARENA, &SUPER_ARENA) else {
+        return false;
+    };
+    SHARED_DOCUMENTS
         .lock()
         .unwrap()
-        .replace(Arc::new(classic));
+        .insert(name.to_string(), Arc::new(index));
 
     true
 }
 
+// Like initialize, but only the products and classic indexes are decoded, the grams are searched in the blob itself.
+// The index can be patched with `apply_patch`, as it keeps the blob anyway
 #[wasm_bindgen]
+pub fn initialize_lazy(input: &[u8]) -> bool {
+    init_panic_hook();
+
+    // The blob has to live as long as the index that points into it
+    let blob: &'static [u8] = SUPER_ARENA.alloc(input.to_vec());
+
+    let Some(sections) = IndexSections::parse(blob) else {
+        return false;
+    };
+    let Some(index) = LazyIndex::load(&sections, &SUPER_ARENA) else {
+        return false;
+    };
+    let Some((_, classic)) =
+        ClassicIndexes::deserialize(sections.get(Section::Classic), index.product_container)
+    else {
+        return false;
+    };
+
+    load(LoadedIndex::Lazy(index), classic, Some(Cow::Borrowed(blob)));
+
+    true
+}
+
+// Loads the core shard made by `serialize_sharded`. The gram shards are requested through `fetch_shard(index)`
+// when a search needs them, and should be handed back through `load_shard`
+#[wasm_bindgen]
+pub fn initialize_sharded(core: &[u8], fetch_shard: js_sys::Function) -> bool {
+    init_panic_hook();
+
+    let core: &'static [u8] = SUPER_ARENA.alloc(core.to_vec());
+
+    let Some(sections) = IndexSections::parse(core) else {
+        return false;
+    };
+    let Some(index) = ShardedIndex::load(core, &sections, &SUPER_ARENA) else {
+        return false;
+    };
+    let Some((_, classic)) =
+        ClassicIndexes::deserialize(sections.get(Section::Classic), index.product_container)
+    else {
+        return false;
+    };
+
+    SHARD_FETCHER.with(|fetcher| fetcher.replace(Some(fetch_shard)));
+    REQUESTED_SHARDS.with(|requested| requested.borrow_mut().clear());
+
+    // Patches apply to whole blobs, so a sharded index is patched with `patch_blob` one blob at a time
+    load(LoadedIndex::Sharded(index), classic, None);
+
+    true
+}
+
+#[wasm_bindgen]
+pub fn load_shard(shard: &[u8]) -> bool {
+    let Some(index) = SHARED_INDEX.lock().unwrap().clone() else {
+        return false;
+    };
+    let LoadedIndex::Sharded(index) = &*index else {
+        return false;
+    };
+    let shard: &'static [u8] = SUPER_ARENA.alloc(shard.to_vec());
+    index.add_shard(shard).is_some()
+}
+
+fn request_shards(shards: &[usize]) {
+    SHARD_FETCHER.with(|fetcher| {
+        let fetcher = fetcher.borrow();
+        let Some(fetcher) = fetcher.as_ref() else {
+            return;
+        };
+        REQUESTED_SHARDS.with(|requested| {
+            let mut requested = requested.borrow_mut();
+            for shard in shards {
+                // Each shard is only requested once
+                if requested.insert(*shard) == false {
+                    continue;
+                }
+                #[allow(clippy::cast_precision_loss)]
+                let shard = JsValue::from_f64(*shard as f64);
+                if let Err(e) = fetcher.call1(&JsValue::UNDEFINED, &shard) {
+                    println!("Failed to call shard fetcher with error: {e:?}");
+                }
+            }
+        });
+    });
+}
+
+// Upgrades the index loaded by `initialize_patchable` or `initialize_lazy` with a patch made by `make_patch`.
+// Returns false if the patch was made for another blob, or if the index wasn't loaded to be patched, like sharded
+// indexes, whose core and shards are patched with `patch_blob` and loaded again.
+// The patched index is loaded like a new one, and the arenas never free the old one,
+// so every patch costs the memory of a loaded index until the page is reloaded
+#[wasm_bindgen]
+pub fn apply_patch(patch: &[u8]) -> bool {
+    let new_blob = {
+        let lock = SHARED_BLOB.lock().unwrap();
+        let Some(new_blob) = lock
+            .as_ref()
+            .and_then(|blob| serialize::patch::apply_patch(blob, patch))
+        else {
+            return false;
+        };
+        new_blob
+    };
+
+    let is_lazy = matches!(
+        SHARED_INDEX.lock().unwrap().as_deref(),
+        Some(LoadedIndex::Lazy(_))
+    );
+    if is_lazy {
+        initialize_lazy(&new_blob)
+    } else {
+        initialize_patchable(&new_blob)
+    }
+}
+
+// Produces the patched blob without loading it, so it can be cached by the caller
+#[wasm_bindgen]
+pub fn patch_blob(old: &[u8], patch: &[u8]) -> Option<Vec<u8>> {
+    serialize::patch::apply_patch(old, patch)
+}
+
+// Removes the products of the wrong category or tag, and keeps which of their variants matched
+fn filter_categories<'p>(
+    results: Vec<(&'p Product<'static>, f32)>,
+    categories: &CategoryHandler,
+    tags: &TagHandler,
+    variants: &[Variant],
+) -> (
+    Vec<&'p Product<'static>>,
+    ahash::AHashMap<usize, Vec<usize>>,
+) {
+    let mut matched_variants = ahash::AHashMap::new();
+    let results = results
+        .into_iter()
+        .map(|(v, _)| v)
+        .filter(|p| tags.is_valid(p))
+        .filter(|p| match categories.matching_variants(p, variants) {
+            Some(matched) => {
+                if matched.is_empty() == false {
+                    matched_variants.insert(p.serialization_id, matched);
+                }
+                true
+            }
+            None => false,
+        })
+        .collect();
+    (results, matched_variants)
+}
+
+// Applies the feature and price filters, giving the serialization ids of the products left
+fn filter_features(
+    results: Vec<&Product<'static>>,
+    filters: &[FeatureFilter],
+    container: &ProductContainer<'static>,
+    currency: &str,
+) -> Vec<usize> {
+    results
+        .into_iter()
+        .filter(|p| {
+            filters
+                .iter()
+                .all(|filter| filter.passes(p, container, currency))
+        })
+        .map(|p| p.serialization_id)
+        .collect()
+}
+
+// Strings from JavaScript are owned
+#[wasm_bindgen]
+#[allow(clippy::too_many_arguments, clippy::needless_pass_by_value)]
 pub fn search(
     input: &str,
     categories: &CategoryHandler,
     tags: &TagHandler,
     order: Option<String>,
     feature_filter: &js_sys::Object,
+    typo_tolerance: Option<js_sys::Object>,
+    currency: Option<String>,
+    collection: Option<String>,
 ) -> Option<ProductProducer> {
     let filters = FeatureFilter::parse(feature_filter)?;
 
-    let lock = SHARED_INDEX.lock().ok()?;
-    let index = lock.as_ref()?.clone();
+    let index = SHARED_INDEX.lock().ok()?.as_ref()?.clone();
+
+    let tolerance = match typo_tolerance {
+        Some(object) => parse_tolerance(&object)?,
+        None => index.product_container().config.typo_tolerance,
+    };
+    let stats = SearchStats::default();
+
+    let classic = SHARED_CLASSIC_INDEX.lock().ok()?.as_ref()?.clone();
+
+    let options = SearchOptions {
+        categories,
+        tags,
+        features: &filters,
+        order: order.as_deref(),
+        currency: currency.as_deref(),
+        collection: collection.as_deref(),
+        tolerance: &tolerance,
+    };
+    let found = index.search_filtered(input, &classic, &options, &stats)?;
+    request_shards(&found.missing_shards);
+
+    Some(ProductProducer::new(
+        index.product_container(),
+        found.ids,
+        found.missing_shards.is_empty() == false,
+        stats.explored_nodes(),
+        found.matched_variants,
+    ))
+}
+
+// Searches the documents loaded by `initialize_documents` with the name, best matches first
+#[wasm_bindgen]
+pub fn search_documents(
+    name: &str,
+    input: &str,
+    typo_tolerance: Option<js_sys::Object>,
+) -> Option<DocumentProducer> {
+    let index = SHARED_DOCUMENTS.lock().ok()?.get(name)?.clone();
+
+    let tolerance = match typo_tolerance {
+        Some(object) => parse_tolerance(&object)?,
+        None => index.container.config.typo_tolerance,
+    };
+    let ranked = rank_documents(&index, input, &tolerance, &SearchStats::default());
 
-    let results = index.search(input.chars().flat_map(char::to_lowercase));
-    let mut filtered_results: Box<dyn Iterator<Item = &Product<'_>>> = Box::new(
-        results
+    Some(DocumentProducer::new(
+        index.container,
+        ranked
+            .results
             .into_iter()
-            .map(|(v, _)| v)
-            // We remove the products of the wrong category or tag
-            .filter(|p| categories.is_valid(p))
-            .filter(|p| tags.is_valid(p)),
-    );
+            .map(|(document, _)| document.serialization_id)
+            .collect(),
+    ))
+}
 
-    for filter in &filters {
-        filtered_results = filter.filter(filtered_results, &index.product_container.extra_features);
-    }
+// The names the product index and its collections have in a federated search
+const PRODUCTS: &str = "products";
+const COLLECTIONS: &str = "collections";
 
-    let mut results: Vec<usize> = filtered_results.map(|p| p.serialization_id).collect();
+// Searches the product index, loaded as "products", its collections as "collections", and the document
+// indexes together. The query is JSON like `{"indexes": [{"name": "products", "limit": 5},
+// {"name": "articles", "limit": 3}], "merge": "grouped"}`, see `FederatedQuery`.
+// Products aren't filtered by category, tag or feature. Indexes that aren't loaded are skipped,
+// and given by `get_missing_indexes`
+#[wasm_bindgen]
+pub fn federated_search(
+    input: &str,
+    query: &str,
+    typo_tolerance: Option<js_sys::Object>,
+) -> Option<FederatedProducer> {
+    let query: FederatedQuery = serde_json::from_str(query).ok()?;
+    // Without a tolerance, every index uses its own
+    let tolerance = match typo_tolerance {
+        Some(object) => Some(parse_tolerance(&object)?),
+        None => None,
+    };
 
-    if let Some(order) = order {
-        let lock = SHARED_CLASSIC_INDEX.lock().ok()?;
-        let classic = lock.as_ref()?;
-        let order = classic.order.get_orders(&order)?;
-        results.sort_by_cached_key(|v| order[*v]);
+    let mut sources = Vec::with_capacity(query.indexes.len());
+    let mut results = Vec::with_capacity(query.indexes.len());
+    let mut partial = false;
+    let mut missing = Vec::new();
+    for FederatedIndex { name, .. } in &query.indexes {
+        let stats = SearchStats::default();
+        let index = SHARED_INDEX.lock().ok()?.clone();
+        let classic = SHARED_CLASSIC_INDEX.lock().ok()?.clone();
+        let documents = SHARED_DOCUMENTS.lock().ok()?.get(name).cloned();
+        let (source, found) = match (name.as_str(), index, classic, documents) {
+            (PRODUCTS, Some(index), Some(classic), _) => {
+                let container = index.product_container();
+                let tolerance = tolerance.unwrap_or(container.config.typo_tolerance);
+                let ranked = index.rank(input, &classic, &tolerance, &stats);
+                request_shards(&ranked.missing_shards);
+                partial |= ranked.missing_shards.is_empty() == false;
+
+                let found = IndexResults {
+                    results: ranked
+                        .results
+                        .into_iter()
+                        .map(|(p, c)| (p.serialization_id, c))
+                        .collect(),
+                    max_confidence: ranked.max_confidence,
+                };
+                (FederatedSource::Products(container), found)
+            }
+            (COLLECTIONS, Some(index), Some(classic), _) => {
+                let normalization = &index.product_container().config.normalization;
+                // A collection's score is the share of the query's words in its title
+                let found = IndexResults {
+                    results: classic.collections.search(input, normalization),
+                    max_confidence: 1.0,
+                };
+                (FederatedSource::Collections(classic), found)
+            }
+            (_, _, _, Some(index)) => {
+                let tolerance = tolerance.unwrap_or(index.container.config.typo_tolerance);
+                let ranked = rank_documents(&index, input, &tolerance, &stats);
+                let found = IndexResults {
+                    results: ranked
+                        .results
+                        .into_iter()
+                        .map(|(d, c)| (d.serialization_id, c))
+                        .collect(),
+                    max_confidence: ranked.max_confidence,
+                };
+                (FederatedSource::Documents(index.container), found)
+            }
+            _ => {
+                // The index keeps its place in the query, without results
+                missing.push(name.clone());
+                (FederatedSource::Missing, IndexResults::default())
+            }
+        };
+        sources.push((name.clone(), source));
+        results.push(found);
     }
 
-    Some(ProductProducer::new(index.product_container, results))
+    let hits = query.merge(results);
+    Some(FederatedProducer::new(sources, hits, partial, missing))
+}
+
+// The tolerance is given as an object like `{ "edits": "off", "min_word_length": 4 }`
+fn parse_tolerance(object: &js_sys::Object) -> Option<TypoTolerance> {
+    let json = String::from(js_sys::JSON::stringify(object).ok()?);
+    serde_json::from_str(&json).ok()
+}
+
+use classic_indexes::{
+    suggest_filters, FilterSuggestion, FilterTarget, LookupIndex, MIN_SUGGESTION_SCORE,
+};
+use federated::{FederatedIndex, FederatedQuery, IndexResults};
+use js_interactable::{
+    CategoryHandler, DocumentProducer, FeatureFilter, FederatedProducer, FederatedSource,
+    JsCollection, JsFilterSuggestion, JsProduct, TagHandler,
+};
+use merchandising::RuleContext;
+use query::ParsedQuery;
+
+// Looks up a product with the lookup index, without searching
+fn lookup(find: impl Fn(&LookupIndex) -> Option<usize>) -> Option<JsProduct> {
+    let container = SHARED_INDEX.lock().ok()?.as_ref()?.product_container();
+    let lock = SHARED_CLASSIC_INDEX.lock().ok()?;
+    let id = find(&lock.as_ref()?.lookup)?;
+    container.products.get(id)?;
+    Some(JsProduct::new(container, id, Vec::new()))
+}
+
+/// The product with an id, either the full id or its numeric part
+#[wasm_bindgen]
+pub fn get_product(id: &str) -> Option<JsProduct> {
+    lookup(|index| index.product(id))
 }
 
-use js_interactable::{CategoryHandler, FeatureFilter, TagHandler};
+/// The product with a variant with this SKU or barcode
+#[wasm_bindgen]
+pub fn lookup_sku(sku: &str) -> Option<JsProduct> {
+    lookup(|index| index.code(sku))
+}
+
+/// The collection with a handle or id
+#[wasm_bindgen]
+pub fn get_collection(handle: &str) -> Option<JsCollection> {
+    let classic = SHARED_CLASSIC_INDEX.lock().ok()?.as_ref()?.clone();
+    let (id, _) = classic.collections.find(handle)?;
+    Some(JsCollection::new(classic, id))
+}
+
+/// The collections with titles matching the query, best first
+#[wasm_bindgen]
+pub fn search_collections(query: &str) -> Option<Vec<JsCollection>> {
+    let normalization = &SHARED_INDEX
+        .lock()
+        .ok()?
+        .as_ref()?
+        .product_container()
+        .config
+        .normalization;
+    let classic = SHARED_CLASSIC_INDEX.lock().ok()?.as_ref()?.clone();
+    let found = classic.collections.search(query, normalization);
+    Some(
+        found
+            .into_iter()
+            .map(|(id, _)| JsCollection::new(classic.clone(), id))
+            .collect(),
+    )
+}
 
 #[wasm_bindgen]
 pub fn get_categories() -> Option<CategoryHandler> {
@@ -124,10 +1023,40 @@ pub fn get_orders() -> Option<Vec<JsValue>> {
     let lock = SHARED_CLASSIC_INDEX.lock().ok()?;
     let index = lock.as_ref()?;
     let options = index.order.options();
-    let js_options = options.map(JsValue::from);
+    // Only searches within a collection can be in its own order
+    let collection_default = (index.collections.is_empty() == false).then_some(COLLECTION_DEFAULT);
+    let js_options = options.chain(collection_default).map(JsValue::from);
     Some(js_options.collect())
 }
 
+// The currencies products have prices in, the default one first
+#[wasm_bindgen]
+pub fn get_currencies() -> Option<Vec<JsValue>> {
+    let container = SHARED_INDEX.lock().ok()?.as_ref()?.product_container();
+    let prices = &container.prices;
+    let default = prices.default_currency();
+    let mut currencies: Vec<&str> = prices.currencies().filter(|c| *c != default).collect();
+    currencies.sort_unstable();
+    let currencies = std::iter::once(default).chain(currencies);
+    Some(currencies.map(JsValue::from).collect())
+}
+
+// How many corrected queries `did_you_mean` offers
+const SPELLING_SUGGESTIONS: usize = 3;
+
+/// Corrected spellings of the query, best first, to offer when it finds nothing or little.
+/// Empty if every word of the query is spelled as in the catalogue
+#[wasm_bindgen]
+pub fn did_you_mean(query: &str) -> Option<Vec<JsValue>> {
+    let container = SHARED_INDEX.lock().ok()?.as_ref()?.product_container();
+    let classic = SHARED_CLASSIC_INDEX.lock().ok()?.as_ref()?.clone();
+    let suggestions =
+        classic
+            .spelling
+            .suggest(query, &container.config.normalization, SPELLING_SUGGESTIONS);
+    Some(suggestions.into_iter().map(JsValue::from).collect())
+}
+
 #[wasm_bindgen]
 pub struct TagSuggestionResult {
     tag: js_interactable::JSTag,
@@ -145,107 +1074,361 @@ impl TagSuggestionResult {
     }
 }
 
-// Simple string match to find likely tags
+// The filters the query likely names, with the indexes they point into.
+// Without a minimum score, suggestions need `MIN_SUGGESTION_SCORE`
+fn filter_suggestions(
+    query: &str,
+    min_score: Option<f32>,
+    limit: usize,
+) -> Option<(
+    Arc<ClassicIndexes<'static>>,
+    &'static ProductContainer<'static>,
+    Vec<FilterSuggestion>,
+)> {
+    let container = SHARED_INDEX.lock().ok()?.as_ref()?.product_container();
+    let classic = SHARED_CLASSIC_INDEX.lock().ok()?.as_ref()?.clone();
+    let normalization = &container.config.normalization;
+    let found = suggest_filters(
+        query,
+        &classic,
+        &container.vendors.by_id,
+        normalization,
+        min_score.unwrap_or(MIN_SUGGESTION_SCORE),
+        limit,
+    );
+    Some((classic, container, found))
+}
+
+/// The tags, category options and vendors that words of the query likely name, best first,
+/// so they can be applied as filters and taken out of the query. Scores are from 0 to 1
 #[wasm_bindgen]
-pub fn tag_suggestion(query: &str) -> Option<TagSuggestionResult> {
-    fn overlap(original: &str, other: &str, min_len: usize) -> f32 {
-        let min_len_found = original.len().min(other.len());
-        if min_len_found < min_len {
-            return 0.0;
-        }
-        #[allow(clippy::cast_precision_loss)]
-        let min_len_found = min_len_found as f32;
-        let overlap = original
-            .chars()
-            .zip(other.chars())
-            .map(|(a, b)| if a == b { 1.0 } else { 0.0 })
-            .reduce(|a, b| a + b)
-            .unwrap_or(0.0);
-        overlap / min_len_found
-    }
+pub fn suggest_query_filters(
+    query: &str,
+    limit: usize,
+    min_score: Option<f32>,
+) -> Option<Vec<JsFilterSuggestion>> {
+    let (classic, container, found) = filter_suggestions(query, min_score, limit)?;
+    let suggestions = found
+        .into_iter()
+        .map(|suggestion| JsFilterSuggestion::new(classic.clone(), container, suggestion, query));
+    Some(suggestions.collect())
+}
 
-    let read_lock = SHARED_CLASSIC_INDEX.lock().unwrap();
+// The tag the query most likely names, if it scores at least the minimum score
+#[wasm_bindgen]
+pub fn tag_suggestion(query: &str, min_score: Option<f32>) -> Option<TagSuggestionResult> {
+    let (classic, _, found) = filter_suggestions(query, min_score, usize::MAX)?;
+    found
+        .into_iter()
+        .find_map(|suggestion| match suggestion.target {
+            FilterTarget::Tag(id) => Some(TagSuggestionResult {
+                tag: js_interactable::JSTag::new(classic.clone(), id),
+                input: query[suggestion.span].to_string(),
+            }),
+            _ => None,
+        })
+}
 
-    let index = read_lock.as_ref()?;
+use crate::config::IndexConfig;
+use crate::data::{
+    optimize_documents, optimize_with_report, IndexReport, ProductCollector, RawDocuments,
+    RawProduct, ValidationPolicy,
+};
+use crate::js_interactable::ProductProducer;
 
-    let mut most_likely: Option<(f32, usize, &str)> = None;
-    for keyword in query.split(' ') {
-        for tag in index.tags.iter() {
-            let overlap = overlap(tag.name.as_str(), keyword, 3);
-            if overlap > 0.8 {
-                most_likely = match most_likely {
-                    Some((current, _, _)) if overlap > current => {
-                        Some((overlap, tag.get_id(), keyword))
-                    }
-                    None => Some((overlap, tag.get_id(), keyword)),
-                    current => current,
-                }
-            }
-        }
-    }
+pub fn index_and_serialize<'a>(
+    products: impl IntoIterator<Item = RawProduct<'a>>,
+    arena: &'static SuperAlloc,
+) -> Result<(Vec<u8>, IndexReport), Box<dyn std::error::Error>> {
+    index_and_serialize_with_n::<NGRAM_INDEX_SIZE>(
+        products,
+        IndexConfig::default(),
+        arena,
+        IndexReport::default(),
+    )
+}
 
-    most_likely.map(|(_, tag_id, input)| TagSuggestionResult {
-        tag: js_interactable::JSTag::new(index.clone(), tag_id),
-        input: input.to_string(),
-    })
+pub fn build_index<'a, const N: usize>(
+    products: impl IntoIterator<Item = RawProduct<'a>>,
+    config: IndexConfig,
+    arena: &'static SuperAlloc,
+) -> (
+    GramIndex<'static, char, Product<'static>, N>,
+    ClassicIndexes<'static>,
+) {
+    build_index_with_report(products, config, arena, &mut IndexReport::default())
 }
 
-use crate::data::{optimize, RawProduct};
-use crate::js_interactable::ProductProducer;
-use crate::ngram::IndexFeed;
+pub fn build_index_with_report<'a, const N: usize>(
+    products: impl IntoIterator<Item = RawProduct<'a>>,
+    config: IndexConfig,
+    arena: &'static SuperAlloc,
+    report: &mut IndexReport,
+) -> (
+    GramIndex<'static, char, Product<'static>, N>,
+    ClassicIndexes<'static>,
+) {
+    let (prods, classic_index) = optimize_with_report(products, config, arena, report);
+    (index_container(prods, arena), classic_index)
+}
 
-pub fn index_and_serialize(
-    products: Vec<RawProduct>,
+// Builds the gram index of the products in the container
+fn index_container<const N: usize>(
+    prods: &'static ProductContainer<'static>,
     arena: &'static SuperAlloc,
-) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    index_and_serialize_with_n::<NGRAM_INDEX_SIZE>(products, arena)
+) -> GramIndex<'static, char, Product<'static>, N> {
+    let iter = prods
+        .products
+        .iter()
+        .map(|p| preprocessor::index_feed(p, &prods.config));
+
+    let node_arena = arena.alloc(Arena::new());
+
+    let (index, _): (GramIndex<char, Product, N>, _) =
+        GramIndex::index_from_with_pruning(iter, node_arena, prods, &prods.config.pruning);
+    index
 }
 
-pub fn index_and_serialize_with_n<const N: usize>(
-    products: Vec<RawProduct>,
+/// Indexes documents of any schema, like the articles and store pages of a site
+pub fn build_document_index<const N: usize>(
+    input: RawDocuments,
+    config: IndexConfig,
     arena: &'static SuperAlloc,
-) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    let (prods, classic_index) = optimize(products, arena);
-
-    let iter = prods.products.iter().map(|p| {
-        let Product {
-            description,
-            title,
-            vendor,
-            ..
-        } = p;
-
-        IndexFeed {
-            data: p,
-            grams: [description, title, &vendor.name]
-                .into_iter()
-                .flat_map(|s| s.chars())
-                .flat_map(char::to_lowercase),
-        }
+    report: &mut IndexReport,
+) -> GramIndex<'static, char, Document, N> {
+    let RawDocuments { schemas, documents } = input;
+    let container = arena.alloc(optimize_documents(schemas, documents, config, report));
+
+    let iter = container.documents.iter().map(|document| {
+        preprocessor::document_feed(document, container.schema(document), &container.config)
     });
 
-    let arena = Arena::new();
+    let node_arena = arena.alloc(Arena::new());
 
-    let index: GramIndex<char, Product, N> = GramIndex::index_from(iter, &arena, prods);
+    let (index, _) =
+        GramIndex::index_from_with_pruning(iter, node_arena, container, &container.config.pruning);
+    index
+}
 
-    let results = index.search("kunst".chars().flat_map(char::to_lowercase));
-    // let results = index.search("UNERsTuOD hvzdom".chars().flat_map(char::to_lowercase));
+pub fn index_documents_and_serialize(
+    input: RawDocuments,
+    config: IndexConfig,
+    arena: &'static SuperAlloc,
+) -> (Vec<u8>, IndexReport) {
+    let mut report = IndexReport::default();
+    let index = build_document_index::<NGRAM_INDEX_SIZE>(input, config, arena, &mut report);
+    (serialize_documents(&index), report)
+}
 
-    println!("Got {} results", results.len());
+type ValidatedIndex<const N: usize> = (
+    GramIndex<'static, char, Product<'static>, N>,
+    ClassicIndexes<'static>,
+    IndexReport,
+);
+
+// Indexes the products the collector kept, after checking them by the config's policy.
+// `report` holds the problems found before, like the products that couldn't be read
+fn build_validated<const N: usize>(
+    products: ProductCollector<'_>,
+    config: IndexConfig,
+    arena: &'static SuperAlloc,
+    mut report: IndexReport,
+) -> Result<ValidatedIndex<N>, Box<dyn std::error::Error>> {
+    let policy = config.validation;
+    let fails = |report: &IndexReport| {
+        policy == ValidationPolicy::Fail && report.diagnostics.is_empty() == false
+    };
+
+    if fails(&report) {
+        return Err(Box::new(report));
+    }
+    let (prods, classic_index) = products.finish(config, arena, &mut report);
+    if fails(&report) {
+        return Err(Box::new(report));
+    }
+    Ok((index_container(prods, arena), classic_index, report))
+}
+
+// Checks the products by the config's policy as they're given, keeping the ones that pass
+fn collect_validated<'a>(
+    products: impl IntoIterator<Item = RawProduct<'a>>,
+    config: &IndexConfig,
+    report: &mut IndexReport,
+) -> ProductCollector<'a> {
+    let mut collector = ProductCollector::validated(config.validation);
+    for product in products {
+        collector.add(product, report);
+    }
+    collector
+}
+
+pub fn index_and_serialize_with_n<'a, const N: usize>(
+    products: impl IntoIterator<Item = RawProduct<'a>>,
+    config: IndexConfig,
+    arena: &'static SuperAlloc,
+    mut report: IndexReport,
+) -> Result<(Vec<u8>, IndexReport), Box<dyn std::error::Error>> {
+    let products = collect_validated(products, &config, &mut report);
+    index_and_serialize_collected::<N>(products, config, arena, report)
+}
+
+/// Like `index_and_serialize_with_n`, for products collected as they were read,
+/// with a collector made by `ProductCollector::validated` with the config's policy
+pub fn index_and_serialize_collected<const N: usize>(
+    products: ProductCollector<'_>,
+    config: IndexConfig,
+    arena: &'static SuperAlloc,
+    report: IndexReport,
+) -> Result<(Vec<u8>, IndexReport), Box<dyn std::error::Error>> {
+    let (index, classic_index, report) = build_validated::<N>(products, config, arena, report)?;
 
     let output = serialize_all(&index, &classic_index);
 
-    Ok(output)
+    Ok((output, report))
+}
+
+/// Like `index_and_serialize_collected`, splitting the gram index into shards
+pub fn index_and_serialize_sharded(
+    products: ProductCollector<'_>,
+    config: IndexConfig,
+    arena: &'static SuperAlloc,
+    shard_count: usize,
+    report: IndexReport,
+) -> Result<(ShardedBlobs, IndexReport), Box<dyn std::error::Error>> {
+    let (index, classic_index, report) =
+        build_validated::<NGRAM_INDEX_SIZE>(products, config, arena, report)?;
+
+    Ok((
+        serialize_sharded(&index, &classic_index, shard_count),
+        report,
+    ))
+}
+
+// Reads the products of a JSON object or array one at a time, so only what the index needs of
+// them is kept while the rest of the input is read
+#[cfg(feature = "indexing")]
+fn read_collected(
+    input: &str,
+    config: &IndexConfig,
+) -> Option<(ProductCollector<'static>, IndexReport)> {
+    let mut report = IndexReport::default();
+    // Problems of the products that could be read come after the ones of the products that couldn't
+    let mut problems = IndexReport::default();
+    let mut products = ProductCollector::validated(config.validation);
+    data::read_products(
+        input.as_bytes(),
+        data::InputFormat::Json,
+        &mut report,
+        |product| {
+            products.add(product, &mut problems);
+        },
+    )
+    .ok()?;
+    report.merge(problems);
+    Some((products, report))
 }
 
 #[cfg(feature = "indexing")]
 #[wasm_bindgen]
-pub fn index(input: &str) -> Option<Vec<u8>> {
-    let products: ahash::AHashMap<String, RawProduct> = serde_json::from_str(input).ok()?;
+pub fn make_patch(old: &[u8], new: &[u8]) -> Vec<u8> {
+    serialize::patch::make_patch(old, new)
+}
 
-    let products: Vec<_> = products.into_iter().map(|(_, v)| v).collect();
+#[cfg(feature = "indexing")]
+#[wasm_bindgen]
+pub fn index(input: &str) -> Option<Vec<u8>> {
+    let config = IndexConfig::default();
+    let (products, report) = read_collected(input, &config)?;
 
-    let output = index_and_serialize(products, &SUPER_ARENA).ok()?;
+    let (output, _) =
+        index_and_serialize_collected::<NGRAM_INDEX_SIZE>(products, config, &SUPER_ARENA, report)
+            .ok()?;
 
     Some(output)
 }
+
+// The config is given as JSON, like `{"description": "full", "title": {"truncated": 60}}`
+#[cfg(feature = "indexing")]
+#[wasm_bindgen]
+pub fn index_with_config(input: &str, config: &str) -> Option<Vec<u8>> {
+    index_with_report(input, config)?.bytes
+}
+
+/// The index of a build with the report of the products that had problems
+#[cfg(feature = "indexing")]
+#[wasm_bindgen]
+pub struct IndexOutput {
+    bytes: Option<Vec<u8>>,
+    report: IndexReport,
+}
+
+#[cfg(feature = "indexing")]
+#[wasm_bindgen]
+impl IndexOutput {
+    // Missing if the validation policy is "fail" and a product had a problem
+    pub fn get_bytes(&self) -> Option<Vec<u8>> {
+        self.bytes.clone()
+    }
+
+    // The report as JSON, like `{"indexed": 10, "skipped": 1, "diagnostics": [...]}`
+    pub fn get_report(&self) -> String {
+        self.report.to_json()
+    }
+}
+
+// Like `index_with_config`, but also tells which products were skipped or repaired
+#[cfg(feature = "indexing")]
+#[wasm_bindgen]
+pub fn index_with_report(input: &str, config: &str) -> Option<IndexOutput> {
+    let config: IndexConfig = serde_json::from_str(config).ok()?;
+    let (products, report) = read_collected(input, &config)?;
+
+    let output =
+        index_and_serialize_collected::<NGRAM_INDEX_SIZE>(products, config, &SUPER_ARENA, report);
+    Some(match output {
+        Ok((bytes, report)) => IndexOutput {
+            bytes: Some(bytes),
+            report,
+        },
+        Err(e) => IndexOutput {
+            bytes: None,
+            report: *e.downcast::<IndexReport>().ok()?,
+        },
+    })
+}
+
+// The input is JSON like `{"schemas": [{"name": "article", "fields": [{"name": "title"}]}], "documents": [...]}`,
+// see `RawDocuments`. Documents with problems are skipped or repaired, and listed in the report
+#[cfg(feature = "indexing")]
+#[wasm_bindgen]
+pub fn index_documents(input: &str, config: &str) -> Option<IndexOutput> {
+    let input: RawDocuments = serde_json::from_str(input).ok()?;
+    let config: IndexConfig = serde_json::from_str(config).ok()?;
+
+    let (bytes, report) = index_documents_and_serialize(input, config, &SUPER_ARENA);
+    Some(IndexOutput {
+        bytes: Some(bytes),
+        report,
+    })
+}
+
+// Returns the core shard followed by the gram shards, see `initialize_sharded`
+#[cfg(feature = "indexing")]
+#[wasm_bindgen]
+pub fn index_sharded(input: &str, shard_count: usize) -> Option<js_sys::Array> {
+    if shard_count == 0 {
+        return None;
+    }
+    let config = IndexConfig::default();
+    let (products, report) = read_collected(input, &config)?;
+
+    let (ShardedBlobs { core, shards }, _) =
+        index_and_serialize_sharded(products, config, &SUPER_ARENA, shard_count, report).ok()?;
+
+    Some(
+        std::iter::once(core)
+            .chain(shards)
+            .map(|blob| js_sys::Uint8Array::from(&blob[..]))
+            .collect(),
+    )
+}
@@ -1,23 +1,744 @@
-use ahash::AHashMap;
+#![allow(clippy::bool_comparison)]
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader},
+    process::ExitCode,
+    str::FromStr,
+    sync::Arc,
+};
+
+use ahash::{AHashMap, AHashSet};
+use colosseum::sync::Arena;
 use indexer_lib::{
-    data::{RawProduct, SuperAlloc},
-    index_and_serialize,
+    classic_indexes::{ClassicIndexes, COLLECTION_DEFAULT},
+    config::IndexConfig,
+    data::{
+        format_minor_units, read_csv, read_mapped, read_products, read_shopify_bulk, FieldMapping,
+        IndexReport, InputFormat, Product, ProductCollector, ProductContainer, SuperAlloc,
+    },
+    index_and_serialize_collected,
+    js_interactable::{CategoryHandler, FeatureFilter, FilterValue, TagHandler},
+    ngram::{GramNode, SearchStats, TypoEdits},
+    serialize::{deserialize_all, Deserializable, IndexSections, Section, FORMAT_VERSION},
+    LoadedIndex, SearchOptions, NGRAM_INDEX_SIZE,
 };
 
 lazy_static::lazy_static! {
     static ref SUPER_ARENA: SuperAlloc = SuperAlloc::new();
+    static ref NODE_ARENA: Arena<GramNode<'static, char>> = Arena::new();
+}
+
+const USAGE: &str = "\
+Usage:
+  indexer build [INPUT] --output PATH [--schema CONFIG] [--ngram 3|4|5|6]
+                [--format auto|json|lines|csv|shopify-bulk] [--mapping MAPPING]
+      Indexes the products in INPUT, or in stdin if it's missing or -. The products are given
+      as a JSON object or array, or one per line, which is detected unless a format is given.
+      Products of other shapes, and CSV, are read with MAPPING, a JSON file telling which
+      JSON pointers or columns hold the fields of a product.
+      CONFIG is a JSON file with the index config. The grams are 5 characters long
+      unless --ngram gives another size, which is written in the index.
+  indexer inspect INDEX
+      Prints the header, counts and section sizes of an index.
+  indexer query INDEX QUERY [--category NAME=OPTION]... [--tag NAME]...
+                            [--feature NAME=VALUE|NAME=FROM..TO]... [--price FROM..TO]
+                            [--currency CODE] [--collection HANDLE] [--order NAME]
+                            [--typos off|min|max] [--limit N]
+      Searches an index and prints the results, filtered, ordered and merchandised the way
+      searches in the browser are.
+  indexer diff OLD NEW
+      Compares the sections and products of two indexes.";
+
+// The gram sizes the CLI builds and searches indexes with, the browser only searches `NGRAM_INDEX_SIZE`
+const GRAM_SIZES: [usize; 4] = [3, 4, 5, 6];
+
+// Calls a function generic over the gram size with one of `GRAM_SIZES`, or gives `otherwise`
+macro_rules! with_gram_size {
+    ($n:expr, $function:ident($($arg:expr),*), $otherwise:expr) => {
+        match $n {
+            3 => $function::<3>($($arg),*),
+            4 => $function::<4>($($arg),*),
+            5 => $function::<5>($($arg),*),
+            6 => $function::<6>($($arg),*),
+            _ => $otherwise,
+        }
+    };
+}
+
+// How many ids of each kind `diff` lists
+const DIFF_LISTED: usize = 20;
+
+enum CliError {
+    // The arguments were wrong, so the usage is printed after the message
+    Usage(String),
+    Failed(String),
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let file = std::fs::read_to_string("./test.json")?;
+fn usage(message: impl Into<String>) -> CliError {
+    CliError::Usage(message.into())
+}
+
+fn failed(message: impl Into<String>) -> CliError {
+    CliError::Failed(message.into())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(CliError::Usage(message)) => {
+            eprintln!("error: {message}\n\n{USAGE}");
+            ExitCode::from(2)
+        }
+        Err(CliError::Failed(message)) => {
+            eprintln!("error: {message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<(), CliError> {
+    let Some((command, rest)) = args.split_first() else {
+        return Err(usage("missing a command"));
+    };
+    match command.as_str() {
+        "build" => build(&Args::parse(
+            rest,
+            &["output", "schema", "format", "mapping", "ngram"],
+        )?),
+        "inspect" => inspect(&Args::parse(rest, &[])?),
+        "query" => query(&Args::parse(
+            rest,
+            &[
+                "category",
+                "tag",
+                "feature",
+                "price",
+                "currency",
+                "collection",
+                "order",
+                "typos",
+                "limit",
+            ],
+        )?),
+        "diff" => diff(&Args::parse(rest, &[])?),
+        "help" | "--help" | "-h" => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        other => Err(usage(format!("unknown command \"{other}\""))),
+    }
+}
+
+/// The arguments after the command, options are given as `--name value` or `--name=value`
+struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, String)>,
+}
 
-    let products: AHashMap<String, RawProduct> = serde_json::from_str(&file)?;
+impl Args {
+    fn parse(args: &[String], known: &[&str]) -> Result<Args, CliError> {
+        let mut parsed = Args {
+            positional: Vec::new(),
+            options: Vec::new(),
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            // A single dash is stdin, and "-term" is an excluded term in a query
+            let name = match arg.strip_prefix("--") {
+                Some(name) => name,
+                None if arg == "-o" => "output",
+                None => {
+                    parsed.positional.push(arg.clone());
+                    continue;
+                }
+            };
+            let (name, value) = match name.split_once('=') {
+                Some((name, value)) => (name, value.to_string()),
+                None => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| usage(format!("--{name} needs a value")))?;
+                    (name, value.clone())
+                }
+            };
+            if known.contains(&name) == false {
+                return Err(usage(format!("unknown option --{name}")));
+            }
+            parsed.options.push((name.to_string(), value));
+        }
+        Ok(parsed)
+    }
 
-    let products: Vec<_> = products.into_iter().map(|(_, v)| v).collect();
+    fn positional(&self, index: usize, name: &str) -> Result<&str, CliError> {
+        self.positional
+            .get(index)
+            .map(String::as_str)
+            .ok_or_else(|| usage(format!("missing {name}")))
+    }
 
-    let output = index_and_serialize(products, &SUPER_ARENA)?;
+    // The last value given for the option
+    fn option<'s>(&'s self, name: &'s str) -> Option<&'s str> {
+        self.all(name).last()
+    }
 
-    std::fs::write("out.test", output)?;
+    fn all<'s>(&'s self, name: &'s str) -> impl Iterator<Item = &'s str> {
+        self.options
+            .iter()
+            .filter(move |(option, _)| option == name)
+            .map(|(_, value)| value.as_str())
+    }
 
+    fn parsed<T: FromStr>(&self, name: &str) -> Result<Option<T>, CliError> {
+        self.option(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| usage(format!("--{name} can't be \"{value}\"")))
+            })
+            .transpose()
+    }
+}
+
+fn read_string(path: &str) -> Result<String, CliError> {
+    std::fs::read_to_string(path).map_err(|e| failed(format!("can't read {path}: {e}")))
+}
+
+fn read_bytes(path: &str) -> Result<Vec<u8>, CliError> {
+    std::fs::read(path).map_err(|e| failed(format!("can't read {path}: {e}")))
+}
+
+// Explains why a file couldn't be loaded as an index
+fn unreadable(path: &str, blob: &[u8]) -> CliError {
+    match u32::deserialize(blob) {
+        // Anything else that doesn't parse probably isn't an index at all
+        Some((_, version)) if version < FORMAT_VERSION => failed(format!(
+            "{path} has format version {version}, but this indexer reads version {FORMAT_VERSION}, so it has to be rebuilt"
+        )),
+        _ => failed(format!("{path} isn't an index, or is damaged")),
+    }
+}
+
+fn print_report(report: &IndexReport) {
+    eprintln!("{report}");
+    for diagnostic in &report.diagnostics {
+        let issue = serde_json::to_string(&diagnostic.issue).unwrap_or_default();
+        eprintln!(
+            "  {}: {issue}, {:?}",
+            diagnostic.product, diagnostic.outcome
+        );
+    }
+}
+
+// Reads the products in the format they're given in, streaming them so the input is never
+// held in memory as a whole, and only what the index needs of each product is kept
+fn read_input(
+    args: &Args,
+    config: &IndexConfig,
+) -> Result<(ProductCollector<'static>, IndexReport), CliError> {
+    let mapping: Option<FieldMapping> = match args.option("mapping") {
+        Some(path) => Some(
+            serde_json::from_str(&read_string(path)?)
+                .map_err(|e| failed(format!("{path} isn't a valid field mapping: {e}")))?,
+        ),
+        None => None,
+    };
+    let input: Box<dyn BufRead> = match args.positional.first().map(String::as_str) {
+        None | Some("-") => Box::new(std::io::stdin().lock()),
+        Some(path) => {
+            let file = File::open(path).map_err(|e| failed(format!("can't read {path}: {e}")))?;
+            Box::new(BufReader::new(file))
+        }
+    };
+
+    let mut report = IndexReport::default();
+    // Problems of the products that could be read come after the ones of the products that couldn't
+    let mut problems = IndexReport::default();
+    let mut products = ProductCollector::validated(config.validation);
+    let add = |product| products.add(product, &mut problems);
+    let format = args.option("format").unwrap_or("auto");
+    let read = match (format, &mapping) {
+        ("csv", Some(mapping)) => {
+            read_csv(input, mapping, &mut report, add).map_err(|e| e.to_string())
+        }
+        ("csv", None) => return Err(usage("CSV needs a --mapping of its columns")),
+        ("shopify-bulk", _) => {
+            read_shopify_bulk(input, &mut report, add).map_err(|e| e.to_string())
+        }
+        (name, mapping) => {
+            let format = match name {
+                "auto" => InputFormat::Auto,
+                "json" => InputFormat::Json,
+                "lines" => InputFormat::Lines,
+                _ => return Err(usage(format!("--format can't be \"{name}\""))),
+            };
+            match mapping {
+                Some(mapping) => read_mapped(input, format, mapping, &mut report, add),
+                None => read_products(input, format, &mut report, add),
+            }
+            .map_err(|e| e.to_string())
+        }
+    };
+    read.map_err(|e| failed(format!("the input can't be read as {format}: {e}")))?;
+    report.merge(problems);
+    Ok((products, report))
+}
+
+fn build(args: &Args) -> Result<(), CliError> {
+    let output = args
+        .option("output")
+        .ok_or_else(|| usage("build needs an --output path"))?;
+    let config: IndexConfig = match args.option("schema") {
+        Some(path) => serde_json::from_str(&read_string(path)?)
+            .map_err(|e| failed(format!("{path} isn't a valid index config: {e}")))?,
+        None => IndexConfig::default(),
+    };
+    let n = args.parsed::<usize>("ngram")?.unwrap_or(NGRAM_INDEX_SIZE);
+    if GRAM_SIZES.contains(&n) == false {
+        return Err(usage(format!(
+            "--ngram can't be {n}, use one of {GRAM_SIZES:?}"
+        )));
+    }
+    let (products, report) = read_input(args, &config)?;
+
+    let built = with_gram_size!(
+        n,
+        index_and_serialize_collected(products, config, &SUPER_ARENA, report),
+        unreachable!("the gram size was checked")
+    );
+    let (bytes, report) = match built {
+        Ok(built) => built,
+        Err(e) => {
+            return match e.downcast::<IndexReport>() {
+                Ok(report) => {
+                    print_report(&report);
+                    Err(failed(
+                        "stopped, as the validation policy is \"fail\" and products had problems",
+                    ))
+                }
+                Err(e) => Err(failed(e.to_string())),
+            };
+        }
+    };
+
+    print_report(&report);
+    std::fs::write(output, &bytes).map_err(|e| failed(format!("can't write {output}: {e}")))?;
+    eprintln!("wrote {} bytes to {output}", bytes.len());
     Ok(())
 }
+
+fn inspect(args: &Args) -> Result<(), CliError> {
+    let path = args.positional(0, "the INDEX to inspect")?;
+    let blob = read_bytes(path)?;
+    let sections = IndexSections::parse(&blob).ok_or_else(|| unreadable(path, &blob))?;
+    let container = sections
+        .products(&SUPER_ARENA)
+        .ok_or_else(|| unreadable(path, &blob))?;
+    let (_, classic) = ClassicIndexes::deserialize(sections.get(Section::Classic), container)
+        .ok_or_else(|| unreadable(path, &blob))?;
+    let (_, shards) =
+        usize::deserialize(sections.get(Section::Shards)).ok_or_else(|| unreadable(path, &blob))?;
+
+    println!("{path}");
+    println!("format version   {}", sections.version);
+    println!("gram size        {}", sections.gram_size);
+    println!("normalization    {:?}", sections.normalization);
+    println!("size             {} bytes", blob.len());
+    println!("sections");
+    for (section, size) in sections.sizes() {
+        println!("  {:<14} {size} bytes", section.name());
+    }
+
+    let options: usize = classic.categories.0.iter().map(|c| c.options.len()).sum();
+    let mut orders: Vec<&str> = classic.order.options().collect();
+    orders.sort_unstable();
+    let mut currencies: Vec<&str> = container.prices.currencies().collect();
+    currencies.sort_unstable();
+
+    println!("products         {}", container.products.len());
+    println!("variants         {}", container.variants.len());
+    println!("vendors          {}", container.vendors.by_id.len());
+    println!("tags             {}", classic.tags.iter().count());
+    println!(
+        "categories       {} with {options} options",
+        classic.categories.0.len()
+    );
+    println!("collections      {}", classic.collections.len());
+    println!("orders           {}", orders.join(", "));
+    println!(
+        "currencies       {} (default {})",
+        currencies.join(", "),
+        container.prices.default_currency()
+    );
+    println!("rules            {}", container.config.rules.0.len());
+    println!("words            {}", classic.spelling.len());
+    println!("gram shards      {shards}");
+    Ok(())
+}
+
+// Parses a range like "100..250", "100.." or "..250", missing bounds are open
+fn range(option: &str, range: &str) -> Result<(Option<f64>, Option<f64>), CliError> {
+    let invalid = || usage(format!("--{option} can't be \"{range}\", use FROM..TO"));
+    let (from, to) = range.split_once("..").ok_or_else(invalid)?;
+    let bound = |bound: &str| match bound.trim() {
+        "" => Ok(None),
+        bound => bound.parse().map(Some).map_err(|_| invalid()),
+    };
+    Ok((bound(from)?, bound(to)?))
+}
+
+// Parses a feature filter like "weight=2.5", "color=red" or "weight=1..5"
+fn feature_filter(filter: &str) -> Result<FeatureFilter, CliError> {
+    let (feature, value) = filter.split_once('=').ok_or_else(|| {
+        usage(format!(
+            "--feature can't be \"{filter}\", use NAME=VALUE or NAME=FROM..TO"
+        ))
+    })?;
+    let feature = feature.to_string();
+    if value.contains("..") {
+        let (from, to) = range("feature", value)?;
+        return Ok(FeatureFilter::new_range(from, to, feature));
+    }
+    let value = match value.parse() {
+        Ok(number) => FilterValue::Number(number),
+        Err(_) => FilterValue::Text(value.to_string()),
+    };
+    Ok(FeatureFilter::new_exactly(value, feature))
+}
+
+fn query(args: &Args) -> Result<(), CliError> {
+    let path = args.positional(0, "the INDEX to search")?;
+    let text = args.positional(1, "the QUERY")?;
+    let limit = args.parsed::<usize>("limit")?.unwrap_or(20);
+    let mut features = args
+        .all("feature")
+        .map(feature_filter)
+        .collect::<Result<Vec<_>, _>>()?;
+    if let Some(price) = args.option("price") {
+        let (from, to) = range("price", price)?;
+        features.push(FeatureFilter::new_range(from, to, "price".to_string()));
+    }
+
+    let blob = read_bytes(path)?;
+    let sections = IndexSections::parse(&blob).ok_or_else(|| unreadable(path, &blob))?;
+    let n = sections.gram_size;
+    with_gram_size!(
+        n,
+        search_index(args, path, &blob, text, &features, limit),
+        Err(failed(format!(
+            "{path} has grams of {n} characters, which can't be searched"
+        )))
+    )
+}
+
+// Searches the index in the blob, whose grams are N characters long
+fn search_index<const N: usize>(
+    args: &Args,
+    path: &str,
+    blob: &[u8],
+    text: &str,
+    features: &[FeatureFilter],
+    limit: usize,
+) -> Result<(), CliError> {
+    let (index, classic) = deserialize_all::<char, N>(blob, &NODE_ARENA, &SUPER_ARENA)
+        .ok_or_else(|| unreadable(path, blob))?;
+    let index = LoadedIndex::Eager(index);
+    let classic = Arc::new(classic);
+    let container = index.product_container();
+
+    let mut tolerance = container.config.typo_tolerance;
+    if let Some(typos) = args.option("typos") {
+        tolerance.edits = match typos {
+            "off" => TypoEdits::Off,
+            "min" => TypoEdits::Min,
+            "max" => TypoEdits::Max,
+            _ => return Err(usage(format!("--typos can't be \"{typos}\""))),
+        };
+    }
+
+    let mut categories = CategoryHandler::new(classic.clone());
+    for filter in args.all("category") {
+        let (category, option) = filter
+            .split_once('=')
+            .ok_or_else(|| usage(format!("--category can't be \"{filter}\", use NAME=OPTION")))?;
+        if categories.activate(category, option) == false {
+            return Err(failed(format!("the index has no category option {filter}")));
+        }
+    }
+    let mut tags = TagHandler::new(classic.clone());
+    for tag in args.all("tag") {
+        if tags.activate(tag) == false {
+            return Err(failed(format!("the index has no tag \"{tag}\"")));
+        }
+    }
+    let collection = args.option("collection");
+    if let Some(collection) = collection {
+        if classic.collections.find(collection).is_none() {
+            return Err(failed(format!(
+                "the index has no collection \"{collection}\""
+            )));
+        }
+    }
+    let currency = args
+        .option("currency")
+        .unwrap_or(container.prices.default_currency());
+    let order = args.option("order");
+    if let Some(name) = order.filter(|name| *name != COLLECTION_DEFAULT) {
+        if classic.order.get_orders_in(name, currency).is_none() {
+            let known: Vec<&str> = classic.order.options().collect();
+            return Err(failed(format!(
+                "the index has no order \"{name}\", it has: {}",
+                known.join(", ")
+            )));
+        }
+    }
+
+    let options = SearchOptions {
+        categories: &categories,
+        tags: &tags,
+        features,
+        order,
+        currency: Some(currency),
+        collection,
+        tolerance: &tolerance,
+    };
+    let stats = SearchStats::default();
+    let found = index
+        .search_filtered(text, &classic, &options, &stats)
+        .ok_or_else(|| failed("the search couldn't be run on this index"))?;
+
+    println!(
+        "{} results for \"{text}\", {} nodes explored",
+        found.ids.len(),
+        stats.explored_nodes()
+    );
+    for (position, id) in found.ids.iter().take(limit).enumerate() {
+        print_result(
+            container,
+            position,
+            *id,
+            &found.confidences,
+            &found.matched_variants,
+            currency,
+        );
+    }
+    Ok(())
+}
+
+fn print_result(
+    container: &ProductContainer<'_>,
+    position: usize,
+    id: usize,
+    confidences: &AHashMap<usize, f32>,
+    matched_variants: &AHashMap<usize, Vec<usize>>,
+    currency: &str,
+) {
+    let product = &container.products[id];
+    // Pinned products can show up without being found by the query
+    let confidence = confidences
+        .get(&id)
+        .map_or_else(|| "pinned".to_string(), |c| format!("{c:.3}"));
+    let price = container
+        .prices
+        .get(id, Some(currency))
+        .map(|price| format!("{} {currency}", format_minor_units(price.min, currency)))
+        .unwrap_or_default();
+    println!(
+        "{:>4}. {confidence:>6}  {}  {}  {price}",
+        position + 1,
+        product.id,
+        product.title
+    );
+    if let Some(variants) = matched_variants.get(&id).filter(|v| v.is_empty() == false) {
+        // Variants from CSV or other shapes may have no title
+        let titles: Vec<&str> = variants
+            .iter()
+            .map(|variant| &container.variants[*variant])
+            .map(|variant| match variant.title.as_str() {
+                "" => variant.id.as_str(),
+                title => title,
+            })
+            .collect();
+        println!("        variants: {}", titles.join(", "));
+    }
+}
+
+// The fields of a product that `diff` compares
+fn summary<'c>(
+    container: &'c ProductContainer<'_>,
+    product: &'c Product<'_>,
+) -> impl PartialEq + 'c {
+    (
+        &product.title,
+        &product.description,
+        &product.vendor.name,
+        container.prices.get(product.serialization_id, None),
+        product
+            .variants
+            .clone()
+            .map(|variant| &container.variants[variant])
+            .collect::<Vec<_>>(),
+    )
+}
+
+fn print_ids(label: &str, ids: &[&str]) {
+    println!("{label:<10} {}", ids.len());
+    for id in ids.iter().take(DIFF_LISTED) {
+        println!("  {id}");
+    }
+    if ids.len() > DIFF_LISTED {
+        println!("  and {} more", ids.len() - DIFF_LISTED);
+    }
+}
+
+fn diff(args: &Args) -> Result<(), CliError> {
+    let old_path = args.positional(0, "the OLD index")?;
+    let new_path = args.positional(1, "the NEW index")?;
+    let old_blob = read_bytes(old_path)?;
+    let new_blob = read_bytes(new_path)?;
+    let old = IndexSections::parse(&old_blob).ok_or_else(|| unreadable(old_path, &old_blob))?;
+    let new = IndexSections::parse(&new_blob).ok_or_else(|| unreadable(new_path, &new_blob))?;
+    let old_container = old
+        .products(&SUPER_ARENA)
+        .ok_or_else(|| unreadable(old_path, &old_blob))?;
+    let new_container = new
+        .products(&SUPER_ARENA)
+        .ok_or_else(|| unreadable(new_path, &new_blob))?;
+
+    println!(
+        "{:<10} {:>12} {:>12} {:>12}",
+        "section", "old", "new", "change"
+    );
+    let sizes = old.sizes().zip(new.sizes());
+    let total = std::iter::once(("total", old_blob.len(), new_blob.len()));
+    for (name, old_size, new_size) in sizes
+        .map(|((section, old_size), (_, new_size))| (section.name(), old_size, new_size))
+        .chain(total)
+    {
+        #[allow(clippy::cast_possible_wrap)]
+        let change = new_size as i64 - old_size as i64;
+        println!("{name:<10} {old_size:>12} {new_size:>12} {change:>+12}");
+    }
+    if old.normalization != new.normalization {
+        println!(
+            "normalization changed from {:?} to {:?}",
+            old.normalization, new.normalization
+        );
+    }
+
+    let by_id = |container: &'static ProductContainer<'static>| -> AHashMap<&str, &Product<'_>> {
+        container
+            .products
+            .iter()
+            .map(|p| (p.id.as_str(), p))
+            .collect()
+    };
+    let old_products = by_id(old_container);
+    let new_products = by_id(new_container);
+    let mut added: Vec<&str> = new_products
+        .keys()
+        .filter(|id| old_products.contains_key(*id) == false)
+        .copied()
+        .collect();
+    let mut removed: Vec<&str> = old_products
+        .keys()
+        .filter(|id| new_products.contains_key(*id) == false)
+        .copied()
+        .collect();
+    let old_ids: AHashSet<&str> = old_products.keys().copied().collect();
+    let mut changed: Vec<&str> = new_products
+        .iter()
+        .filter(|(id, _)| old_ids.contains(*id))
+        .filter(|(id, product)| {
+            summary(old_container, old_products[*id]) != summary(new_container, product)
+        })
+        .map(|(id, _)| *id)
+        .collect();
+    added.sort_unstable();
+    removed.sort_unstable();
+    changed.sort_unstable();
+
+    println!();
+    print_ids("added", &added);
+    print_ids("removed", &removed);
+    print_ids("changed", &changed);
+    Ok(())
+}
+
+#[test]
+fn test_commands() {
+    let dir = std::env::temp_dir().join(format!("indexer-cli-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let index = dir.join("index").to_string_lossy().into_owned();
+    let lines = dir.join("lines").to_string_lossy().into_owned();
+    let run = |args: &[&str]| {
+        let args: Vec<String> = args.iter().map(ToString::to_string).collect();
+        run(&args)
+    };
+    let is_usage = |result: Result<(), CliError>| matches!(result, Err(CliError::Usage(_)));
+    let is_failed = |result: Result<(), CliError>| matches!(result, Err(CliError::Failed(_)));
+
+    assert!(run(&["build", "./test.json", "--output", &index]).is_ok());
+    assert!(run(&["inspect", &index]).is_ok());
+    assert!(is_failed(run(&["inspect", "./test.json"])));
+    assert!(is_usage(run(&["build", "./test.json"])));
+
+    // Other gram sizes are written in the index, and read back from it
+    let trigrams = dir.join("trigrams").to_string_lossy().into_owned();
+    let build = ["build", "./test.json", "--output", &trigrams, "--ngram"];
+    assert!(run(&[&build[..], &["3"]].concat()).is_ok());
+    assert!(is_usage(run(&[&build[..], &["9"]].concat())));
+    let blob = std::fs::read(&trigrams).unwrap();
+    assert_eq!(IndexSections::parse(&blob).unwrap().gram_size, 3);
+    assert!(deserialize_all::<char, NGRAM_INDEX_SIZE>(&blob, &NODE_ARENA, &SUPER_ARENA).is_none());
+    assert!(run(&["inspect", &trigrams]).is_ok());
+    assert!(run(&["query", &trigrams, "poster"]).is_ok());
+
+    // Every filter of a search in the browser
+    assert!(run(&[
+        "query",
+        &index,
+        "poster -kunstplakat",
+        "--category",
+        "Size=50x70",
+        "--tag",
+        "poster",
+        "--feature",
+        "weight=0..",
+        "--price",
+        "..1000",
+        "--order",
+        "Price low to high",
+        "--limit",
+        "3",
+    ])
+    .is_ok());
+    assert!(is_failed(run(&[
+        "query", &index, "poster", "--tag", "nope"
+    ])));
+    assert!(is_failed(run(&[
+        "query",
+        &index,
+        "poster",
+        "--collection",
+        "nope"
+    ])));
+    assert!(is_failed(run(&[
+        "query", &index, "poster", "--order", "nope"
+    ])));
+    assert!(is_usage(run(&["query", &index, "poster", "--price", "10"])));
+    assert!(is_usage(run(&["query", &index])));
+
+    // The same products one per line give an index with the same products
+    let file = std::fs::read_to_string("./test.json").unwrap();
+    let products: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&file).unwrap();
+    let products: Vec<String> = products.values().map(ToString::to_string).collect();
+    std::fs::write(&lines, products.join("\n")).unwrap();
+    let other = dir.join("other").to_string_lossy().into_owned();
+    assert!(run(&["build", &lines, "--format", "lines", "-o", &other]).is_ok());
+    assert!(run(&["diff", &index, &other]).is_ok());
+    assert!(is_usage(run(&["diff", &index])));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,532 @@
+use crate::{
+    classic_indexes::ClassicIndexes,
+    config::finite_f32,
+    data::Product,
+    normalize::TextNormalization,
+    serialize::{Deserializable, Serializable},
+};
+
+/// The queries a merchandising rule is used for
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryPattern {
+    // Rules without a query are used for every search in their context
+    #[default]
+    Any,
+    Exact(String),
+    // The words of the pattern appear in the query, in the same order
+    Contains(String),
+}
+
+impl QueryPattern {
+    fn matches(&self, query: &str, normalization: TextNormalization) -> bool {
+        let query: Vec<&str> = query.split_whitespace().collect();
+        let pattern = match self {
+            QueryPattern::Any => return true,
+            QueryPattern::Exact(pattern) | QueryPattern::Contains(pattern) => {
+                normalization.normalize(pattern)
+            }
+        };
+        let pattern: Vec<&str> = pattern.split_whitespace().collect();
+        match self {
+            QueryPattern::Exact(_) => query == pattern,
+            _ => pattern.is_empty() == false && query.windows(pattern.len()).any(|w| w == pattern),
+        }
+    }
+}
+
+/// A filter that has to be active for a rule to be used
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleContext {
+    Tag(String),
+    Category { category: String, option: String },
+}
+
+/// The products an action applies to
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleTarget {
+    Product(String),
+    Tag(String),
+    Vendor(String),
+}
+
+impl RuleTarget {
+    pub fn matches(&self, product: &Product<'_>, classic: &ClassicIndexes<'_>) -> bool {
+        match self {
+            RuleTarget::Product(id) => product.id == *id,
+            RuleTarget::Vendor(name) => product.vendor.name == *name,
+            RuleTarget::Tag(name) => classic
+                .tags
+                .iter()
+                .any(|tag| tag.name == *name && tag.contains(product)),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    // Positions start at 0, a pinned product is shown even if the query didn't find it
+    Pin {
+        id: String,
+        position: usize,
+    },
+    // Multiplies the confidence of the products, a factor below 1 lowers them instead
+    Boost {
+        target: RuleTarget,
+        #[serde(deserialize_with = "finite_f32")]
+        factor: f32,
+    },
+    // Moves the products after every other result
+    Bury(RuleTarget),
+    Hide(RuleTarget),
+}
+
+// Boost factors are never NaN, they're rejected when the rules are read
+impl Eq for RuleAction {}
+
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MerchandisingRule {
+    #[serde(default)]
+    pub query: QueryPattern,
+    #[serde(default)]
+    pub context: Option<RuleContext>,
+    pub actions: Vec<RuleAction>,
+}
+
+/// Pinned, boosted, buried and hidden products for given queries.
+///
+/// The rules are written in their own section of the index, so they can be changed without touching the products.
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(transparent)]
+pub struct MerchandisingRules(pub Vec<MerchandisingRule>);
+
+impl MerchandisingRules {
+    /// The actions of every rule that applies to a search, `is_active` tells if a context filter is in use
+    pub fn matching<F: Fn(&RuleContext) -> bool>(
+        &self,
+        query: &str,
+        normalization: &TextNormalization,
+        is_active: F,
+    ) -> RuleActions<'_> {
+        let actions = self
+            .0
+            .iter()
+            .filter(|rule| rule.query.matches(query, *normalization))
+            .filter(|rule| rule.context.as_ref().is_none_or(&is_active))
+            .flat_map(|rule| &rule.actions)
+            .collect();
+        RuleActions(actions)
+    }
+}
+
+/// The actions of the rules that matched a search
+pub struct RuleActions<'r>(Vec<&'r RuleAction>);
+
+impl RuleActions<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Applies the boosts to ranked results, and ranks them again
+    pub fn boost(&self, results: &mut [(&Product<'_>, f32)], classic: &ClassicIndexes<'_>) {
+        let mut boosted = false;
+        for action in &self.0 {
+            let RuleAction::Boost { target, factor } = action else {
+                continue;
+            };
+            for (product, confidence) in results.iter_mut() {
+                if target.matches(product, classic) {
+                    *confidence *= factor;
+                    boosted = true;
+                }
+            }
+        }
+        if boosted {
+            results.sort_by(|(_, a), (_, b)| {
+                a.partial_cmp(b)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+                    .reverse()
+            });
+        }
+    }
+
+    /// Hides, buries and pins products in the final results, `allowed` decides if a pinned product passes the active filters
+    pub fn arrange<F: Fn(&Product<'_>) -> bool>(
+        &self,
+        results: &mut Vec<usize>,
+        products: &[Product<'_>],
+        classic: &ClassicIndexes<'_>,
+        allowed: F,
+    ) {
+        let matches_any =
+            |product: &Product<'_>, wanted: fn(&RuleAction) -> Option<&RuleTarget>| {
+                self.0
+                    .iter()
+                    .filter_map(|action| wanted(action))
+                    .any(|target| target.matches(product, classic))
+            };
+        let hidden = |product: &Product<'_>| {
+            matches_any(product, |action| match action {
+                RuleAction::Hide(target) => Some(target),
+                _ => None,
+            })
+        };
+        let buried = |product: &Product<'_>| {
+            matches_any(product, |action| match action {
+                RuleAction::Bury(target) => Some(target),
+                _ => None,
+            })
+        };
+
+        results.retain(|id| products.get(*id).is_some_and(|p| hidden(p) == false));
+        // Stable, so both parts keep their order
+        let (mut kept, sunk): (Vec<usize>, Vec<usize>) = results
+            .iter()
+            .partition(|id| buried(&products[**id]) == false);
+        kept.extend(sunk);
+        *results = kept;
+
+        let mut pins: Vec<(&str, usize)> = self
+            .0
+            .iter()
+            .filter_map(|action| match action {
+                RuleAction::Pin { id, position } => Some((id.as_str(), *position)),
+                _ => None,
+            })
+            .collect();
+        pins.sort_by_key(|(_, position)| *position);
+        for (id, position) in pins {
+            let Some(product) = classic.lookup.product(id).and_then(|id| products.get(id)) else {
+                continue;
+            };
+            if hidden(product) || allowed(product) == false {
+                continue;
+            }
+            results.retain(|other| *other != product.serialization_id);
+            results.insert(position.min(results.len()), product.serialization_id);
+        }
+    }
+}
+
+impl Serializable for QueryPattern {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        match self {
+            QueryPattern::Any => 0u8.serialize(output),
+            QueryPattern::Exact(pattern) => {
+                1u8.serialize(output);
+                pattern.serialize(output);
+            }
+            QueryPattern::Contains(pattern) => {
+                2u8.serialize(output);
+                pattern.serialize(output);
+            }
+        }
+    }
+}
+
+impl Deserializable for QueryPattern {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (input, id) = u8::deserialize(input)?;
+        match id {
+            0 => Some((input, QueryPattern::Any)),
+            1 => {
+                let (input, pattern) = String::deserialize(input)?;
+                Some((input, QueryPattern::Exact(pattern)))
+            }
+            2 => {
+                let (input, pattern) = String::deserialize(input)?;
+                Some((input, QueryPattern::Contains(pattern)))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Serializable for RuleContext {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        match self {
+            RuleContext::Tag(name) => {
+                0u8.serialize(output);
+                name.serialize(output);
+            }
+            RuleContext::Category { category, option } => {
+                1u8.serialize(output);
+                category.serialize(output);
+                option.serialize(output);
+            }
+        }
+    }
+}
+
+impl Deserializable for RuleContext {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (input, id) = u8::deserialize(input)?;
+        match id {
+            0 => {
+                let (input, name) = String::deserialize(input)?;
+                Some((input, RuleContext::Tag(name)))
+            }
+            1 => {
+                let (input, category) = String::deserialize(input)?;
+                let (input, option) = String::deserialize(input)?;
+                Some((input, RuleContext::Category { category, option }))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Serializable for RuleTarget {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        let (id, name) = match self {
+            RuleTarget::Product(name) => (0u8, name),
+            RuleTarget::Tag(name) => (1u8, name),
+            RuleTarget::Vendor(name) => (2u8, name),
+        };
+        id.serialize(output);
+        name.serialize(output);
+    }
+}
+
+impl Deserializable for RuleTarget {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (input, id) = u8::deserialize(input)?;
+        let (input, name) = String::deserialize(input)?;
+        let target = match id {
+            0 => RuleTarget::Product(name),
+            1 => RuleTarget::Tag(name),
+            2 => RuleTarget::Vendor(name),
+            _ => return None,
+        };
+        Some((input, target))
+    }
+}
+
+impl Serializable for RuleAction {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        match self {
+            RuleAction::Pin { id, position } => {
+                0u8.serialize(output);
+                id.serialize(output);
+                position.serialize(output);
+            }
+            RuleAction::Boost { target, factor } => {
+                1u8.serialize(output);
+                target.serialize(output);
+                factor.serialize(output);
+            }
+            RuleAction::Bury(target) => {
+                2u8.serialize(output);
+                target.serialize(output);
+            }
+            RuleAction::Hide(target) => {
+                3u8.serialize(output);
+                target.serialize(output);
+            }
+        }
+    }
+}
+
+impl Deserializable for RuleAction {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (input, id) = u8::deserialize(input)?;
+        match id {
+            0 => {
+                let (input, product_id) = String::deserialize(input)?;
+                let (input, position) = usize::deserialize(input)?;
+                Some((
+                    input,
+                    RuleAction::Pin {
+                        id: product_id,
+                        position,
+                    },
+                ))
+            }
+            1 => {
+                let (input, target) = RuleTarget::deserialize(input)?;
+                let (input, factor) = f32::deserialize(input).filter(|(_, f)| f.is_finite())?;
+                Some((input, RuleAction::Boost { target, factor }))
+            }
+            2 => {
+                let (input, target) = RuleTarget::deserialize(input)?;
+                Some((input, RuleAction::Bury(target)))
+            }
+            3 => {
+                let (input, target) = RuleTarget::deserialize(input)?;
+                Some((input, RuleAction::Hide(target)))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Serializable for MerchandisingRule {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        self.query.serialize(output);
+        self.context.serialize(output);
+        (&self.actions).serialize(output);
+    }
+}
+
+impl Deserializable for MerchandisingRule {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (input, query) = QueryPattern::deserialize(input)?;
+        let (input, context) = Deserializable::deserialize(input)?;
+        let (input, actions) = Vec::deserialize(input)?;
+        Some((
+            input,
+            MerchandisingRule {
+                query,
+                context,
+                actions,
+            },
+        ))
+    }
+}
+
+impl Serializable for MerchandisingRules {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        (&self.0).serialize(output);
+    }
+}
+
+impl Deserializable for MerchandisingRules {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (input, rules) = Vec::deserialize(input)?;
+        Some((input, MerchandisingRules(rules)))
+    }
+}
+
+#[test]
+fn test_merchandising_rules() {
+    use crate::{
+        config::IndexConfig,
+        testing::{build_test_index, test_products},
+    };
+
+    let products = test_products();
+    let pinned = products.last().unwrap().id.to_string();
+    let rules = format!(
+        r#"[
+            {{ "query": {{ "exact": "Kunst" }}, "actions": [{{ "pin": {{ "id": "{pinned}", "position": 1 }} }}] }},
+            {{ "query": {{ "contains": "plakat" }}, "actions": [{{ "hide": {{ "product": "{pinned}" }} }}] }},
+            {{ "context": {{ "tag": "Sale" }}, "actions": [{{ "bury": {{ "vendor": "Nobody" }} }}] }}
+        ]"#
+    );
+    let config = IndexConfig {
+        rules: serde_json::from_str(&rules).unwrap(),
+        ..IndexConfig::default()
+    };
+    let built = build_test_index(products, config.clone());
+    let (container, classic) = (built.container, &built.classic);
+    assert_eq!(container.config.rules, config.rules);
+    // Factors have to be finite, for the rules to equal themselves
+    let boost = |factor: &str| {
+        let rules = format!(
+            r#"[{{ "actions": [{{ "boost": {{ "target": {{ "vendor": "Nobody" }}, "factor": {factor} }} }}] }}]"#
+        );
+        serde_json::from_str::<MerchandisingRules>(&rules)
+    };
+    assert!(boost("2.5").is_ok());
+    assert!(boost("1e39").is_err());
+
+    let normalization = &config.normalization;
+    let no_context = |_: &RuleContext| false;
+    let rules: &MerchandisingRules = &container.config.rules;
+    assert!(
+        rules
+            .matching("kunst", normalization, no_context)
+            .is_empty()
+            == false
+    );
+    assert!(rules
+        .matching("kunst tryk", normalization, no_context)
+        .is_empty());
+    assert!(
+        rules
+            .matching("sort plakat", normalization, no_context)
+            .is_empty()
+            == false
+    );
+    assert!(rules.matching("tryk", normalization, |_| true).is_empty() == false);
+
+    let mut results: Vec<usize> = built
+        .index
+        .search("kunst".chars())
+        .into_iter()
+        .map(|(product, _)| product.serialization_id)
+        .collect();
+    let pinned_id = container
+        .products
+        .iter()
+        .find(|product| product.id == pinned)
+        .unwrap()
+        .serialization_id;
+    rules.matching("kunst", normalization, no_context).arrange(
+        &mut results,
+        &container.products,
+        classic,
+        |_| true,
+    );
+    assert_eq!(results[1], pinned_id);
+    assert_eq!(results.iter().filter(|id| **id == pinned_id).count(), 1);
+
+    rules
+        .matching("sort plakat", normalization, no_context)
+        .arrange(&mut results, &container.products, classic, |_| true);
+    assert!(results.contains(&pinned_id) == false);
+}
+
+#[test]
+fn test_pins_pass_filters() {
+    use crate::{
+        config::IndexConfig,
+        js_interactable::{CategoryHandler, FeatureFilter, TagHandler},
+        ngram::SearchStats,
+        testing::{build_test_index, test_products},
+        LoadedIndex, SearchOptions,
+    };
+
+    let products = test_products();
+    let pinned = products.last().unwrap().id.to_string();
+    let rules = format!(
+        r#"[{{ "query": {{ "exact": "Kunst" }}, "actions": [{{ "pin": {{ "id": "{pinned}", "position": 1 }} }}] }}]"#
+    );
+    let config = IndexConfig {
+        rules: serde_json::from_str(&rules).unwrap(),
+        ..IndexConfig::default()
+    };
+    let built = build_test_index(products, config.clone());
+    let pinned = built.index.container.products.last().unwrap();
+    let index = LoadedIndex::Eager(built.index);
+    let classic = std::sync::Arc::new(built.classic);
+
+    let categories = CategoryHandler::new(classic.clone());
+    let tags = TagHandler::new(classic.clone());
+    let search = |query: &str, features: &[FeatureFilter]| {
+        let options = SearchOptions {
+            categories: &categories,
+            tags: &tags,
+            features,
+            order: None,
+            currency: None,
+            collection: None,
+            tolerance: &config.typo_tolerance,
+        };
+        index
+            .search_filtered(query, &classic, &options, &SearchStats::default())
+            .unwrap()
+            .ids
+    };
+    assert_eq!(search("kunst", &[])[1], pinned.serialization_id);
+
+    // Pins only show up in searches whose filters they pass, like the price or the excluded terms
+    let free = FeatureFilter::new_range(None, Some(0.0), "price".to_string());
+    assert!(search("kunst", &[free]).is_empty());
+    let word = pinned.title.split_whitespace().next().unwrap();
+    let excluded = search(&format!("kunst -{word}"), &[]);
+    assert!(excluded.contains(&pinned.serialization_id) == false);
+}
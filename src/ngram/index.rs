@@ -2,20 +2,34 @@ use std::{fmt::Debug, hash::Hash};
 
 use ahash::AHashMap;
 
-use crate::{
-    data::ProductContainer,
-    serialize::{Deserializable, Serializable},
-};
+use crate::serialize::{Deserializable, Serializable};
 
-use super::result_ranker::{HashExtractable, ResultRanker};
+use super::{
+    result_ranker::HashExtractable,
+    tree::{GramSource, GramTreeNode},
+};
 
-pub trait GramAtom: Default + Copy + Eq + Hash + Debug + Serializable + Deserializable {}
+pub trait GramAtom:
+    Default + Copy + Eq + Ord + Hash + Debug + Serializable + Deserializable
+{
+}
 
 impl<'a, T> GramAtom for T where
-    T: Copy + Default + Eq + Hash + Debug + Serializable + Deserializable
+    T: Copy + Default + Eq + Ord + Hash + Debug + Serializable + Deserializable
 {
 }
 
+/// What a gram index holds, kept in a container the index refers to
+pub trait IndexedData: Ord {
+    type Container;
+
+    // How many there are in the container, which grams are pruned relative to
+    fn count(container: &Self::Container) -> usize;
+
+    // The position in the container, which postings are serialized as
+    fn serialization_id(&self) -> usize;
+}
+
 pub struct IndexFeed<'a, G: GramAtom, GI, Data>
 where
     GI: Iterator<Item = G> + Clone,
@@ -59,15 +73,15 @@ impl<G: GramAtom> PartialOrd for GramNode<'_, G> {
 }
 
 #[derive(PartialEq, Eq)]
-pub struct GramIndex<'a, G: GramAtom, Data: Ord, const N: usize = 8> {
+pub struct GramIndex<'a, G: GramAtom, Data: IndexedData, const N: usize = 8> {
     // Note we don't need popularity since we'll search for all parts of the gram
     pub roots: AHashMap<G, &'a GramNode<'a, G>>,
     pub data: AHashMap<[G; N], Vec<&'a Data>>,
-    pub product_container: &'a ProductContainer<'a>,
+    pub container: &'a Data::Container,
 }
 
-impl<'a, G: GramAtom, Data: Ord + HashExtractable + Debug, const N: usize>
-    GramIndex<'a, G, Data, N>
+impl<G: GramAtom, Data: IndexedData + HashExtractable + Debug, const N: usize>
+    GramIndex<'_, G, Data, N>
 {
     pub fn most_popular_chain(&self, input: G) -> Vec<G> {
         let mut out = vec![];
@@ -80,182 +94,52 @@ impl<'a, G: GramAtom, Data: Ord + HashExtractable + Debug, const N: usize>
     }
 
     pub fn search<I: Iterator<Item = G>>(&self, input: I) -> Vec<(&Data, f32)> {
-        let mut results = ResultRanker::new();
-        let mut ngram = [G::default(); N];
-        for gram in input {
-            for i in 1..N {
-                ngram[i - 1] = ngram[i];
-            }
-            ngram[N - 1] = gram;
-            let (ngram, confidence) = match self.search_gram(ngram) {
-                Some(v) => v,
-                None => continue,
-            };
-            let data = match self.data.get(&ngram) {
-                Some(v) => v,
-                None => continue,
-            };
-            for data in data {
-                results.add(*data, confidence);
-            }
-        }
-        results.export_data_by_confidence()
+        GramSource::search(self, input)
     }
 
-    /*
-    For the current step in the tree:
-        The user entered gram
-        No Gram
-        The 5 most popular grams
-
-    And these are tested on:
-        This node
-        Last node
-
-    If skip limit is reached, we don't do the previous node or no node
-    */
-
     pub fn search_gram(&self, query: [G; N]) -> Option<([G; N], f32)> {
-        let root_node = self.roots.get(query.get(0)?)?;
-        let mut previous = [G::default(); N];
-        previous[0] = query[0];
-        let changes_limit = u8::try_from(N / 3).unwrap();
-        Self::recursive_search(
-            &query[1..],
-            root_node,
-            None,
-            0,
-            changes_limit,
-            1.0,
-            previous,
-            1,
-        )
+        GramSource::search_gram(self, query)
     }
+}
 
-    #[allow(clippy::too_many_arguments)]
-    fn recursive_search(
-        input: &[G],
-        node: &GramNode<'_, G>,
-        previous_node: Option<&GramNode<'_, G>>,
-        changes: u8,
-        changes_limit: u8,
-        cummulative_weight: f32,
-        previous_input: [G; N],
-        index: usize,
-    ) -> Option<([G; N], f32)>
-    where
-        G: 'a,
-        Data: 'a,
-    {
-        if index == N {
-            return Some((previous_input, cummulative_weight));
-        }
-
-        let best_result = |a: Option<([G; N], f32)>, b: Option<([G; N], f32)>| {
-            match (a, b) {
-                // If only one exists, we use the existing
-                (Some(v), None) | (None, Some(v)) => Some(v),
-                // If none exist, we don't have any
-                (None, None) => None,
-                (Some(a), Some(b)) => Some(if a.1 > b.1 { a } else { b }),
-            }
-        };
-
-        let user_entered = input.get(0).and_then(|gram| node.items.get(gram));
-        let user_entered_gram = user_entered.map(|v| v.item);
-
-        let mut most_likely = None;
-
-        let next_input = if input.is_empty() { &[] } else { &input[1..] };
+impl<'a, G: GramAtom> GramTreeNode<G> for &'a GramNode<'a, G> {
+    fn item(&self) -> G {
+        self.item
+    }
 
-        // We try the current options, on the current node
-        if let Some(user_entered) = user_entered {
-            let mut previous_input = previous_input;
-            previous_input[index] = user_entered.item;
+    fn weight(&self) -> f32 {
+        self.weight
+    }
 
-            let found = Self::recursive_search(
-                next_input,
-                user_entered,
-                Some(node),
-                changes,
-                changes_limit,
-                cummulative_weight * user_entered.weight,
-                previous_input,
-                index + 1,
-            );
+    fn child(&self, gram: &G) -> Option<Self> {
+        self.items.get(gram).copied()
+    }
 
-            // If we found something, we keep the most likely
-            most_likely = best_result(found, most_likely);
-        }
+    fn children(&self) -> impl Iterator<Item = Self> {
+        self.by_occurances.iter().copied()
+    }
+}
 
-        // If more skips are allowed, we try those
-        if changes_limit > changes {
-            let changes = changes + 1;
+impl<'a, G: GramAtom, Data: IndexedData + HashExtractable, const N: usize> GramSource<G, N>
+    for GramIndex<'a, G, Data, N>
+{
+    type Data = Data;
+    type Node<'s>
+        = &'a GramNode<'a, G>
+    where
+        Self: 's;
 
-            // We try the most popular options at this node
-            let most_popular = node
-                .by_occurances
-                .iter()
-                // We only want grams that do not match the
-                .filter(|potential| Some(potential.item) != user_entered_gram)
-                .copied();
-
-            for next_node in most_popular {
-                let mut previous_input = previous_input;
-                previous_input[index] = next_node.item;
-
-                let found = Self::recursive_search(
-                    next_input,
-                    next_node,
-                    Some(node),
-                    changes,
-                    changes_limit,
-                    cummulative_weight * next_node.weight,
-                    previous_input,
-                    index + 1,
-                );
-
-                // We also try running this with the same input as we got, thereby compensating for forgotten grams
-                let repeat_found = Self::recursive_search(
-                    input,
-                    next_node,
-                    Some(node),
-                    changes + 1,
-                    changes_limit,
-                    cummulative_weight * next_node.weight,
-                    previous_input,
-                    index + 1,
-                );
-
-                // If we found something, we keep the most likely
-                most_likely = best_result(best_result(found, repeat_found), most_likely);
-            }
+    fn root(&self, gram: &G) -> Option<Self::Node<'_>> {
+        self.roots.get(gram).copied()
+    }
 
-            // We try ignoring the previous gram, in case the user entered "abc" when they meant "ac"
-            if let Some(current_input) = input.get(0) {
-                if let Some(Some(last)) = previous_node.map(|node| node.items.get(current_input)) {
-                    let mut previous_input = previous_input;
-                    // We overwrite the old gram
-                    previous_input[index - 1] = last.item;
-
-                    let found = Self::recursive_search(
-                        next_input,
-                        *last,
-                        None,
-                        changes,
-                        changes_limit,
-                        cummulative_weight,
-                        previous_input,
-                        // We use the same index since we really looked at index - 1
-                        index,
-                    );
-
-                    most_likely = best_result(found, most_likely);
-                }
-            }
+    fn for_each_posting<'s, F: FnMut(&'s Data)>(&'s self, key: &[G; N], mut found: F)
+    where
+        Data: 's,
+    {
+        for data in self.data.get(key).into_iter().flatten() {
+            found(data);
         }
-
-        most_likely
     }
 }
 
@@ -263,10 +147,25 @@ impl<'a, G: GramAtom, Data: Ord + HashExtractable + Debug, const N: usize>
 mod test {
     use crate::data::Product;
 
-    use super::GramIndex;
+    use super::{GramIndex, GramNode};
+    use crate::preprocessor::{GramPruning, GramStatistics};
 
-    fn make_index<'a, const N: usize>(
-    ) -> Result<GramIndex<'a, char, Product<'a>, N>, Box<dyn std::error::Error>> {
+    fn make_index<const N: usize>(
+    ) -> Result<GramIndex<'static, char, Product<'static>, N>, Box<dyn std::error::Error>> {
+        let (index, _) = make_index_with_pruning(&GramPruning::default())?;
+        Ok(index)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn make_index_with_pruning<const N: usize>(
+        pruning: &GramPruning,
+    ) -> Result<
+        (
+            GramIndex<'static, char, Product<'static>, N>,
+            GramStatistics<char, N>,
+        ),
+        Box<dyn std::error::Error>,
+    > {
         use crate::data::{optimize, RawProduct, SuperAlloc};
         use colosseum::sync::Arena;
 
@@ -301,9 +200,148 @@ mod test {
 
         let arena = SUPER_ARENA.alloc(Arena::new());
 
-        let index: GramIndex<char, Product, N> = GramIndex::index_from(iter, arena, prods);
+        Ok(GramIndex::index_from_with_pruning(
+            iter, arena, prods, pruning,
+        ))
+    }
 
-        Ok(index)
+    fn full_length_paths<const N: usize>(
+        node: &GramNode<'_, char>,
+        path: &mut Vec<char>,
+        out: &mut Vec<[char; N]>,
+    ) {
+        path.push(node.item);
+        if let Ok(key) = <[char; N]>::try_from(&path[..]) {
+            out.push(key);
+        } else {
+            for child in &node.by_occurances {
+                full_length_paths(child, path, out);
+            }
+        }
+        path.pop();
+    }
+
+    #[test]
+    fn test_pruning() -> Result<(), Box<dyn std::error::Error>> {
+        let pruning = GramPruning {
+            max_document_frequency: 0.3,
+            min_documents: 2,
+        };
+        let (index, statistics) = make_index_with_pruning::<5>(&pruning)?;
+
+        assert!(statistics.pruned().next().is_some());
+        for frequency in &statistics.grams {
+            assert_eq!(index.data.contains_key(&frequency.gram), frequency.kept);
+        }
+
+        // The tree only leads to grams that are still in the data
+        let mut paths = Vec::new();
+        for root in index.roots.values() {
+            full_length_paths::<5>(root, &mut Vec::new(), &mut paths);
+        }
+        assert!(paths.is_empty() == false);
+        for path in paths {
+            assert!(index.data.contains_key(&path), "{path:?} was pruned");
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_typo_tolerance() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::ngram::{GramSource, SearchStats, TypoEdits, TypoTolerance};
+
+        let index = make_index::<5>()?;
+        let explored = |tolerance: TypoTolerance, query: &str| {
+            let stats = SearchStats::default();
+            let results =
+                index.search_with(tolerance.apply(query, 5).into_iter(), &tolerance, &stats);
+            (results.len(), stats.explored_nodes())
+        };
+
+        let off = TypoTolerance {
+            edits: TypoEdits::Off,
+            ..TypoTolerance::default()
+        };
+        let narrow = TypoTolerance {
+            max_branching: 2,
+            ..TypoTolerance::default()
+        };
+        let (found, fuzzy_cost) = explored(TypoTolerance::default(), "kunst plakat");
+        let (_, exact_cost) = explored(off, "kunst plakat");
+        let (_, narrow_cost) = explored(narrow, "kunst plakat");
+        let (_, max_cost) = explored(
+            TypoTolerance {
+                edits: TypoEdits::Max,
+                ..TypoTolerance::default()
+            },
+            "kunst plakat",
+        );
+        assert!(found > 0);
+        assert!(exact_cost < narrow_cost && narrow_cost < fuzzy_cost && fuzzy_cost < max_cost);
+
+        // A typo is only forgiven in words long enough
+        let (typo_found, _) = explored(TypoTolerance::default(), "unerstuod");
+        let (short_found, _) = explored(
+            TypoTolerance {
+                min_word_length: 10,
+                ..TypoTolerance::default()
+            },
+            "unerstuod",
+        );
+        assert!(typo_found > short_found);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_exact_matches() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::ngram::GramSource;
+
+        let keep_all = GramPruning {
+            max_document_frequency: 1.0,
+            min_documents: 0,
+        };
+        let (index, _) = make_index_with_pruning::<5>(&keep_all)?;
+        let containing = |phrase: &str| -> Vec<usize> {
+            let mut ids: Vec<usize> = index
+                .container
+                .products
+                .iter()
+                .filter(|p| {
+                    let text = [&p.description, &p.title, &p.vendor.name]
+                        .into_iter()
+                        .flat_map(|s| s.chars())
+                        .flat_map(char::to_lowercase)
+                        .collect::<String>();
+                    text.contains(phrase)
+                })
+                .map(|p| p.serialization_id)
+                .collect();
+            ids.sort_unstable();
+            ids
+        };
+        let exact = |phrase: &str| -> Vec<usize> {
+            let mut ids: Vec<usize> = index
+                .exact_matches(phrase.chars())
+                .into_iter()
+                .map(|p| p.serialization_id)
+                .collect();
+            ids.sort_unstable();
+            ids
+        };
+
+        for phrase in ["kunst", "plakat", "kunstplakat"] {
+            assert!(exact(phrase).is_empty() == false);
+            assert_eq!(exact(phrase), containing(phrase), "{phrase}");
+        }
+        // Short phrases can't be found right at the end of a text
+        let short = exact("kun");
+        assert!(short.is_empty() == false);
+        assert!(short.iter().all(|id| containing("kun").contains(id)));
+        assert!(exact("kunzt").is_empty());
+
+        Ok(())
     }
 
     #[test]
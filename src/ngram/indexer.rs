@@ -1,11 +1,11 @@
 use std::{cell::RefCell, collections::VecDeque, rc::Rc};
 
-use ahash::AHashMap;
+use ahash::{AHashMap, AHashSet};
 use colosseum::sync::Arena;
 
-use crate::data::ProductContainer;
+use crate::preprocessor::{GramPruning, GramStatistics};
 
-use super::{GramAtom, GramIndex, GramNode, IndexFeed};
+use super::{GramAtom, GramIndex, GramNode, IndexFeed, IndexedData};
 
 #[derive(Debug, Clone)]
 struct InnerMutableGramNode<G: GramAtom> {
@@ -19,8 +19,6 @@ fn occurances_to_weight(total: u32, this: u32) -> f32 {
     (this as f32) / (total as f32)
 }
 
-const DATA_CUTOFF_PERCENTAGE: f32 = 0.8;
-
 #[derive(Clone, Debug)]
 struct MutableGramNode<G: GramAtom>(Rc<RefCell<InnerMutableGramNode<G>>>);
 
@@ -33,6 +31,26 @@ impl<G: GramAtom> MutableGramNode<G> {
         })))
     }
 
+    // Removes the full length paths that lead to a pruned gram
+    fn prune<const N: usize>(&self, path: &mut Vec<G>, pruned: &AHashSet<[G; N]>) {
+        let mut me = self.0.borrow_mut();
+        path.push(me.item);
+        if path.len() + 1 == N {
+            me.items.retain(|gram, _| {
+                path.push(*gram);
+                let keep = <[G; N]>::try_from(&path[..])
+                    .map_or(true, |key| pruned.contains(&key) == false);
+                path.pop();
+                keep
+            });
+        } else {
+            for child in me.items.values() {
+                child.prune(path, pruned);
+            }
+        }
+        path.pop();
+    }
+
     pub fn immutalize<'arena>(
         &self,
         parent_occurances: u32,
@@ -48,7 +66,8 @@ impl<G: GramAtom> MutableGramNode<G> {
             .map(|(_, v)| v.immutalize(me.occurances, arena))
             .collect();
 
-        by_occurances.sort_by(|a, b| a.cmp(b).reverse());
+        // Ties are broken by the gram itself, so the tree is laid out the same way on every build
+        by_occurances.sort_by(|a, b| a.cmp(b).reverse().then_with(|| a.item.cmp(&b.item)));
 
         let items =
             by_occurances
@@ -67,12 +86,32 @@ impl<G: GramAtom> MutableGramNode<G> {
     }
 }
 
-impl<'a, G: GramAtom, Data: Ord, const N: usize> GramIndex<'a, G, Data, N> {
+impl<'a, G: GramAtom, Data: IndexedData, const N: usize> GramIndex<'a, G, Data, N> {
     pub fn index_from<'arena, I, S>(
         source_iter: S,
         node_arena: &'arena Arena<GramNode<'arena, G>>,
-        product_container: &'arena ProductContainer<'arena>,
+        container: &'arena Data::Container,
     ) -> GramIndex<'arena, G, Data, N>
+    where
+        I: Iterator<Item = G> + Clone,
+        S: Iterator<Item = IndexFeed<'arena, G, I, Data>>,
+    {
+        let (index, _) = Self::index_from_with_pruning(
+            source_iter,
+            node_arena,
+            container,
+            &GramPruning::default(),
+        );
+        index
+    }
+
+    /// Indexes the source, leaving out the grams the pruning rejects from both the tree and the data
+    pub fn index_from_with_pruning<'arena, I, S>(
+        source_iter: S,
+        node_arena: &'arena Arena<GramNode<'arena, G>>,
+        container: &'arena Data::Container,
+        pruning: &GramPruning,
+    ) -> (GramIndex<'arena, G, Data, N>, GramStatistics<G, N>)
     where
         I: Iterator<Item = G> + Clone,
         S: Iterator<Item = IndexFeed<'arena, G, I, Data>>,
@@ -131,6 +170,22 @@ impl<'a, G: GramAtom, Data: Ord, const N: usize> GramIndex<'a, G, Data, N> {
             }
         }
 
+        // We sort the data for easier access later, which also puts the duplicates of a product next to each other
+        for (_, data) in data_map.iter_mut() {
+            data.sort();
+        }
+
+        // We remove the grams that occur in too many or too few products, since they don't carry enough information
+        let statistics = GramStatistics::collect(&data_map, Data::count(container), pruning);
+        let pruned: AHashSet<[G; N]> = statistics.pruned().copied().collect();
+        data_map.retain(|gram, _| pruned.contains(gram) == false);
+        if pruned.is_empty() == false {
+            let mut path = Vec::with_capacity(N);
+            for node in root.values() {
+                node.prune(&mut path, &pruned);
+            }
+        }
+
         let total_root_occurances = root
             .values()
             .map(|v| v.0.borrow().occurances)
@@ -143,23 +198,11 @@ impl<'a, G: GramAtom, Data: Ord, const N: usize> GramIndex<'a, G, Data, N> {
             .map(|(k, v)| (k, v.immutalize(total_root_occurances, node_arena)))
             .collect();
 
-        // We remove the data if it contains more than DATA_CUTOFF percent of products, since it doesn't carry enough information
-        let products_amount = product_container.products.len();
-        #[allow(clippy::cast_precision_loss)]
-        let maximum_product_amount = (products_amount as f32) * DATA_CUTOFF_PERCENTAGE;
-        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-        let maximum_product_amount = maximum_product_amount as usize;
-        data_map.retain(|_, data| data.len() < maximum_product_amount);
-
-        // We also sort the data for easier access later
-        for (_, data) in data_map.iter_mut() {
-            data.sort();
-        }
-
-        GramIndex {
+        let index = GramIndex {
             roots,
             data: data_map,
-            product_container,
-        }
+            container,
+        };
+        (index, statistics)
     }
 }
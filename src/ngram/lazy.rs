@@ -0,0 +1,181 @@
+use std::marker::PhantomData;
+
+use crate::{
+    data::{Product, ProductContainer, SuperAlloc},
+    serialize::{read_node, GramDataSection, GramTreeSection, IndexSections, NodeRecord, Section},
+};
+
+use super::{GramAtom, GramSource, GramTreeNode};
+
+/// A gram index that searches the serialized bytes in place.
+///
+/// Only the product container is decoded up front, since result cards and filters need it.
+/// Its products are decoded all at once, as the classic indexes hold references to every one of them.
+/// Tree nodes are decoded as the search walks them, and postings when a gram is found.
+pub struct LazyGramIndex<'a, G: GramAtom, const N: usize> {
+    tree: GramTreeSection<'a>,
+    data: GramDataSection<'a>,
+    pub product_container: &'a ProductContainer<'a>,
+    grams: PhantomData<G>,
+}
+
+impl<'a, G: GramAtom, const N: usize> LazyGramIndex<'a, G, N> {
+    pub fn load(
+        sections: &IndexSections<'a>,
+        super_alloc: &'static SuperAlloc,
+    ) -> Option<LazyGramIndex<'a, G, N>> {
+        if sections.gram_size != N {
+            return None;
+        }
+        let container = sections.products(super_alloc)?;
+
+        Some(LazyGramIndex {
+            tree: GramTreeSection::parse(sections.get(Section::GramTree))?,
+            data: GramDataSection::parse(sections.get(Section::GramData))?,
+            product_container: container,
+            grams: PhantomData,
+        })
+    }
+
+    pub fn search<I: Iterator<Item = G>>(&self, input: I) -> Vec<(&Product<'a>, f32)> {
+        GramSource::search(self, input)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct LazyGramNode<'a, G: GramAtom> {
+    nodes: &'a [u8],
+    record: NodeRecord<'a, G>,
+}
+
+impl<'a, G: GramAtom> LazyGramNode<'a, G> {
+    pub(crate) fn new(nodes: &'a [u8], record: NodeRecord<'a, G>) -> Self {
+        LazyGramNode { nodes, record }
+    }
+}
+
+impl<G: GramAtom> GramTreeNode<G> for LazyGramNode<'_, G> {
+    fn item(&self) -> G {
+        self.record.item
+    }
+
+    fn weight(&self) -> f32 {
+        self.record.weight
+    }
+
+    fn child(&self, gram: &G) -> Option<Self> {
+        self.children().find(|child| child.record.item == *gram)
+    }
+
+    fn children(&self) -> impl Iterator<Item = Self> {
+        let nodes = self.nodes;
+        self.record.child_offsets().filter_map(move |offset| {
+            let record = read_node(nodes, offset)?;
+            Some(LazyGramNode { nodes, record })
+        })
+    }
+}
+
+impl<'a, G: GramAtom, const N: usize> GramSource<G, N> for LazyGramIndex<'a, G, N> {
+    type Data = Product<'a>;
+    type Node<'s>
+        = LazyGramNode<'a, G>
+    where
+        Self: 's;
+
+    fn root(&self, gram: &G) -> Option<Self::Node<'_>> {
+        let record = self.tree.find_root(gram)?;
+        Some(LazyGramNode::new(self.tree.nodes, record))
+    }
+
+    fn for_each_posting<'s, F: FnMut(&'s Self::Data)>(&'s self, key: &[G; N], mut found: F)
+    where
+        Self::Data: 's,
+    {
+        let products = &self.product_container.products;
+        for id in self.data.find_postings(key).into_iter().flatten() {
+            if let Some(product) = products.get(id) {
+                found(product);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_lazy_search_matches_eager() {
+    use crate::{
+        config::IndexConfig,
+        serialize::{deserialize_all, Deserializable, Serializable},
+        testing::{build_test_index, test_products, TEST_ARENA},
+    };
+    use colosseum::sync::Arena;
+
+    let built = build_test_index(test_products(), IndexConfig::default());
+
+    let node_arena = Arena::new();
+    let (eager, _): (super::GramIndex<char, Product, 5>, _) =
+        deserialize_all(&built.blob, &node_arena, &TEST_ARENA).unwrap();
+
+    let sections = IndexSections::parse(&built.blob).unwrap();
+    let lazy: LazyGramIndex<char, 5> = LazyGramIndex::load(&sections, &TEST_ARENA).unwrap();
+
+    let by_id = |results: Vec<(&Product, f32)>| {
+        let mut results: Vec<_> = results
+            .into_iter()
+            .map(|(p, confidence)| (p.serialization_id, confidence.to_bits()))
+            .collect();
+        results.sort_unstable();
+        results
+    };
+
+    for query in ["kunst", "plakat", "blå", "unerstuod hvzdom", "x"] {
+        let eager_results = by_id(eager.search(query.chars()));
+        let lazy_results = by_id(lazy.search(query.chars()));
+        assert_eq!(eager_results, lazy_results, "Results differ for {query}");
+    }
+
+    // A data section claiming fewer entries than it has finds nothing, rather than panicking
+    let data = sections.get(Section::GramData);
+    let (key, postings) = GramDataSection::parse(data)
+        .unwrap()
+        .entries::<char, 5>()
+        .unwrap()[20]
+        .clone();
+    let (entries, _) = usize::deserialize(data).unwrap();
+    let mut broken = Vec::new();
+    0usize.serialize(&mut |byte| broken.push(byte));
+    broken.extend_from_slice(entries);
+    let broken = GramDataSection::parse(&broken).unwrap();
+    assert_eq!(
+        GramDataSection::parse(data).unwrap().find_postings(&key),
+        Some(postings)
+    );
+    assert_eq!(broken.find_postings(&key), None);
+
+    // And one claiming more entries than it could hold is read as broken, without reserving room for them
+    let mut broken = Vec::new();
+    (usize::MAX / 2).serialize(&mut |byte| broken.push(byte));
+    broken.extend_from_slice(entries);
+    let broken = GramDataSection::parse(&broken).unwrap();
+    assert_eq!(broken.entries::<char, 5>(), None);
+}
+
+#[test]
+fn test_lazy_node_cannot_be_its_own_child() {
+    use crate::serialize::Serializable;
+
+    // A tree with one root, which lists itself as its child
+    let mut tree = Vec::new();
+    1usize.serialize(&mut |byte| tree.push(byte));
+    tree.extend(0u32.to_be_bytes());
+    let mut save = |byte| tree.push(byte);
+    'a'.serialize(&mut save);
+    1.0f32.serialize(&mut save);
+    1usize.serialize(&mut save);
+    0usize.serialize(&mut save);
+
+    let tree = GramTreeSection::parse(&tree).unwrap();
+    let root = LazyGramNode::new(tree.nodes, tree.find_root(&'a').unwrap());
+    assert_eq!(root.children().count(), 0);
+    assert!(root.child(&'a').is_none());
+}
@@ -1,6 +1,14 @@
 mod index;
 mod indexer;
+mod lazy;
 mod result_ranker;
+mod sharded;
+mod tree;
+mod typo;
 
 pub use index::*;
-pub use result_ranker::HashExtractable;
+pub use lazy::{LazyGramIndex, LazyGramNode};
+pub use result_ranker::{HashExtractable, ResultRanker};
+pub use sharded::ShardedGramIndex;
+pub use tree::{GramSource, GramTreeNode, SearchLimits};
+pub use typo::{SearchStats, TypoEdits, TypoTolerance};
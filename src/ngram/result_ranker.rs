@@ -58,3 +58,9 @@ impl<'a, H: Hash + Eq, Data: HashExtractable<Inner = H>> ResultRanker<'a, H, Dat
         }
     }
 }
+
+impl<H: Hash + Eq, Data: HashExtractable<Inner = H>> Default for ResultRanker<'_, H, Data> {
+    fn default() -> Self {
+        ResultRanker::new()
+    }
+}
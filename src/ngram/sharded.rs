@@ -0,0 +1,191 @@
+use std::{marker::PhantomData, sync::RwLock};
+
+use ahash::AHashSet;
+
+use crate::{
+    data::{Product, ProductContainer, SuperAlloc},
+    serialize::{
+        patch::checksum, shard_count, shard_for, GramDataSection, GramShard, GramTreeSection,
+        IndexSections,
+    },
+};
+
+use super::{GramAtom, GramSource, LazyGramNode};
+
+#[derive(Clone, Copy)]
+struct LoadedShard<'a> {
+    tree: GramTreeSection<'a>,
+    data: GramDataSection<'a>,
+}
+
+/// A lazy gram index whose grams are split into shards by their first gram.
+///
+/// Shards are added as they're downloaded, and until then searches simply don't find the grams they hold.
+pub struct ShardedGramIndex<'a, G: GramAtom, const N: usize> {
+    shards: RwLock<Vec<Option<LoadedShard<'a>>>>,
+    // The checksum of the core blob, which every shard has to be built with
+    core_checksum: u64,
+    pub product_container: &'a ProductContainer<'a>,
+    grams: PhantomData<G>,
+}
+
+impl<'a, G: GramAtom, const N: usize> ShardedGramIndex<'a, G, N> {
+    /// Loads the products of a core blob, whose sections are given next to it
+    pub fn load(
+        core: &[u8],
+        sections: &IndexSections<'a>,
+        super_alloc: &'static SuperAlloc,
+    ) -> Option<ShardedGramIndex<'a, G, N>> {
+        if sections.gram_size != N {
+            return None;
+        }
+        let container = sections.products(super_alloc)?;
+        let shard_count = shard_count(sections)?;
+        if shard_count == 0 {
+            return None;
+        }
+
+        Some(ShardedGramIndex {
+            shards: RwLock::new(vec![None; shard_count]),
+            core_checksum: checksum(core),
+            product_container: container,
+            grams: PhantomData,
+        })
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.read().unwrap().len()
+    }
+
+    // Returns the index of the shard that was added, shards of another build are rejected
+    pub fn add_shard(&self, input: &'a [u8]) -> Option<usize> {
+        let shard = GramShard::parse(input)?;
+        if shard.core != self.core_checksum {
+            return None;
+        }
+        let loaded = LoadedShard {
+            tree: GramTreeSection::parse(shard.gram_tree)?,
+            data: GramDataSection::parse(shard.gram_data)?,
+        };
+        let mut shards = self.shards.write().unwrap();
+        *shards.get_mut(shard.index)? = Some(loaded);
+        Some(shard.index)
+    }
+
+    /// The shards a query needs that haven't been added yet
+    pub fn missing_shards<I: Iterator<Item = G>>(&self, input: I) -> Vec<usize> {
+        let shards = self.shards.read().unwrap();
+        let shard_count = shards.len();
+        // The first windows of a query are padded with the default gram
+        let needed: AHashSet<usize> = std::iter::once(G::default())
+            .chain(input)
+            .map(|gram| shard_for(&gram, shard_count))
+            .collect();
+
+        let mut missing: Vec<usize> = needed
+            .into_iter()
+            .filter(|shard| shards[*shard].is_none())
+            .collect();
+        missing.sort_unstable();
+        missing
+    }
+
+    fn shard(&self, gram: &G) -> Option<LoadedShard<'a>> {
+        let shards = self.shards.read().unwrap();
+        shards[shard_for(gram, shards.len())]
+    }
+
+    pub fn search<I: Iterator<Item = G>>(&self, input: I) -> Vec<(&Product<'a>, f32)> {
+        GramSource::search(self, input)
+    }
+}
+
+impl<'a, G: GramAtom, const N: usize> GramSource<G, N> for ShardedGramIndex<'a, G, N> {
+    type Data = Product<'a>;
+    type Node<'s>
+        = LazyGramNode<'a, G>
+    where
+        Self: 's;
+
+    fn root(&self, gram: &G) -> Option<Self::Node<'_>> {
+        let shard = self.shard(gram)?;
+        Some(LazyGramNode::new(
+            shard.tree.nodes,
+            shard.tree.find_root(gram)?,
+        ))
+    }
+
+    fn for_each_posting<'s, F: FnMut(&'s Self::Data)>(&'s self, key: &[G; N], mut found: F)
+    where
+        Self::Data: 's,
+    {
+        let Some(shard) = self.shard(&key[0]) else {
+            return;
+        };
+        let products = &self.product_container.products;
+        for id in shard.data.find_postings(key).into_iter().flatten() {
+            if let Some(product) = products.get(id) {
+                found(product);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_sharded_search_matches_eager() {
+    use crate::{
+        config::IndexConfig,
+        serialize::{serialize_sharded, Serializable, ShardedBlobs},
+        testing::{build_test_index, test_products, TEST_ARENA},
+    };
+
+    let built = build_test_index(test_products(), IndexConfig::default());
+    let (index, classic) = (&built.index, &built.classic);
+    let ShardedBlobs { core, shards } = serialize_sharded(index, classic, 4);
+    assert_eq!(shards.len(), 4);
+
+    let sections = IndexSections::parse(&core[..]).unwrap();
+    let sharded: ShardedGramIndex<char, 5> =
+        ShardedGramIndex::load(&core, &sections, &TEST_ARENA).unwrap();
+
+    let by_id = |results: Vec<(&Product, f32)>| {
+        let mut results: Vec<_> = results
+            .into_iter()
+            .map(|(p, confidence)| (p.serialization_id, confidence.to_bits()))
+            .collect();
+        results.sort_unstable();
+        results
+    };
+
+    let query = "kunst plakat";
+    let missing = sharded.missing_shards(query.chars());
+    assert!(missing.is_empty() == false);
+
+    // Shards of another build of the index don't fit it
+    let other = serialize_sharded(index, classic, 3);
+    assert!(other.core != core);
+    assert!(sharded.add_shard(&other.shards[0]).is_none());
+    // Nor do shards with lengths past the end of the blob
+    let core_checksum = GramShard::parse(&shards[0]).unwrap().core;
+    let mut broken = Vec::new();
+    let mut save = |byte| broken.push(byte);
+    0usize.serialize(&mut save);
+    core_checksum.serialize(&mut save);
+    1usize.serialize(&mut save);
+    usize::MAX.serialize(&mut save);
+    broken.push(0);
+    assert!(sharded.add_shard(TEST_ARENA.alloc(broken)).is_none());
+
+    for shard in &shards {
+        sharded.add_shard(shard).unwrap();
+    }
+    assert!(sharded.missing_shards(query.chars()).is_empty());
+
+    for query in ["kunst", "plakat", "blå", "unerstuod hvzdom", "x"] {
+        assert_eq!(
+            by_id(index.search(query.chars())),
+            by_id(sharded.search(query.chars())),
+            "Results differ for {query}"
+        );
+    }
+}
@@ -0,0 +1,293 @@
+use ahash::AHashMap;
+
+use super::{
+    result_ranker::{HashExtractable, ResultRanker},
+    typo::{SearchStats, TypoTolerance},
+    GramAtom,
+};
+
+/// A node in a gram tree, either an arena allocated `GramNode` or a view into serialized bytes
+pub trait GramTreeNode<G: GramAtom>: Copy {
+    fn item(&self) -> G;
+
+    fn weight(&self) -> f32;
+
+    fn child(&self, gram: &G) -> Option<Self>;
+
+    /// The children, most popular first
+    fn children(&self) -> impl Iterator<Item = Self>;
+}
+
+/// Anything that can answer a gram search: a tree of grams to correct the query with, and postings for the full n-grams
+pub trait GramSource<G: GramAtom, const N: usize> {
+    type Data: HashExtractable;
+    type Node<'s>: GramTreeNode<G>
+    where
+        Self: 's;
+
+    fn root(&self, gram: &G) -> Option<Self::Node<'_>>;
+
+    fn for_each_posting<'s, F: FnMut(&'s Self::Data)>(&'s self, key: &[G; N], found: F)
+    where
+        Self::Data: 's;
+
+    fn search<I: Iterator<Item = G>>(&self, input: I) -> Vec<(&Self::Data, f32)> {
+        let tolerance = TypoTolerance::default();
+        let edits = tolerance.edits.limit(N);
+        self.search_with(
+            input.map(|gram| (gram, edits)),
+            &tolerance,
+            &SearchStats::default(),
+        )
+    }
+
+    /// Searches with the corrections allowed for the window ending at each gram, see `TypoTolerance::apply`
+    fn search_with<I: Iterator<Item = (G, u8)>>(
+        &self,
+        input: I,
+        tolerance: &TypoTolerance,
+        stats: &SearchStats,
+    ) -> Vec<(&Self::Data, f32)> {
+        let mut results = ResultRanker::new();
+        let mut ngram = [G::default(); N];
+        for (gram, edits) in input {
+            for i in 1..N {
+                ngram[i - 1] = ngram[i];
+            }
+            ngram[N - 1] = gram;
+            let limits = SearchLimits {
+                changes: edits,
+                branching: tolerance.max_branching,
+                stats,
+            };
+            let Some((ngram, confidence)) = self.search_gram_with(ngram, &limits) else {
+                continue;
+            };
+            self.for_each_posting(&ngram, |data| results.add(data, confidence));
+        }
+        results.export_data_by_confidence()
+    }
+
+    /// The data containing the grams exactly, without any typo tolerance.
+    ///
+    /// Longer inputs need every full window of the input. Shorter ones are looked up through every
+    /// window that starts with them, and the padded window of data that starts with them.
+    /// Pruned grams have no postings, so phrases with them aren't found here.
+    fn exact_matches<I: Iterator<Item = G>>(&self, input: I) -> Vec<&Self::Data> {
+        let grams: Vec<G> = input.collect();
+        if grams.is_empty() {
+            return Vec::new();
+        }
+
+        let mut found: AHashMap<_, &Self::Data> = AHashMap::new();
+        if grams.len() >= N {
+            for (i, window) in grams.windows(N).enumerate() {
+                let key: [G; N] = window.try_into().unwrap();
+                let mut in_window = AHashMap::new();
+                self.for_each_posting(&key, |data| {
+                    if i == 0 || found.contains_key(data.extract()) {
+                        in_window.insert(data.extract(), data);
+                    }
+                });
+                found = in_window;
+            }
+            return found.into_iter().map(|(_, data)| data).collect();
+        }
+
+        let mut padded = [G::default(); N];
+        padded[N - grams.len()..].copy_from_slice(&grams);
+        self.for_each_posting(&padded, |data| {
+            found.insert(data.extract(), data);
+        });
+
+        let mut node = self.root(&grams[0]);
+        for gram in &grams[1..] {
+            node = node.and_then(|node| node.child(gram));
+        }
+        if let Some(node) = node {
+            let mut key = [G::default(); N];
+            key[..grams.len()].copy_from_slice(&grams);
+            for_each_completion(node, &mut key, grams.len(), &mut |key| {
+                self.for_each_posting(key, |data| {
+                    found.insert(data.extract(), data);
+                });
+            });
+        }
+        found.into_iter().map(|(_, data)| data).collect()
+    }
+
+    /*
+    For the current step in the tree:
+        The user entered gram
+        No Gram
+        The 5 most popular grams
+
+    And these are tested on:
+        This node
+        Last node
+
+    If skip limit is reached, we don't do the previous node or no node
+    */
+
+    fn search_gram(&self, query: [G; N]) -> Option<([G; N], f32)> {
+        let limits = SearchLimits {
+            changes: TypoTolerance::default().edits.limit(N),
+            branching: usize::MAX,
+            stats: &SearchStats::default(),
+        };
+        self.search_gram_with(query, &limits)
+    }
+
+    fn search_gram_with(&self, query: [G; N], limits: &SearchLimits<'_>) -> Option<([G; N], f32)> {
+        let root_node = self.root(query.first()?)?;
+        let mut previous = [G::default(); N];
+        previous[0] = query[0];
+        recursive_search(&query[1..], root_node, None, 0, limits, 1.0, previous, 1)
+    }
+}
+
+// Calls `found` with every full length key below a node, `len` grams of the key are already filled in
+fn for_each_completion<G: GramAtom, Node: GramTreeNode<G>, F: FnMut(&[G; N]), const N: usize>(
+    node: Node,
+    key: &mut [G; N],
+    len: usize,
+    found: &mut F,
+) {
+    if len == N {
+        found(key);
+        return;
+    }
+    for child in node.children() {
+        key[len] = child.item();
+        for_each_completion(child, key, len + 1, found);
+    }
+}
+
+/// The typo tolerance of a single gram window
+pub struct SearchLimits<'s> {
+    changes: u8,
+    branching: usize,
+    stats: &'s SearchStats,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn recursive_search<G: GramAtom, Node: GramTreeNode<G>, const N: usize>(
+    input: &[G],
+    node: Node,
+    previous_node: Option<Node>,
+    changes: u8,
+    limits: &SearchLimits<'_>,
+    cummulative_weight: f32,
+    previous_input: [G; N],
+    index: usize,
+) -> Option<([G; N], f32)> {
+    limits.stats.explore();
+    if index == N {
+        return Some((previous_input, cummulative_weight));
+    }
+
+    let best_result = |a: Option<([G; N], f32)>, b: Option<([G; N], f32)>| {
+        match (a, b) {
+            // If only one exists, we use the existing
+            (Some(v), None) | (None, Some(v)) => Some(v),
+            // If none exist, we don't have any
+            (None, None) => None,
+            (Some(a), Some(b)) => Some(if a.1 > b.1 { a } else { b }),
+        }
+    };
+
+    let user_entered = input.first().and_then(|gram| node.child(gram));
+    let user_entered_gram = user_entered.map(|v| v.item());
+
+    let mut most_likely = None;
+
+    let next_input = if input.is_empty() { &[] } else { &input[1..] };
+
+    // We try the current options, on the current node
+    if let Some(user_entered) = user_entered {
+        let mut previous_input = previous_input;
+        previous_input[index] = user_entered.item();
+
+        let found = recursive_search(
+            next_input,
+            user_entered,
+            Some(node),
+            changes,
+            limits,
+            cummulative_weight * user_entered.weight(),
+            previous_input,
+            index + 1,
+        );
+
+        // If we found something, we keep the most likely
+        most_likely = best_result(found, most_likely);
+    }
+
+    // If more skips are allowed, we try those
+    if limits.changes > changes {
+        let changes = changes + 1;
+
+        // We try the most popular options at this node
+        let most_popular = node
+            .children()
+            // We only want grams that do not match the
+            .filter(|potential| Some(potential.item()) != user_entered_gram)
+            .take(limits.branching);
+
+        for next_node in most_popular {
+            let mut previous_input = previous_input;
+            previous_input[index] = next_node.item();
+
+            let found = recursive_search(
+                next_input,
+                next_node,
+                Some(node),
+                changes,
+                limits,
+                cummulative_weight * next_node.weight(),
+                previous_input,
+                index + 1,
+            );
+
+            // We also try running this with the same input as we got, thereby compensating for forgotten grams
+            let repeat_found = recursive_search(
+                input,
+                next_node,
+                Some(node),
+                changes + 1,
+                limits,
+                cummulative_weight * next_node.weight(),
+                previous_input,
+                index + 1,
+            );
+
+            // If we found something, we keep the most likely
+            most_likely = best_result(best_result(found, repeat_found), most_likely);
+        }
+
+        // We try ignoring the previous gram, in case the user entered "abc" when they meant "ac"
+        if let Some(current_input) = input.first() {
+            if let Some(last) = previous_node.and_then(|node| node.child(current_input)) {
+                let mut previous_input = previous_input;
+                // We overwrite the old gram
+                previous_input[index - 1] = last.item();
+
+                let found = recursive_search(
+                    next_input,
+                    last,
+                    None,
+                    changes,
+                    limits,
+                    cummulative_weight,
+                    previous_input,
+                    // We use the same index since we really looked at index - 1
+                    index,
+                );
+
+                most_likely = best_result(found, most_likely);
+            }
+        }
+    }
+
+    most_likely
+}
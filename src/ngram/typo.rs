@@ -0,0 +1,145 @@
+use std::cell::Cell;
+
+use crate::serialize::{Deserializable, Serializable};
+
+/// How many grams of a window may be corrected
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TypoEdits {
+    // Only exact matches, for things like part numbers
+    Off,
+    // A third of the window and at least one, one for 5-grams and two for 8-grams
+    Min,
+    // Half of the window after its first gram, which is never corrected
+    Max,
+}
+
+impl TypoEdits {
+    pub fn limit(self, window: usize) -> u8 {
+        let corrections = match self {
+            TypoEdits::Off => 0,
+            TypoEdits::Min => (window / 3).max(1),
+            TypoEdits::Max => (window.saturating_sub(1) / 2).max(1),
+        };
+        u8::try_from(corrections.min(window.saturating_sub(1))).unwrap_or(u8::MAX)
+    }
+}
+
+/// How forgiving a search is towards typos, and how much of the gram tree it may explore doing so
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct TypoTolerance {
+    pub edits: TypoEdits,
+    // Shorter words are only matched exactly
+    pub min_word_length: usize,
+    // How many of the most popular children are tried at each node when correcting
+    pub max_branching: usize,
+}
+
+impl Default for TypoTolerance {
+    fn default() -> Self {
+        TypoTolerance {
+            edits: TypoEdits::Min,
+            min_word_length: 0,
+            max_branching: usize::MAX,
+        }
+    }
+}
+
+impl TypoTolerance {
+    /// The query with the corrections allowed for the window ending at each character,
+    /// which depends on the length of the word the character is part of
+    pub fn apply(&self, query: &str, window: usize) -> Vec<(char, u8)> {
+        let limit = self.edits.limit(window);
+        let mut out: Vec<(char, u8)> = Vec::with_capacity(query.len());
+        let mut word_start = 0;
+        for char in query.chars().chain(std::iter::once(' ')) {
+            if char.is_alphanumeric() {
+                out.push((char, limit));
+                continue;
+            }
+            let word = &mut out[word_start..];
+            if word.len() < self.min_word_length {
+                for (_, edits) in word {
+                    *edits = 0;
+                }
+            }
+            out.push((char, limit));
+            word_start = out.len();
+        }
+        out.pop();
+        out
+    }
+}
+
+/// Counts what a search did, so the cost of a typo tolerance setting can be observed
+#[derive(Debug, Default)]
+pub struct SearchStats {
+    explored_nodes: Cell<usize>,
+}
+
+impl SearchStats {
+    pub fn explored_nodes(&self) -> usize {
+        self.explored_nodes.get()
+    }
+
+    pub(super) fn explore(&self) {
+        self.explored_nodes.set(self.explored_nodes.get() + 1);
+    }
+}
+
+impl Serializable for TypoTolerance {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        let edits: u8 = match self.edits {
+            TypoEdits::Off => 0,
+            TypoEdits::Min => 1,
+            TypoEdits::Max => 2,
+        };
+        edits.serialize(output);
+        self.min_word_length.serialize(output);
+        self.max_branching.serialize(output);
+    }
+}
+
+impl Deserializable for TypoTolerance {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (input, edits) = u8::deserialize(input)?;
+        let edits = match edits {
+            0 => TypoEdits::Off,
+            1 => TypoEdits::Min,
+            2 => TypoEdits::Max,
+            _ => return None,
+        };
+        let (input, min_word_length) = usize::deserialize(input)?;
+        let (input, max_branching) = usize::deserialize(input)?;
+        Some((
+            input,
+            TypoTolerance {
+                edits,
+                min_word_length,
+                max_branching,
+            },
+        ))
+    }
+}
+
+#[test]
+fn test_typo_tolerance() {
+    assert_eq!(TypoEdits::Off.limit(5), 0);
+    assert_eq!(TypoEdits::Min.limit(3), 1);
+    assert_eq!(TypoEdits::Min.limit(5), 1);
+    assert_eq!(TypoEdits::Min.limit(8), 2);
+    assert_eq!(TypoEdits::Max.limit(3), 1);
+    assert_eq!(TypoEdits::Max.limit(8), 3);
+
+    let tolerance = TypoTolerance {
+        min_word_length: 4,
+        ..TypoTolerance::default()
+    };
+    let edits: Vec<u8> = tolerance
+        .apply("a12 plakat", 5)
+        .into_iter()
+        .map(|(_, edits)| edits)
+        .collect();
+    assert_eq!(edits, vec![0, 0, 0, 1, 1, 1, 1, 1, 1, 1]);
+}
@@ -0,0 +1,150 @@
+use unicode_normalization::{
+    char::decompose_canonical, char::is_combining_mark, UnicodeNormalization,
+};
+
+use crate::{
+    language::Language,
+    serialize::{Deserializable, Serializable},
+};
+
+/// How text is normalised before it's turned into grams.
+///
+/// The same normalisation has to be used for indexing and searching, so it's stored in the index header.
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct TextNormalization {
+    // NFKC instead of NFC, so ligatures and full width letters match their plain forms
+    pub compatibility: bool,
+    pub case_fold: bool,
+    pub strip_accents: bool,
+    // The letters this language treats as its own are kept when stripping accents
+    pub language: Option<Language>,
+}
+
+impl Default for TextNormalization {
+    fn default() -> Self {
+        TextNormalization {
+            compatibility: true,
+            case_fold: true,
+            strip_accents: false,
+            language: None,
+        }
+    }
+}
+
+// Letters without a canonical decomposition, that are still typed as their base letters
+fn fold_letter(letter: char) -> Option<&'static str> {
+    Some(match letter {
+        'æ' => "ae",
+        'Æ' => "AE",
+        'ø' => "o",
+        'Ø' => "O",
+        'œ' => "oe",
+        'Œ' => "OE",
+        'ß' => "ss",
+        'ł' => "l",
+        'Ł' => "L",
+        'đ' => "d",
+        'Đ' => "D",
+        'ı' => "i",
+        _ => return None,
+    })
+}
+
+impl TextNormalization {
+    pub fn normalize(&self, input: &str) -> String {
+        let composed: String = if self.compatibility {
+            input.nfkc().collect()
+        } else {
+            input.nfc().collect()
+        };
+
+        let folded: String = if self.case_fold {
+            composed.chars().flat_map(char::to_lowercase).collect()
+        } else {
+            composed
+        };
+
+        if self.strip_accents == false {
+            return folded;
+        }
+
+        let kept = self.language.map_or(&[][..], Language::distinct_letters);
+        let mut out = String::with_capacity(folded.len());
+        for char in folded.chars() {
+            if char.is_ascii() || kept.contains(&char) {
+                out.push(char);
+            } else if let Some(replacement) = fold_letter(char) {
+                out.push_str(replacement);
+            } else {
+                decompose_canonical(char, |part| {
+                    if is_combining_mark(part) == false {
+                        out.push(part);
+                    }
+                });
+            }
+        }
+        out
+    }
+}
+
+impl Serializable for TextNormalization {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        let flags = u8::from(self.compatibility)
+            | u8::from(self.case_fold) << 1
+            | u8::from(self.strip_accents) << 2
+            | u8::from(self.language.is_some()) << 3;
+        flags.serialize(output);
+        if let Some(language) = self.language {
+            language.serialize(output);
+        }
+    }
+}
+
+impl Deserializable for TextNormalization {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (mut input, flags) = u8::deserialize(input)?;
+        let mut language = None;
+        if flags & 1 << 3 != 0 {
+            let (next_input, parsed) = Language::deserialize(input)?;
+            input = next_input;
+            language = Some(parsed);
+        }
+        Some((
+            input,
+            TextNormalization {
+                compatibility: flags & 1 != 0,
+                case_fold: flags & 1 << 1 != 0,
+                strip_accents: flags & 1 << 2 != 0,
+                language,
+            },
+        ))
+    }
+}
+
+#[test]
+fn test_normalize() {
+    let folding = TextNormalization {
+        strip_accents: true,
+        ..TextNormalization::default()
+    };
+    let danish = TextNormalization {
+        language: Some(Language::Danish),
+        ..folding
+    };
+
+    // "å" written as an "a" followed by a combining ring
+    let decomposed = "Bla\u{30A}";
+
+    for (normalization, input, expected) in [
+        (TextNormalization::default(), "Café", "café"),
+        (TextNormalization::default(), decomposed, "blå"),
+        (TextNormalization::default(), "ﬁne ＡＢ", "fine ab"),
+        (folding, "Café Crème", "cafe creme"),
+        (folding, "Blå Ø", "bla o"),
+        (danish, decomposed, "blå"),
+        (danish, "Café Ørn", "cafe ørn"),
+    ] {
+        assert_eq!(normalization.normalize(input), expected);
+    }
+}
@@ -1,65 +1,578 @@
-// use std::{
-//     cell::RefCell,
-//     collections::{HashMap, HashSet},
-//     rc::Rc,
-// };
-
-// use crate::ngramindex::{GramAtom, IndexFeed};
-
-// pub fn preprocessor<G: GramAtom, const N: usize, I, S, Data>(source: S, cutoff_percent: f64) -> S
-// where
-//     I: Iterator<Item = G> + Clone,
-//     S: Iterator<Item = IndexFeed<G, I, Data>> + Clone,
-// {
-//     let data: Vec<(Data, Vec<G>)> = source
-//         .map(|source| (source.data, source.grams.collect()))
-//         .collect();
-//     let mut total_seen = HashMap::new();
-
-//     for (_, grams) in data.iter() {
-//         let occurances = occurances_for_source::<_, N>(grams);
-//         for val in occurances {
-//             total_seen
-//                 .entry(val)
-//                 .and_modify(|v| *v += 1)
-//                 .or_insert(1usize);
-//         }
-//     }
-
-//     let len = data.len() as f64;
-
-//     let mut with_percent: Vec<_> = total_seen
-//         .into_iter()
-//         .filter_map(|(gram, v)| {
-//             let seen_in_percent = (v as f64) / len;
-//             if seen_in_percent >= cutoff_percent {
-//                 Some((gram, seen_in_percent))
-//             } else {
-//                 None
-//             }
-//         })
-//         .collect();
-
-//     with_percent.sort_by(|(_, a), (_, b)| a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal));
-
-//     for (gram, percent) in with_percent {
-//         println!("Saw: {gram:?} in {percent:.04}")
-//     }
-
-//     todo!()
-// }
-
-// fn occurances_for_source<'grams, G: GramAtom, const N: usize>(
-//     grams: &'grams [G],
-// ) -> HashSet<&'grams [G]> {
-//     let mut seen = HashSet::new();
-
-//     for i in 0..(grams.len() - N) {
-//         let n_slice = &grams[i..(i + N)];
-//         for j in 2..N {
-//             seen.insert(&n_slice[0..j]);
-//         }
-//     }
-
-//     seen
-// }
+use std::{
+    borrow::Cow,
+    fmt::{Display, Write},
+};
+
+use ahash::AHashMap;
+
+use crate::{
+    config::{finite_f32, IndexConfig},
+    data::{Document, Product, Schema},
+    ngram::{GramAtom, IndexFeed},
+    serialize::{Deserializable, Serializable},
+};
+
+// Tags that separate words, so they're replaced by a space instead of just removed
+const BLOCK_TAGS: [&str; 24] = [
+    "p",
+    "br",
+    "div",
+    "li",
+    "ul",
+    "ol",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "tr",
+    "td",
+    "th",
+    "table",
+    "section",
+    "article",
+    "header",
+    "footer",
+    "hr",
+    "blockquote",
+    "pre",
+    "dd",
+];
+
+fn decode_entity(entity: &str) -> Option<char> {
+    if let Some(number) = entity.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    Some(match entity {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => ' ',
+        "aelig" => 'æ',
+        "AElig" => 'Æ',
+        "oslash" => 'ø',
+        "Oslash" => 'Ø',
+        "aring" => 'å',
+        "Aring" => 'Å',
+        "auml" => 'ä',
+        "ouml" => 'ö',
+        "uuml" => 'ü',
+        "szlig" => 'ß',
+        "eacute" => 'é',
+        "ndash" => '–',
+        "mdash" => '—',
+        _ => return None,
+    })
+}
+
+/// Removes the tags from an HTML snippet and decodes its entities.
+///
+/// The contents of `<script>` and `<style>` are dropped.
+pub fn strip_html(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(start) = rest.find(['<', '&']) {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if rest.starts_with('&') {
+            // Entities are short, so we don't look far for the end. The bytes are searched as the
+            // limit may fall inside a multibyte character
+            let decoded = rest.as_bytes()[1..rest.len().min(12)]
+                .iter()
+                .position(|byte| *byte == b';')
+                .and_then(|end| Some((end, decode_entity(&rest[1..=end])?)));
+            if let Some((end, char)) = decoded {
+                out.push(char);
+                rest = &rest[end + 2..];
+            } else {
+                out.push('&');
+                rest = &rest[1..];
+            }
+            continue;
+        }
+
+        // A `<` that doesn't start a tag, or a tag that's never closed, is just text
+        let starts_tag =
+            rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!');
+        let Some(end) = rest.find('>').filter(|_| starts_tag) else {
+            out.push('<');
+            rest = &rest[1..];
+            continue;
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        if closing == false && (name == "script" || name == "style") {
+            let close = format!("</{name}");
+            rest = match rest.to_ascii_lowercase().find(&close) {
+                Some(position) => &rest[position..],
+                None => "",
+            };
+            continue;
+        }
+        if BLOCK_TAGS.contains(&name.as_str()) {
+            out.push(' ');
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+pub fn collapse_whitespace(input: &str) -> String {
+    input.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Control characters other than whitespace
+pub fn remove_control_characters(input: &str) -> String {
+    input
+        .chars()
+        .filter(|c| c.is_control() == false || c.is_whitespace())
+        .collect()
+}
+
+/// The clean up a field gets before it's grammed
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct Normalization {
+    pub strip_html: bool,
+    pub remove_control_characters: bool,
+    pub collapse_whitespace: bool,
+}
+
+impl Normalization {
+    pub const NONE: Normalization = Normalization {
+        strip_html: false,
+        remove_control_characters: false,
+        collapse_whitespace: false,
+    };
+    pub const PLAIN_TEXT: Normalization = Normalization {
+        strip_html: false,
+        remove_control_characters: true,
+        collapse_whitespace: true,
+    };
+    pub const HTML: Normalization = Normalization {
+        strip_html: true,
+        remove_control_characters: true,
+        collapse_whitespace: true,
+    };
+
+    pub fn apply<'s>(&self, input: &'s str) -> Cow<'s, str> {
+        let mut out = Cow::Borrowed(input);
+        if self.strip_html {
+            out = Cow::Owned(strip_html(&out));
+        }
+        if self.remove_control_characters && out.chars().any(char::is_control) {
+            out = Cow::Owned(remove_control_characters(&out));
+        }
+        if self.collapse_whitespace {
+            out = Cow::Owned(collapse_whitespace(&out));
+        }
+        out
+    }
+}
+
+impl Default for Normalization {
+    fn default() -> Self {
+        Normalization::PLAIN_TEXT
+    }
+}
+
+impl Serializable for Normalization {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        let flags = u8::from(self.strip_html)
+            | u8::from(self.remove_control_characters) << 1
+            | u8::from(self.collapse_whitespace) << 2;
+        flags.serialize(output);
+    }
+}
+
+impl Deserializable for Normalization {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (input, flags) = u8::deserialize(input)?;
+        Some((
+            input,
+            Normalization {
+                strip_html: flags & 1 != 0,
+                remove_control_characters: flags & 1 << 1 != 0,
+                collapse_whitespace: flags & 1 << 2 != 0,
+            },
+        ))
+    }
+}
+
+/// How each field of a product is normalised before indexing
+#[derive(serde::Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct PreprocessConfig {
+    pub title: Normalization,
+    pub description: Normalization,
+    pub vendor: Normalization,
+}
+
+impl Default for PreprocessConfig {
+    fn default() -> Self {
+        PreprocessConfig {
+            title: Normalization::PLAIN_TEXT,
+            // Shopify descriptions are HTML fragments
+            description: Normalization::HTML,
+            vendor: Normalization::PLAIN_TEXT,
+        }
+    }
+}
+
+impl Serializable for PreprocessConfig {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        let PreprocessConfig {
+            title,
+            description,
+            vendor,
+        } = self;
+        title.serialize(output);
+        description.serialize(output);
+        vendor.serialize(output);
+    }
+}
+
+impl Deserializable for PreprocessConfig {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (input, title) = Normalization::deserialize(input)?;
+        let (input, description) = Normalization::deserialize(input)?;
+        let (input, vendor) = Normalization::deserialize(input)?;
+        Some((
+            input,
+            PreprocessConfig {
+                title,
+                description,
+                vendor,
+            },
+        ))
+    }
+}
+
+/// Cleans up and normalises the fields of a product, and turns them into the grams it's indexed by
+pub fn index_feed<'p>(
+    product: &'p Product<'p>,
+    config: &IndexConfig,
+) -> IndexFeed<'p, char, std::vec::IntoIter<char>, Product<'p>> {
+    let Product {
+        description,
+        title,
+        vendor,
+        ..
+    } = product;
+
+    let IndexConfig {
+        preprocess,
+        normalization,
+        analysis,
+        ..
+    } = config;
+    // A field's own language wins over the product's, which wins over the index's
+    let language = product.language.or(analysis.language);
+    // Vendors are names, so they aren't analysed
+    let text: String = [
+        (
+            preprocess.description.apply(description),
+            analysis.description_language.or(language),
+        ),
+        (
+            preprocess.title.apply(title),
+            analysis.title_language.or(language),
+        ),
+        (preprocess.vendor.apply(&vendor.name), None),
+    ]
+    .iter()
+    .map(|(field, language)| {
+        let normalized = normalization.normalize(field);
+        analysis.analyze(&normalized, *language).into_owned()
+    })
+    .collect();
+    let grams: Vec<char> = text.chars().collect();
+
+    IndexFeed {
+        data: product,
+        grams: grams.into_iter(),
+    }
+}
+
+/// Like `index_feed`, with every text field of the document's schema normalised as the schema says
+pub fn document_feed<'d>(
+    document: &'d Document,
+    schema: &Schema,
+    config: &IndexConfig,
+) -> IndexFeed<'d, char, std::vec::IntoIter<char>, Document> {
+    let IndexConfig {
+        normalization,
+        analysis,
+        ..
+    } = config;
+    let language = document.language.or(analysis.language);
+    let text: String = schema
+        .fields
+        .iter()
+        .zip(&document.fields)
+        .map(|(field, text)| {
+            let normalized = normalization.normalize(&field.normalization.apply(text));
+            analysis.analyze(&normalized, language).into_owned()
+        })
+        .collect();
+    let grams: Vec<char> = text.chars().collect();
+
+    IndexFeed {
+        data: document,
+        grams: grams.into_iter(),
+    }
+}
+
+#[test]
+fn test_strip_html() {
+    for (input, expected) in [
+        ("Plain text", "Plain text"),
+        ("<p>Hello</p><p>world</p>", "Hello world"),
+        ("<b>Fed</b> plakat &amp; ramme", "Fed plakat & ramme"),
+        ("Bl&aring; &#230;ble &#xF8;", "Blå æble ø"),
+        ("a<script>alert('<p>')</script>b<STYLE>p {}</STYLE>c", "abc"),
+        ("5 < 6 & 7 > 2", "5 < 6 & 7 > 2"),
+        ("<ul>\n  <li>One</li>\n  <li>Two</li>\n</ul>", "One Two"),
+        ("Line\u{0} one\u{7}<br>line two", "Line one line two"),
+        ("&nbsp;æøåæøå", "æøåæøå"),
+        ("Fed &amp;øøøø", "Fed &øøøø"),
+        ("&æøåæøå;", "&æøåæøå;"),
+    ] {
+        assert_eq!(Normalization::HTML.apply(input), expected);
+    }
+}
+
+/// Which grams are left out of the index, by how many products they occur in
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct GramPruning {
+    // Grams in more than this fraction of the products don't tell them apart, like " the "
+    #[serde(deserialize_with = "finite_f32")]
+    pub max_document_frequency: f32,
+    // Grams in fewer products than this are mostly typos
+    pub min_documents: usize,
+}
+
+impl GramPruning {
+    pub fn keeps(&self, documents: usize, product_count: usize) -> bool {
+        #[allow(clippy::cast_precision_loss)]
+        let frequency = documents as f32 / product_count.max(1) as f32;
+        frequency <= self.max_document_frequency && documents >= self.min_documents
+    }
+}
+
+impl Default for GramPruning {
+    fn default() -> Self {
+        GramPruning {
+            max_document_frequency: 0.8,
+            min_documents: 0,
+        }
+    }
+}
+
+// The frequency is never NaN, it's rejected when the config is read
+impl Eq for GramPruning {}
+
+impl Serializable for GramPruning {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        self.max_document_frequency.serialize(output);
+        self.min_documents.serialize(output);
+    }
+}
+
+impl Deserializable for GramPruning {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (input, max_document_frequency) =
+            f32::deserialize(input).filter(|(_, f)| f.is_finite())?;
+        let (input, min_documents) = usize::deserialize(input)?;
+        Some((
+            input,
+            GramPruning {
+                max_document_frequency,
+                min_documents,
+            },
+        ))
+    }
+}
+
+pub struct GramFrequency<G, const N: usize> {
+    pub gram: [G; N],
+    // The amount of products the gram occurs in
+    pub documents: usize,
+    pub kept: bool,
+}
+
+/// The document frequency of every gram seen while indexing, and whether it was pruned
+pub struct GramStatistics<G, const N: usize> {
+    pub product_count: usize,
+    pub grams: Vec<GramFrequency<G, N>>,
+}
+
+impl<G: GramAtom, const N: usize> GramStatistics<G, N> {
+    // The postings have to be sorted, so the duplicates of a product are next to each other
+    pub fn collect<Data: Ord>(
+        data: &AHashMap<[G; N], Vec<&Data>>,
+        product_count: usize,
+        pruning: &GramPruning,
+    ) -> Self {
+        let mut grams: Vec<GramFrequency<G, N>> = data
+            .iter()
+            .map(|(gram, postings)| {
+                let documents = postings.first().map_or(0, |_| {
+                    1 + postings
+                        .windows(2)
+                        .filter(|pair| pair[0] != pair[1])
+                        .count()
+                });
+                GramFrequency {
+                    gram: *gram,
+                    documents,
+                    kept: pruning.keeps(documents, product_count),
+                }
+            })
+            .collect();
+        // Most common first
+        grams.sort_by(|a, b| b.documents.cmp(&a.documents).then(a.gram.cmp(&b.gram)));
+
+        GramStatistics {
+            product_count,
+            grams,
+        }
+    }
+
+    pub fn pruned(&self) -> impl Iterator<Item = &[G; N]> {
+        self.grams
+            .iter()
+            .filter(|frequency| frequency.kept == false)
+            .map(|frequency| &frequency.gram)
+    }
+}
+
+impl<G: GramAtom + Display, const N: usize> GramStatistics<G, N> {
+    pub fn to_csv(&self) -> String {
+        let mut csv = "gram;documents;frequency;kept\n".to_string();
+        for GramFrequency {
+            gram,
+            documents,
+            kept,
+        } in &self.grams
+        {
+            // The padding before the start of a text isn't written
+            let gram: String = gram
+                .iter()
+                .filter(|g| **g != G::default())
+                .map(ToString::to_string)
+                .collect();
+            #[allow(clippy::cast_precision_loss)]
+            let frequency = *documents as f32 / self.product_count.max(1) as f32;
+            // Writing to a string can't fail
+            let _ = writeln!(
+                csv,
+                "\"{}\";{documents};{frequency:.4};{kept}",
+                gram.replace('"', "\"\"")
+            );
+        }
+        csv
+    }
+}
+
+#[test]
+fn test_gram_statistics() {
+    let products = [1, 2, 3, 4];
+    let mut data = AHashMap::new();
+    // In every product, and twice in the first
+    data.insert(
+        ['a', 'b'],
+        vec![
+            &products[0],
+            &products[0],
+            &products[1],
+            &products[2],
+            &products[3],
+        ],
+    );
+    data.insert(['b', 'c'], vec![&products[1], &products[2]]);
+    data.insert(['c', 'd'], vec![&products[3]]);
+
+    let pruning = GramPruning {
+        max_document_frequency: 0.8,
+        min_documents: 2,
+    };
+    let statistics = GramStatistics::collect(&data, products.len(), &pruning);
+
+    let documents: Vec<_> = statistics
+        .grams
+        .iter()
+        .map(|frequency| (frequency.gram, frequency.documents, frequency.kept))
+        .collect();
+    assert_eq!(
+        documents,
+        vec![
+            (['a', 'b'], 4, false),
+            (['b', 'c'], 2, true),
+            (['c', 'd'], 1, false)
+        ]
+    );
+    assert!(statistics
+        .to_csv()
+        .starts_with("gram;documents;frequency;kept\n\"ab\";4;1.0000;false\n"));
+
+    // The frequency has to be finite, for the config to equal itself
+    let pruning = |json: &str| serde_json::from_str::<GramPruning>(json);
+    assert!(pruning(r#"{"max_document_frequency": 0.5}"#).is_ok());
+    assert!(pruning(r#"{"max_document_frequency": 1e39}"#).is_err());
+}
+
+#[test]
+fn test_preprocess_config_per_field() {
+    use crate::{
+        ngram::SearchStats,
+        testing::{build_test_index, test_products},
+        LoadedIndex,
+    };
+
+    let mut products = test_products();
+    products[0].title = "<i class=\"zebrafish\">Xylo\u{7}fon</i>\n\t&aelig;bletr&aelig;".into();
+    products[0].description = "<span class=\"narwhal\">Plakat</span>".into();
+    let id = products[0].id.to_string();
+    let config = IndexConfig {
+        preprocess: PreprocessConfig {
+            title: Normalization::HTML,
+            description: Normalization::NONE,
+            vendor: Normalization::PLAIN_TEXT,
+        },
+        ..IndexConfig::default()
+    };
+    let tolerance = config.typo_tolerance;
+    let built = build_test_index(products, config);
+    let (index, classic) = (LoadedIndex::Eager(built.index), built.classic);
+    let found = |query: &str| {
+        let ranked = index.rank(query, &classic, &tolerance, &SearchStats::default());
+        ranked.results.iter().any(|(product, _)| product.id == id)
+    };
+
+    // The title is stripped of its markup, its entities and its control characters
+    assert!(found("xylofon"));
+    assert!(found("æbletræ"));
+    assert!(found("zebrafish") == false);
+    // While the markup of the description is indexed as it is
+    assert!(found("narwhal"));
+}
@@ -0,0 +1,212 @@
+use crate::{
+    classic_indexes::ClassicIndexes,
+    data::{Document, DocumentContainer, Product},
+    normalize::TextNormalization,
+};
+
+/// The fields a query can be restricted to with `field:value`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryField {
+    Title,
+    Vendor,
+    Tag,
+}
+
+impl QueryField {
+    pub fn from_name(name: &str) -> Option<QueryField> {
+        match name {
+            "title" => Some(QueryField::Title),
+            "vendor" => Some(QueryField::Vendor),
+            "tag" => Some(QueryField::Tag),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            QueryField::Title => "title",
+            QueryField::Vendor => "vendor",
+            QueryField::Tag => "tag",
+        }
+    }
+}
+
+/// A search query split into its fuzzy text and its exact parts.
+///
+/// `"quoted phrases"` have to match exactly, `-term` removes the products matching the term,
+/// and `title:`, `vendor:` and `tag:` restrict the results to products with that value.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ParsedQuery {
+    // Everything that isn't part of the syntax, searched with typo tolerance as before
+    pub text: String,
+    pub phrases: Vec<String>,
+    pub excluded: Vec<String>,
+    pub fields: Vec<(QueryField, String)>,
+}
+
+// Splits off the next word, or the next quoted phrase including its spaces
+fn next_value(input: &str) -> (&str, bool, &str) {
+    if let Some(quoted) = input.strip_prefix('"') {
+        return match quoted.split_once('"') {
+            Some((value, rest)) => (value, true, rest),
+            // An unclosed quote runs to the end of the query
+            None => (quoted, true, ""),
+        };
+    }
+    let end = input.find(char::is_whitespace).unwrap_or(input.len());
+    (&input[..end], false, &input[end..])
+}
+
+impl ParsedQuery {
+    pub fn parse(input: &str) -> ParsedQuery {
+        let mut query = ParsedQuery::default();
+        let mut words: Vec<&str> = Vec::new();
+
+        let mut rest = input.trim_start();
+        while rest.is_empty() == false {
+            if let Some(excluded) = rest
+                .strip_prefix('-')
+                .filter(|r| r.starts_with(' ') == false)
+            {
+                let (value, _, next) = next_value(excluded);
+                if value.is_empty() == false {
+                    query.excluded.push(value.to_string());
+                }
+                rest = next.trim_start();
+                continue;
+            }
+
+            let field = rest.split_once(':').and_then(|(name, value)| {
+                let is_field = name.contains(char::is_whitespace) == false
+                    && value.starts_with(char::is_whitespace) == false;
+                QueryField::from_name(name)
+                    .filter(|_| is_field)
+                    .zip(Some(value))
+            });
+            if let Some((field, value)) = field {
+                let (value, _, next) = next_value(value);
+                if value.is_empty() == false {
+                    query.fields.push((field, value.to_string()));
+                }
+                rest = next.trim_start();
+                continue;
+            }
+
+            let (value, quoted, next) = next_value(rest);
+            if quoted {
+                if value.trim().is_empty() == false {
+                    query.phrases.push(value.to_string());
+                }
+            } else {
+                words.push(value);
+            }
+            rest = next.trim_start();
+        }
+
+        query.text = words.join(" ");
+        query
+    }
+
+    /// True if the query uses any of the syntax, so the results need more than the fuzzy search
+    pub fn is_plain(&self) -> bool {
+        self.phrases.is_empty() && self.excluded.is_empty() && self.fields.is_empty()
+    }
+
+    /// Checks the `field:value` restrictions, values are compared after normalisation
+    pub fn fields_match(
+        &self,
+        product: &Product<'_>,
+        classic: &ClassicIndexes<'_>,
+        normalization: &TextNormalization,
+    ) -> bool {
+        self.fields.iter().all(|(field, value)| {
+            let value = normalization.normalize(value);
+            match field {
+                // Only the stored title can be checked, see `StoragePolicy`
+                QueryField::Title => normalization.normalize(&product.title).contains(&value),
+                QueryField::Vendor => normalization.normalize(&product.vendor.name) == value,
+                QueryField::Tag => classic.tags.iter().any(|tag| {
+                    normalization.normalize(&tag.name) == value && tag.contains(product)
+                }),
+            }
+        })
+    }
+
+    /// Checks the `field:value` restrictions against the stored text field of the same name in a document's schema.
+    /// Documents whose schema doesn't have the field don't match
+    pub fn document_fields_match(
+        &self,
+        document: &Document,
+        container: &DocumentContainer,
+        normalization: &TextNormalization,
+    ) -> bool {
+        self.fields.iter().all(|(field, value)| {
+            container.field(document, field.name()).is_some_and(|text| {
+                normalization
+                    .normalize(text)
+                    .contains(&normalization.normalize(value))
+            })
+        })
+    }
+}
+
+#[test]
+fn test_parse_query() {
+    let query = ParsedQuery::parse(
+        r#"poster "A-12 B" -frame -"red poster" vendor:"Art Co" tag:sale x-ray"#,
+    );
+    assert_eq!(
+        query,
+        ParsedQuery {
+            text: "poster x-ray".to_string(),
+            phrases: vec!["A-12 B".to_string()],
+            excluded: vec!["frame".to_string(), "red poster".to_string()],
+            fields: vec![
+                (QueryField::Vendor, "Art Co".to_string()),
+                (QueryField::Tag, "sale".to_string()),
+            ],
+        }
+    );
+
+    // Things that only look like the syntax are searched as text
+    let query = ParsedQuery::parse("size: 12 - 14 color:red");
+    assert_eq!(query.text, "size: 12 - 14 color:red");
+    assert!(query.is_plain());
+
+    assert_eq!(
+        ParsedQuery::parse(r#""unclosed phrase"#).phrases,
+        vec!["unclosed phrase"]
+    );
+}
+
+#[test]
+fn test_exact_phrases_with_pruning() {
+    use crate::{
+        config::IndexConfig,
+        ngram::SearchStats,
+        testing::{build_test_index, test_products},
+        LoadedIndex,
+    };
+
+    // The default pruning drops the grams of words most products have, like "plakat"
+    let config = IndexConfig::default();
+    let tolerance = config.typo_tolerance;
+    let built = build_test_index(test_products(), config);
+    let (index, classic) = (LoadedIndex::Eager(built.index), built.classic);
+    let titles = |query: &str| -> Vec<String> {
+        let ranked = index.rank(query, &classic, &tolerance, &SearchStats::default());
+        let mut titles: Vec<String> = ranked
+            .results
+            .iter()
+            .map(|(p, _)| p.title.clone())
+            .collect();
+        titles.sort_unstable();
+        titles
+    };
+
+    let camouflage = ["Camouflage 01", "Camouflage 02", "Camouflage 03"];
+    assert_eq!(titles("camouflage"), camouflage);
+    assert_eq!(titles("camouflage \"grafisk plakat\""), camouflage);
+    assert!(titles("camouflage -kunstplakat").is_empty());
+    assert!(titles("\"grafisk plakat\"").len() >= camouflage.len());
+}
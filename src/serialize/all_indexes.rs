@@ -2,21 +2,40 @@ use colosseum::sync::Arena;
 
 use crate::{
     classic_indexes::ClassicIndexes,
-    data::{Product, SuperAlloc},
+    data::{Document, Product, SuperAlloc},
     ngram::{GramAtom, GramIndex, GramNode},
 };
 
-use super::Serializable;
+use super::{
+    header::write_sections,
+    nodes::{serialize_gram_data, serialize_gram_tree},
+    IndexSections, Section, Serializable,
+};
+
+pub(super) fn to_bytes<T: Serializable>(item: &T) -> Vec<u8> {
+    let mut out = Vec::new();
+    item.serialize(&mut |input| out.push(input));
+    out
+}
 
 pub fn serialize_all<G: GramAtom, const N: usize>(
     ngram: &GramIndex<'_, G, Product<'_>, N>,
     classic: &ClassicIndexes<'_>,
 ) -> Vec<u8> {
-    let mut out = Vec::new();
-    let mut save = |input: u8| out.push(input);
-    ngram.serialize(&mut save);
-    classic.serialize(&mut save);
-    out
+    let normalization = &ngram.container.config.normalization;
+    write_sections(
+        N,
+        normalization,
+        vec![
+            (Section::Products, to_bytes(ngram.container)),
+            (Section::GramTree, serialize_gram_tree(&ngram.roots)),
+            (Section::GramData, serialize_gram_data(&ngram.data)),
+            (Section::Classic, to_bytes(classic)),
+            (Section::Shards, to_bytes(&0usize)),
+            (Section::Rules, to_bytes(&ngram.container.config.rules)),
+            (Section::Documents, Vec::new()),
+        ],
+    )
 }
 
 pub fn deserialize_all<'arena, G: GramAtom, const N: usize>(
@@ -27,8 +46,39 @@ pub fn deserialize_all<'arena, G: GramAtom, const N: usize>(
     GramIndex<'arena, G, Product<'arena>, N>,
     ClassicIndexes<'arena>,
 )> {
-    let (input, ngram) = GramIndex::deserialize(input, node_arena, super_alloc)?;
-    let (_, classic) = ClassicIndexes::deserialize(input, ngram.product_container)?;
+    let sections = IndexSections::parse(input)?;
+    let ngram = GramIndex::deserialize(&sections, node_arena, super_alloc)?;
+    let (_, classic) =
+        ClassicIndexes::deserialize(sections.get(Section::Classic), ngram.container)?;
 
     Some((ngram, classic))
 }
+
+/// Serializes an index of documents, which has no product, classic or shard sections
+pub fn serialize_documents<G: GramAtom, const N: usize>(
+    ngram: &GramIndex<'_, G, Document, N>,
+) -> Vec<u8> {
+    let config = &ngram.container.config;
+    write_sections(
+        N,
+        &config.normalization,
+        vec![
+            (Section::Products, Vec::new()),
+            (Section::GramTree, serialize_gram_tree(&ngram.roots)),
+            (Section::GramData, serialize_gram_data(&ngram.data)),
+            (Section::Classic, Vec::new()),
+            (Section::Shards, to_bytes(&0usize)),
+            (Section::Rules, to_bytes(&config.rules)),
+            (Section::Documents, to_bytes(ngram.container)),
+        ],
+    )
+}
+
+pub fn deserialize_documents<'arena, G: GramAtom, const N: usize>(
+    input: &[u8],
+    node_arena: &'arena Arena<GramNode<'arena, G>>,
+    super_alloc: &'static SuperAlloc,
+) -> Option<GramIndex<'arena, G, Document, N>> {
+    let sections = IndexSections::parse(input)?;
+    GramIndex::deserialize_documents(&sections, node_arena, super_alloc)
+}
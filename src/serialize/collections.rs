@@ -38,7 +38,8 @@ impl Serializable for Vec<usize> {
 impl<T: Deserializable> Deserializable for Vec<T> {
     fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
         let (mut input, len) = usize::deserialize(input)?;
-        let mut out = Vec::with_capacity(len);
+        // Items take a byte or more, so a broken length can't reserve more than the input
+        let mut out = Vec::with_capacity(len.min(input.len()));
         for _ in 0..len {
             let (new_input, item) = T::deserialize(input)?;
             input = new_input;
@@ -48,6 +49,26 @@ impl<T: Deserializable> Deserializable for Vec<T> {
     }
 }
 
+impl<T: Serializable> Serializable for Option<T> {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        self.is_some().serialize(output);
+        if let Some(item) = self {
+            item.serialize(output);
+        }
+    }
+}
+
+impl<T: Deserializable> Deserializable for Option<T> {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (input, is_some) = bool::deserialize(input)?;
+        if is_some == false {
+            return Some((input, None));
+        }
+        let (input, item) = T::deserialize(input)?;
+        Some((input, Some(item)))
+    }
+}
+
 impl<'arena, T: ArenaDeserializable<'arena, T>> ArenaDeserializableCollection<'arena, T>
     for Vec<&'arena T>
 {
@@ -59,7 +80,7 @@ impl<'arena, T: ArenaDeserializable<'arena, T>> ArenaDeserializableCollection<'a
         'arena: 'input,
     {
         let (mut input, len) = usize::deserialize(input)?;
-        let mut out = Vec::with_capacity(len);
+        let mut out = Vec::with_capacity(len.min(input.len()));
         for _ in 0..len {
             let (new_input, item) = T::deserialize_arena(input, arena)?;
             input = new_input;
@@ -84,10 +105,11 @@ impl Serializable for String {
     }
 }
 
-fn limit_string_len(input: &str, max_len: usize) -> &str {
+// Cuts the string to at most `max_len` characters, at the last space before them if there is one
+pub fn limit_string_len(input: &str, max_len: usize) -> &str {
     let mut last_candidate = None;
-    for (offset, char) in input.char_indices() {
-        if offset > max_len {
+    for (count, (offset, char)) in input.char_indices().enumerate() {
+        if count >= max_len {
             // Then we return either the last viable candidate, or now
             return match last_candidate {
                 Some(viable) => &input[0..viable],
@@ -107,14 +129,16 @@ pub fn serialize_string_with_limit<Out: FnMut(u8)>(input: &str, max_len: usize,
 
 #[test]
 fn test() {
-    for str in [
-        "",
-        "asudfhj asiudf",
-        "as ias ias is a",
-        "oai aisæø øåæå",
-        "👽 👽 👽👽 👽👽",
+    for (str, expected) in [
+        ("", ""),
+        ("asudfhj asiudf", "asudfhj"),
+        ("as ias ias is a", "as ias ias"),
+        ("oai aisæø øåæå", "oai aisæø"),
+        // Characters are counted rather than bytes, so this fits
+        ("👽 👽 👽👽 👽👽", "👽 👽 👽👽 👽👽"),
+        ("asudfhjasiudfg", "asudfhjasiud"),
     ] {
-        println!("'{}'", limit_string_len(str, 12));
+        assert_eq!(limit_string_len(str, 12), expected);
     }
 }
 
@@ -127,10 +151,14 @@ impl Deserializable for String {
     }
 }
 
-impl<K: Serializable, V: Serializable> Serializable for AHashMap<K, V> {
+impl<K: Serializable + Ord, V: Serializable> Serializable for AHashMap<K, V> {
     fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        // Entries are written in key order, so the same content always gives the same bytes
+        let mut entries: Vec<_> = self.iter().collect();
+        entries.sort_by_key(|(key, _)| *key);
+
         self.len().serialize(output);
-        for (key, value) in self.iter() {
+        for (key, value) in entries {
             key.serialize(output);
             value.serialize(output);
         }
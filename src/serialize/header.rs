@@ -0,0 +1,147 @@
+use crate::{
+    data::{DocumentContainer, ProductContainer, SuperAlloc},
+    merchandising::MerchandisingRules,
+    normalize::TextNormalization,
+};
+
+use super::{Deserializable, Serializable};
+
+// Bumped whenever the layout of a section changes
+pub const FORMAT_VERSION: u32 = 18;
+
+/// The parts of a serialized index, in the order they're written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    Products,
+    GramTree,
+    GramData,
+    Classic,
+    // How many gram shards the grams were split into, 0 if they're in this blob
+    Shards,
+    // Merchandising rules, see `IndexConfig::rules`
+    Rules,
+    // The documents of an index built from schemas, empty for product indexes
+    Documents,
+}
+
+impl Section {
+    pub const ALL: [Section; 7] = [
+        Section::Products,
+        Section::GramTree,
+        Section::GramData,
+        Section::Classic,
+        Section::Shards,
+        Section::Rules,
+        Section::Documents,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Section::Products => "products",
+            Section::GramTree => "gram_tree",
+            Section::GramData => "gram_data",
+            Section::Classic => "classic",
+            Section::Shards => "shards",
+            Section::Rules => "rules",
+            Section::Documents => "documents",
+        }
+    }
+}
+
+/// The header of a serialized index, and the sections it points to.
+///
+/// Each section is prefixed by its length in the header, so a reader can slice out any section without parsing the ones before it.
+pub struct IndexSections<'i> {
+    pub version: u32,
+    // The n of the n-grams, an index is only read back with the size it was built with
+    pub gram_size: usize,
+    // How the indexed text was normalised, queries have to be normalised the same way
+    pub normalization: TextNormalization,
+    sections: Vec<&'i [u8]>,
+}
+
+impl<'i> IndexSections<'i> {
+    pub fn parse(input: &'i [u8]) -> Option<IndexSections<'i>> {
+        let (input, version) = u32::deserialize(input)?;
+        if version != FORMAT_VERSION {
+            return None;
+        }
+        let (input, gram_size) = usize::deserialize(input)?;
+        let (input, normalization) = TextNormalization::deserialize(input)?;
+        let (mut input, lengths): (_, Vec<usize>) = Deserializable::deserialize(input)?;
+        if lengths.len() != Section::ALL.len() {
+            return None;
+        }
+
+        let mut sections = Vec::with_capacity(lengths.len());
+        for len in lengths {
+            sections.push(input.get(..len)?);
+            input = &input[len..];
+        }
+
+        Some(IndexSections {
+            version,
+            gram_size,
+            normalization,
+            sections,
+        })
+    }
+
+    pub fn get(&self, section: Section) -> &'i [u8] {
+        self.sections[section as usize]
+    }
+
+    /// Decodes the products section, with the normalisation from the header and the rules put back into its config
+    pub fn products(
+        &self,
+        super_alloc: &'static SuperAlloc,
+    ) -> Option<&'static ProductContainer<'static>> {
+        let (_, mut container) =
+            ProductContainer::deserialize(self.get(Section::Products), super_alloc)?;
+        container.config.normalization = self.normalization;
+        let (_, rules) = MerchandisingRules::deserialize(self.get(Section::Rules))?;
+        container.config.rules = rules;
+        Some(super_alloc.alloc(container))
+    }
+
+    /// Decodes the documents section, like `products` does for product indexes
+    pub fn documents(
+        &self,
+        super_alloc: &'static SuperAlloc,
+    ) -> Option<&'static DocumentContainer> {
+        let (_, mut container) = DocumentContainer::deserialize(self.get(Section::Documents))?;
+        container.config.normalization = self.normalization;
+        let (_, rules) = MerchandisingRules::deserialize(self.get(Section::Rules))?;
+        container.config.rules = rules;
+        Some(super_alloc.alloc(container))
+    }
+
+    pub fn sizes(&self) -> impl Iterator<Item = (Section, usize)> + '_ {
+        Section::ALL
+            .into_iter()
+            .map(|section| (section, self.get(section).len()))
+    }
+}
+
+pub fn write_sections(
+    gram_size: usize,
+    normalization: &TextNormalization,
+    sections: Vec<(Section, Vec<u8>)>,
+) -> Vec<u8> {
+    assert!(
+        sections.iter().map(|(s, _)| *s).eq(Section::ALL),
+        "Sections must be written in order"
+    );
+
+    let mut out = Vec::new();
+    let mut save = |input: u8| out.push(input);
+    FORMAT_VERSION.serialize(&mut save);
+    gram_size.serialize(&mut save);
+    normalization.serialize(&mut save);
+    let lengths: Vec<usize> = sections.iter().map(|(_, bytes)| bytes.len()).collect();
+    lengths.serialize(&mut save);
+    for (_, bytes) in sections {
+        out.extend(bytes);
+    }
+    out
+}
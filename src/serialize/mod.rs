@@ -1,13 +1,21 @@
 mod all_indexes;
 mod collections;
+mod header;
 mod nodes;
 mod primitives;
+mod shards;
 mod traits;
 
+pub mod packed_array;
+pub mod patch;
 pub mod sequential_array;
 
-pub use all_indexes::{deserialize_all, serialize_all};
-pub use collections::serialize_string_with_limit;
+pub use all_indexes::{deserialize_all, deserialize_documents, serialize_all, serialize_documents};
+pub use collections::{limit_string_len, serialize_string_with_limit};
+pub use header::{write_sections, IndexSections, Section, FORMAT_VERSION};
+pub(crate) use nodes::{read_node, GramDataSection, GramTreeSection, NodeRecord};
+pub use nodes::{serialize_gram_data, serialize_gram_tree};
+pub use shards::{serialize_sharded, shard_count, shard_for, GramShard, ShardedBlobs};
 pub use traits::*;
 
 #[cfg(test)]
@@ -23,7 +31,8 @@ fn test_serialize_deserialize_wasm() {
 fn test_serialize_and_deserialize() -> Result<(), Box<dyn std::error::Error>> {
     use crate::{
         classic_indexes::ClassicIndexes,
-        data::{optimize, RawProduct},
+        config::{IndexConfig, StoragePolicy},
+        data::{optimize_with_config, RawProduct},
         ngram::{GramIndex, IndexFeed},
         Product, SuperAlloc,
     };
@@ -40,7 +49,15 @@ fn test_serialize_and_deserialize() -> Result<(), Box<dyn std::error::Error>> {
         static ref SUPER_ARENA: SuperAlloc = SuperAlloc::new();
     }
 
-    let (prods, classic) = optimize(products, &SUPER_ARENA);
+    let (prods, classic) = optimize_with_config(
+        products,
+        // Full descriptions, so the products survive the round trip unchanged
+        IndexConfig {
+            description: StoragePolicy::FULL,
+            ..IndexConfig::default()
+        },
+        &SUPER_ARENA,
+    );
 
     let iter = prods.products.iter().map(|p| {
         let Product {
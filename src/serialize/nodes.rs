@@ -2,102 +2,393 @@ use ahash::AHashMap;
 use colosseum::sync::Arena;
 
 use crate::{
-    data::{ProductContainer, SuperAlloc},
-    ngram::{GramAtom, GramIndex, GramNode},
+    data::{Document, SuperAlloc},
+    ngram::{GramAtom, GramIndex, GramNode, IndexedData},
     Product,
 };
 
-use super::{ArenaDeserializable, ArenaDeserializableCollection, Deserializable, Serializable};
+use super::{packed_array, Deserializable, IndexSections, Section, Serializable};
 
-fn serialize_node<G: GramAtom, Out: FnMut(u8)>(node: &GramNode<'_, G>, output: &mut Out) {
-    node.item.serialize(output);
-    node.weight.serialize(output);
-    node.by_occurances.serialize(output);
+/*
+The gram tree and gram data sections are laid out so they can be navigated without parsing them first,
+which is what `LazyGramIndex` does. `GramIndex::deserialize` reads the same layout into arenas.
+
+Gram tree:
+    root count, then a table of root offsets sorted by gram, then the node records.
+    A node record is its gram, weight, child count and the distances back to its children, most popular first.
+
+Gram data:
+    entry count, restart count, a table of restart offsets, then the entries.
+    Entries are sorted by key and front coded: each stores how many grams it shares with the key before it,
+    the remaining grams, and its postings. Every RESTART_INTERVAL entries the key is written in full,
+    so a reader can binary search the restarts and only scan a few entries.
+*/
+
+// Offsets are written as fixed width, so tables can be indexed directly
+const OFFSET_WIDTH: usize = 4;
+const RESTART_INTERVAL: usize = 16;
+
+fn write_offset(offset: usize, output: &mut Vec<u8>) {
+    let offset = u32::try_from(offset).expect("Index sections are limited to 4GB");
+    output.extend(offset.to_be_bytes());
+}
+
+pub(crate) fn read_offset(table: &[u8], index: usize) -> Option<usize> {
+    let start = index * OFFSET_WIDTH;
+    let bytes = table.get(start..start + OFFSET_WIDTH)?;
+    let offset = u32::from_be_bytes(bytes.try_into().ok()?);
+    usize::try_from(offset).ok()
+}
+
+fn split_table(input: &[u8]) -> Option<(&[u8], usize, &[u8])> {
+    let (input, len) = usize::deserialize(input)?;
+    let table_len = len.checked_mul(OFFSET_WIDTH)?;
+    let table = input.get(..table_len)?;
+    Some((&input[table_len..], len, table))
+}
+
+fn write_node<G: GramAtom>(node: &GramNode<'_, G>, nodes: &mut Vec<u8>) -> usize {
+    // Children are written first, so we know their offsets when writing the parent
+    let children: Vec<usize> = node
+        .by_occurances
+        .iter()
+        .map(|child| write_node(child, nodes))
+        .collect();
+
+    let offset = nodes.len();
+    let mut save = |input: u8| nodes.push(input);
+    node.item.serialize(&mut save);
+    node.weight.serialize(&mut save);
+    children.len().serialize(&mut save);
+    // Children are always read in order, so they're stored as varint distances back from the parent
+    for child in children {
+        (offset - child).serialize(&mut save);
+    }
+    offset
+}
+
+pub fn serialize_gram_tree<G: GramAtom>(roots: &AHashMap<G, &GramNode<'_, G>>) -> Vec<u8> {
+    let mut roots: Vec<_> = roots.iter().collect();
+    roots.sort_by_key(|(gram, _)| *gram);
+
+    let mut nodes = Vec::new();
+    let offsets: Vec<usize> = roots
+        .iter()
+        .map(|(_, node)| write_node(node, &mut nodes))
+        .collect();
+
+    let mut out = Vec::with_capacity(nodes.len() + offsets.len() * OFFSET_WIDTH + 8);
+    offsets.len().serialize(&mut |input| out.push(input));
+    for offset in offsets {
+        write_offset(offset, &mut out);
+    }
+    out.extend(nodes);
+    out
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct NodeRecord<'s, G> {
+    pub item: G,
+    pub weight: f32,
+    pub child_count: usize,
+    offset: usize,
+    children: &'s [u8],
+}
+
+impl<'s, G> NodeRecord<'s, G> {
+    /// The offsets of the children, most popular first
+    pub fn child_offsets(&self) -> impl Iterator<Item = usize> + 's {
+        let offset = self.offset;
+        let mut input = self.children;
+        (0..self.child_count).map_while(move |_| {
+            let (next_input, distance) = usize::deserialize(input)?;
+            input = next_input;
+            // Children are written before their parent, so a node can't be its own child
+            Some(distance)
+                .filter(|d| *d > 0)
+                .and_then(|distance| offset.checked_sub(distance))
+        })
+    }
+}
+
+pub(crate) fn read_node<G: GramAtom>(nodes: &[u8], offset: usize) -> Option<NodeRecord<'_, G>> {
+    let input = nodes.get(offset..)?;
+    let (input, item) = G::deserialize(input)?;
+    let (input, weight) = f32::deserialize(input)?;
+    let (children, child_count) = usize::deserialize(input)?;
+    Some(NodeRecord {
+        item,
+        weight,
+        child_count,
+        offset,
+        children,
+    })
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct GramTreeSection<'s> {
+    pub roots: &'s [u8],
+    pub root_count: usize,
+    pub nodes: &'s [u8],
+}
+
+impl<'s> GramTreeSection<'s> {
+    pub fn parse(input: &'s [u8]) -> Option<GramTreeSection<'s>> {
+        let (nodes, root_count, roots) = split_table(input)?;
+        Some(GramTreeSection {
+            roots,
+            root_count,
+            nodes,
+        })
+    }
+
+    pub fn find_root<G: GramAtom>(&self, gram: &G) -> Option<NodeRecord<'s, G>> {
+        // The roots are sorted by gram, so we binary search them
+        let (mut low, mut high) = (0, self.root_count);
+        while low < high {
+            let middle = usize::midpoint(low, high);
+            let node = read_node::<G>(self.nodes, read_offset(self.roots, middle)?)?;
+            match node.item.cmp(gram) {
+                std::cmp::Ordering::Equal => return Some(node),
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+            }
+        }
+        None
+    }
+}
+
+fn shared_prefix<G: GramAtom>(a: &[G], b: &[G]) -> usize {
+    a.iter().zip(b.iter()).take_while(|(a, b)| a == b).count()
 }
 
-impl<G: GramAtom> Serializable for GramNode<'_, G> {
-    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
-        serialize_node(self, output);
+pub fn serialize_gram_data<G: GramAtom, Data: IndexedData, const N: usize>(
+    data: &AHashMap<[G; N], Vec<&Data>>,
+) -> Vec<u8> {
+    let mut data: Vec<_> = data.iter().collect();
+    data.sort_by_key(|(key, _)| *key);
+
+    let mut entries = Vec::new();
+    let mut restarts = Vec::with_capacity(data.len() / RESTART_INTERVAL + 1);
+    let mut previous: Option<&[G; N]> = None;
+    for (i, (key, products)) in data.iter().enumerate() {
+        let shared = if i % RESTART_INTERVAL == 0 {
+            restarts.push(entries.len());
+            0
+        } else {
+            previous.map_or(0, |previous| shared_prefix(previous, &key[..]))
+        };
+
+        let mut save = |input: u8| entries.push(input);
+        shared.serialize(&mut save);
+        for gram in &key[shared..] {
+            gram.serialize(&mut save);
+        }
+        // The postings are stored as bit packed deltas of serialization ids
+        packed_array::serialize(products.iter().map(|p| p.serialization_id()), &mut save);
+        previous = Some(key);
     }
+
+    let mut out = Vec::with_capacity(entries.len() + restarts.len() * OFFSET_WIDTH + 8);
+    data.len().serialize(&mut |input| out.push(input));
+    restarts.len().serialize(&mut |input| out.push(input));
+    for restart in restarts {
+        write_offset(restart, &mut out);
+    }
+    out.extend(entries);
+    out
 }
 
-impl<G: GramAtom> Serializable for &GramNode<'_, G> {
-    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
-        serialize_node(self, output);
+fn read_key<'i, G: GramAtom, const N: usize>(
+    mut input: &'i [u8],
+    key: &mut [G; N],
+) -> Option<&'i [u8]> {
+    let (after_shared, shared) = usize::deserialize(input)?;
+    if shared > N {
+        return None;
+    }
+    input = after_shared;
+    for gram in &mut key[shared..] {
+        let (after_gram, parsed) = G::deserialize(input)?;
+        *gram = parsed;
+        input = after_gram;
     }
+    Some(input)
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct GramDataSection<'s> {
+    len: usize,
+    restarts: &'s [u8],
+    restart_count: usize,
+    entries: &'s [u8],
 }
 
-impl<'arena, G: GramAtom> ArenaDeserializable<'arena, Self> for GramNode<'arena, G> {
-    fn deserialize_arena<'input>(
-        input: &'input [u8],
-        arena: &'arena Arena<Self>,
-    ) -> Option<(&'input [u8], &'arena Self)>
-    where
-        'arena: 'input,
-    {
-        let (input, item) = <G as Deserializable>::deserialize(input)?;
-        let (input, weight) = f32::deserialize(input)?;
-        let (input, by_occurances) = Vec::deserialize_arena(input, arena)?;
-        let items = by_occurances.iter().fold(AHashMap::new(), |mut map, item| {
-            map.insert(item.item, *item);
-            map
-        });
+impl<'s> GramDataSection<'s> {
+    pub fn parse(input: &'s [u8]) -> Option<GramDataSection<'s>> {
+        let (input, len) = usize::deserialize(input)?;
+        let (entries, restart_count, restarts) = split_table(input)?;
+        Some(GramDataSection {
+            len,
+            restarts,
+            restart_count,
+            entries,
+        })
+    }
+
+    fn restart_key<G: GramAtom, const N: usize>(&self, restart: usize) -> Option<[G; N]> {
+        let mut key = [G::default(); N];
+        read_key(
+            self.entries.get(read_offset(self.restarts, restart)?..)?,
+            &mut key,
+        )?;
+        Some(key)
+    }
+
+    /// Finds the serialization ids stored for a key, only decoding the entries around it
+    pub fn find_postings<G: GramAtom, const N: usize>(
+        &self,
+        target: &[G; N],
+    ) -> Option<Vec<usize>> {
+        // We find the last restart with a key before or at the target
+        let (mut low, mut high) = (0, self.restart_count);
+        while low < high {
+            let middle = usize::midpoint(low, high);
+            if self.restart_key::<G, N>(middle)? <= *target {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        let restart = low.checked_sub(1)?;
+
+        let mut input = self.entries.get(read_offset(self.restarts, restart)?..)?;
+        let mut key = [G::default(); N];
+        // A section that counts fewer entries than its restarts point to is broken
+        let entries_left = self
+            .len
+            .checked_sub(restart.checked_mul(RESTART_INTERVAL)?)?
+            .min(RESTART_INTERVAL);
+        for _ in 0..entries_left {
+            input = read_key(input, &mut key)?;
+            match key.cmp(target) {
+                std::cmp::Ordering::Equal => return Some(packed_array::deserialize(input)?.1),
+                std::cmp::Ordering::Greater => return None,
+                std::cmp::Ordering::Less => input = packed_array::skip(input)?,
+            }
+        }
+        None
+    }
+
+    /// Decodes every entry, in key order
+    pub fn entries<G: GramAtom, const N: usize>(&self) -> Option<Vec<([G; N], Vec<usize>)>> {
+        let mut input = self.entries;
+        let mut key = [G::default(); N];
+        // Entries take a byte or more, so a broken count can't reserve more than the section
+        let mut out = Vec::with_capacity(self.len.min(self.entries.len()));
+        for _ in 0..self.len {
+            input = read_key(input, &mut key)?;
+            let (next_input, ids) = packed_array::deserialize(input)?;
+            input = next_input;
+            out.push((key, ids));
+        }
+        Some(out)
+    }
+}
 
-        let node: &'arena GramNode<'arena, G> = arena.alloc(GramNode {
-            item,
-            weight,
-            by_occurances,
-            items,
-        });
-        Some((input, node))
+fn build_node<'arena, G: GramAtom>(
+    nodes: &[u8],
+    offset: usize,
+    arena: &'arena Arena<GramNode<'arena, G>>,
+) -> Option<&'arena GramNode<'arena, G>> {
+    let record = read_node::<G>(nodes, offset)?;
+    let mut by_occurances = Vec::with_capacity(record.child_count.min(record.children.len()));
+    for child in record.child_offsets() {
+        by_occurances.push(build_node(nodes, child, arena)?);
     }
+    if by_occurances.len() != record.child_count {
+        return None;
+    }
+    let items = by_occurances.iter().fold(AHashMap::new(), |mut map, item| {
+        map.insert(item.item, *item);
+        map
+    });
+
+    Some(arena.alloc(GramNode {
+        item: record.item,
+        weight: record.weight,
+        by_occurances,
+        items,
+    }))
 }
 
-impl<G: GramAtom, const N: usize> Serializable for GramIndex<'_, G, Product<'_>, N> {
-    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
-        self.product_container.serialize(output);
-        self.roots.serialize(output);
+// The gram tree and the postings of a blob's gram sections, with the postings looked up in its container
+type GramParts<'arena, G, Data, const N: usize> = (
+    AHashMap<G, &'arena GramNode<'arena, G>>,
+    AHashMap<[G; N], Vec<&'arena Data>>,
+);
+
+fn deserialize_grams<'arena, G: GramAtom, Data, const N: usize>(
+    sections: &IndexSections<'_>,
+    node_arena: &'arena Arena<GramNode<'arena, G>>,
+    all: &'arena [Data],
+) -> Option<GramParts<'arena, G, Data, N>> {
+    let tree = GramTreeSection::parse(sections.get(Section::GramTree))?;
+    let mut roots = AHashMap::with_capacity(tree.root_count.min(tree.nodes.len()));
+    for root in 0..tree.root_count {
+        let node = build_node(tree.nodes, read_offset(tree.roots, root)?, node_arena)?;
+        roots.insert(node.item, node);
+    }
 
-        // We replace the product refs with their serialization id and save as a sequential array
-        self.data.len().serialize(output);
-        for (key, products) in self.data.iter() {
-            key.serialize(output);
-            Product::serialize_to_sequential_array(products, output);
+    let gram_data = GramDataSection::parse(sections.get(Section::GramData))?;
+    let mut data = AHashMap::with_capacity(gram_data.len.min(gram_data.entries.len()));
+    for (key, ids) in gram_data.entries::<G, N>()? {
+        let mut postings = Vec::with_capacity(ids.len());
+        for id in ids {
+            postings.push(all.get(id)?);
         }
+        data.insert(key, postings);
     }
+    Some((roots, data))
 }
 
 impl<'arena, G: GramAtom, const N: usize> GramIndex<'arena, G, Product<'arena>, N> {
-    pub fn deserialize<'input, 'super_arena>(
-        input: &'input [u8],
+    pub fn deserialize(
+        sections: &IndexSections<'_>,
         node_arena: &'arena Arena<GramNode<'arena, G>>,
         super_alloc: &'static SuperAlloc,
-    ) -> Option<(&'input [u8], Self)>
-    where
-        'super_arena: 'arena,
-        'arena: 'input,
-    {
-        let (input, container) = ProductContainer::deserialize(input, super_alloc)?;
-        let container = super_alloc.alloc(container);
-        let (input, roots) = AHashMap::deserialize_arena(input, node_arena)?;
-
-        let (mut input, data_len) = usize::deserialize(input)?;
-        let mut data = AHashMap::with_capacity(data_len);
-        for _ in 0..data_len {
-            let (next_input, gram) = <[G; N]>::deserialize(input)?;
-            let (next_input, products, _) =
-                Product::deserialize_from_sequential_ids(next_input, &container.products)?;
-            input = next_input;
-            data.insert(gram, products);
+    ) -> Option<Self> {
+        // The grams of an index are only read with the size they were built with
+        if sections.gram_size != N {
+            return None;
+        }
+        let container = sections.products(super_alloc)?;
+        let (roots, data) = deserialize_grams(sections, node_arena, &container.products)?;
+
+        Some(GramIndex {
+            roots,
+            data,
+            container,
+        })
+    }
+}
+
+impl<'arena, G: GramAtom, const N: usize> GramIndex<'arena, G, Document, N> {
+    pub fn deserialize_documents(
+        sections: &IndexSections<'_>,
+        node_arena: &'arena Arena<GramNode<'arena, G>>,
+        super_alloc: &'static SuperAlloc,
+    ) -> Option<Self> {
+        if sections.gram_size != N {
+            return None;
         }
+        let container = sections.documents(super_alloc)?;
+        let (roots, data) = deserialize_grams(sections, node_arena, &container.documents)?;
 
-        Some((
-            input,
-            GramIndex {
-                product_container: container,
-                roots,
-                data,
-            },
-        ))
+        Some(GramIndex {
+            roots,
+            data,
+            container,
+        })
     }
 }
@@ -0,0 +1,186 @@
+use super::{Deserializable, Serializable};
+
+// Deltas are packed in blocks, each with its own bit width, so one large gap only widens its own block
+const BLOCK_LEN: usize = 128;
+// Below this length the block header costs more than it saves, so the deltas are written as varints
+const SHORT_LIST_LEN: usize = 4;
+
+fn bit_width(value: u64) -> u8 {
+    #[allow(clippy::cast_possible_truncation)]
+    let width = (64 - value.leading_zeros()) as u8;
+    width
+}
+
+fn pack_block<Out: FnMut(u8)>(block: &[u64], output: &mut Out) {
+    let width = block.iter().copied().map(bit_width).max().unwrap_or(0);
+    width.serialize(output);
+
+    let mut buffer: u128 = 0;
+    let mut buffered_bits = 0;
+    for value in block {
+        buffer |= u128::from(*value) << buffered_bits;
+        buffered_bits += u32::from(width);
+        while buffered_bits >= 8 {
+            #[allow(clippy::cast_possible_truncation)]
+            output(buffer as u8);
+            buffer >>= 8;
+            buffered_bits -= 8;
+        }
+    }
+    if buffered_bits > 0 {
+        #[allow(clippy::cast_possible_truncation)]
+        output(buffer as u8);
+    }
+}
+
+fn unpack_block<'i>(input: &'i [u8], len: usize, output: &mut Vec<u64>) -> Option<&'i [u8]> {
+    let (input, width) = u8::deserialize(input)?;
+    if width > 64 {
+        return None;
+    }
+    let width = u32::from(width);
+    let byte_len = (len * width as usize).div_ceil(8);
+    let bytes = input.get(..byte_len)?;
+    // The length is only trusted once its bytes are there
+    output.reserve(len);
+
+    let mask = if width == 64 {
+        u128::from(u64::MAX)
+    } else {
+        (1u128 << width) - 1
+    };
+    let mut buffer: u128 = 0;
+    let mut buffered_bits = 0;
+    let mut bytes = bytes.iter();
+    for _ in 0..len {
+        while buffered_bits < width {
+            buffer |= u128::from(*bytes.next()?) << buffered_bits;
+            buffered_bits += 8;
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        output.push((buffer & mask) as u64);
+        buffer >>= width;
+        buffered_bits -= width;
+    }
+
+    Some(&input[byte_len..])
+}
+
+/// Like `sequential_array`, but longer lists have their deltas bit packed in blocks instead of written as varints
+pub fn serialize<I: Iterator<Item = usize>, Out: FnMut(u8)>(input: I, output: &mut Out) {
+    let mut content: Vec<u64> = input.map(|v| v as u64).collect();
+    content.sort_unstable();
+
+    content.len().serialize(output);
+
+    let mut previous = 0;
+    for item in &mut content {
+        let current = *item;
+        *item -= previous;
+        previous = current;
+    }
+
+    if content.len() < SHORT_LIST_LEN {
+        for delta in content {
+            delta.serialize(output);
+        }
+        return;
+    }
+
+    for block in content.chunks(BLOCK_LEN) {
+        pack_block(block, output);
+    }
+}
+
+pub fn deserialize(input: &[u8]) -> Option<(&[u8], Vec<usize>)> {
+    let (mut input, len) = usize::deserialize(input)?;
+
+    let mut deltas = Vec::new();
+    if len < SHORT_LIST_LEN {
+        for _ in 0..len {
+            let (next_input, delta) = u64::deserialize(input)?;
+            deltas.push(delta);
+            input = next_input;
+        }
+    } else {
+        // Every block takes a byte or more for its bit width, so a length the input can't hold is broken
+        if len.div_ceil(BLOCK_LEN) > input.len() {
+            return None;
+        }
+        let mut remaining = len;
+        while remaining > 0 {
+            let block_len = remaining.min(BLOCK_LEN);
+            input = unpack_block(input, block_len, &mut deltas)?;
+            remaining -= block_len;
+        }
+    }
+
+    let mut output = Vec::with_capacity(deltas.len());
+    let mut previous: usize = 0;
+    for delta in deltas {
+        previous = previous.checked_add(usize::try_from(delta).ok()?)?;
+        output.push(previous);
+    }
+
+    Some((input, output))
+}
+
+/// Moves past a list without decoding it
+pub fn skip(input: &[u8]) -> Option<&[u8]> {
+    let (mut input, len) = usize::deserialize(input)?;
+    if len < SHORT_LIST_LEN {
+        for _ in 0..len {
+            input = u64::deserialize(input)?.0;
+        }
+        return Some(input);
+    }
+
+    let mut remaining = len;
+    while remaining > 0 {
+        let block_len = remaining.min(BLOCK_LEN);
+        let (after_width, width) = u8::deserialize(input)?;
+        let byte_len = (block_len * usize::from(width)).div_ceil(8);
+        input = after_width.get(byte_len..)?;
+        remaining -= block_len;
+    }
+    Some(input)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_packed_array_roundtrip() {
+        let long: Vec<usize> = (0..1_000).map(|i| i * i % 7_919).collect();
+        for input in [
+            vec![],
+            vec![0],
+            vec![5, 5, 5],
+            vec![1, 2, 3, 300, 70_000],
+            vec![usize::MAX, 0],
+            long,
+        ] {
+            let mut bytes = Vec::new();
+            super::serialize(input.iter().copied(), &mut |b| bytes.push(b));
+            let (rest, output) = super::deserialize(&bytes).unwrap();
+            assert!(super::skip(&bytes).unwrap().is_empty());
+
+            let mut expected = input.clone();
+            expected.sort_unstable();
+            assert!(rest.is_empty());
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn test_packed_array_rejects_broken_length() {
+        use crate::serialize::Serializable;
+
+        // A list claiming more entries than its bytes hold is broken, whatever its blocks' widths
+        for width in [0u8, 1, 64] {
+            let mut bytes = Vec::new();
+            (usize::MAX / 2).serialize(&mut |b| bytes.push(b));
+            bytes.extend([width, 0xff, 0xff]);
+            assert_eq!(super::deserialize(&bytes), None);
+        }
+    }
+}
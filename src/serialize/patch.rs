@@ -0,0 +1,410 @@
+use std::ops::Range;
+
+use ahash::AHashMap;
+
+use super::{Deserializable, IndexSections, Section, Serializable};
+
+// Matches are looked up by the 8 bytes starting at a position, read as a single u64
+const BLOCK_SIZE: usize = 8;
+// A copy costs a tag and two varints, so shorter matches are cheaper to insert directly
+const MIN_COPY_LEN: usize = 12;
+
+#[derive(Debug, PartialEq, Eq)]
+enum PatchOp {
+    Copy { offset: usize, len: usize },
+    Insert(Vec<u8>),
+}
+
+/// A binary diff between two serialized indexes.
+///
+/// Since the serializer writes everything in a deterministic order, a small catalogue change
+/// (a new product, a changed price, a reordered collection) leaves the products and classic sections
+/// mostly as they were, and their part of the patch is mostly made of copies from the old blob.
+/// The gram tree stores relative weights and the gram data absolute restart offsets, so any product added or removed
+/// rewrites a good part of them, and they make up most of a patch
+#[derive(Debug, PartialEq, Eq)]
+pub struct Patch {
+    base_checksum: u64,
+    target_checksum: u64,
+    target_len: usize,
+    ops: Vec<PatchOp>,
+}
+
+// Copies are stored as their distance from the end of the copy before them, which is short
+// since the sections of the new blob are diffed against the same sections of the old one
+fn zigzag(distance: i64) -> u64 {
+    ((distance << 1) ^ (distance >> 63)).cast_unsigned()
+}
+
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1).cast_signed() ^ -(value & 1).cast_signed()
+}
+
+impl Serializable for Patch {
+    fn serialize<Out: FnMut(u8)>(&self, output: &mut Out) {
+        let Patch {
+            base_checksum,
+            target_checksum,
+            target_len,
+            ops,
+        } = self;
+        base_checksum.serialize(output);
+        target_checksum.serialize(output);
+        target_len.serialize(output);
+        ops.len().serialize(output);
+        let mut copied_to = 0;
+        for op in ops {
+            match op {
+                PatchOp::Copy { offset, len } => {
+                    0u8.serialize(output);
+                    #[allow(clippy::cast_possible_wrap)]
+                    zigzag((*offset as i64).wrapping_sub(copied_to as i64)).serialize(output);
+                    len.serialize(output);
+                    copied_to = offset.saturating_add(*len);
+                }
+                PatchOp::Insert(bytes) => {
+                    1u8.serialize(output);
+                    bytes.serialize(output);
+                }
+            }
+        }
+    }
+}
+
+impl Deserializable for Patch {
+    fn deserialize(input: &[u8]) -> Option<(&[u8], Self)> {
+        let (input, base_checksum) = u64::deserialize(input)?;
+        let (input, target_checksum) = u64::deserialize(input)?;
+        let (input, target_len) = usize::deserialize(input)?;
+        let (mut input, op_count) = usize::deserialize(input)?;
+        // Every op takes at least a byte, so a broken count can't make us reserve more than the patch
+        let mut ops = Vec::with_capacity(op_count.min(input.len()));
+        let mut copied_to: usize = 0;
+        for _ in 0..op_count {
+            let (rest, id) = u8::deserialize(input)?;
+            let (rest, op) = match id {
+                0 => {
+                    let (rest, distance) = u64::deserialize(rest)?;
+                    let (rest, len) = usize::deserialize(rest)?;
+                    let offset =
+                        copied_to.checked_add_signed(unzigzag(distance).try_into().ok()?)?;
+                    copied_to = offset.checked_add(len)?;
+                    (rest, PatchOp::Copy { offset, len })
+                }
+                1 => {
+                    let (rest, bytes) = Deserializable::deserialize(rest)?;
+                    (rest, PatchOp::Insert(bytes))
+                }
+                _ => return None,
+            };
+            input = rest;
+            ops.push(op);
+        }
+        Some((
+            input,
+            Patch {
+                base_checksum,
+                target_checksum,
+                target_len,
+                ops,
+            },
+        ))
+    }
+}
+
+/// FNV-1a, used to make sure a patch is applied to the blob it was made from
+pub fn checksum(input: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in input {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+fn block_at(input: &[u8], position: usize) -> Option<u64> {
+    let bytes = input.get(position..position + BLOCK_SIZE)?;
+    Some(u64::from_le_bytes(bytes.try_into().ok()?))
+}
+
+// Where the header and every section of a serialized index are, None for blobs that aren't indexes
+fn section_ranges(blob: &[u8]) -> Option<Vec<Range<usize>>> {
+    let sections = IndexSections::parse(blob)?;
+    let mut start = blob.len() - sections.sizes().map(|(_, len)| len).sum::<usize>();
+    let mut ranges = Vec::with_capacity(Section::ALL.len() + 1);
+    ranges.push(0..start);
+    for (_, len) in sections.sizes() {
+        ranges.push(start..start + len);
+        start += len;
+    }
+    Some(ranges)
+}
+
+// The ops of a diff, as the new blob is walked from start to end
+struct Diff<'b> {
+    old: &'b [u8],
+    new: &'b [u8],
+    ops: Vec<PatchOp>,
+    pending_insert: Vec<u8>,
+}
+
+impl Diff<'_> {
+    // Diffs a part of the new blob, copying from the matching part of the old one
+    fn region(&mut self, old_range: Range<usize>, new_range: Range<usize>) {
+        let Diff { old, new, .. } = *self;
+
+        // We remember where every aligned block of the old part starts
+        let mut blocks = AHashMap::with_capacity(old_range.len() / BLOCK_SIZE);
+        for position in old_range.step_by(BLOCK_SIZE) {
+            if let Some(block) = block_at(old, position) {
+                blocks.entry(block).or_insert(position);
+            }
+        }
+
+        let mut position = new_range.start;
+        while position < new_range.end {
+            let found = block_at(new, position).and_then(|block| blocks.get(&block).copied());
+
+            if let Some(old_start) = found {
+                // We extend the match as far forwards as it goes
+                let mut len = 0;
+                while old_start + len < old.len()
+                    && position + len < new_range.end
+                    && old[old_start + len] == new[position + len]
+                {
+                    len += 1;
+                }
+
+                // And backwards into what we were about to insert
+                let mut back = 0;
+                while back < self.pending_insert.len()
+                    && back < old_start
+                    && old[old_start - back - 1] == new[position - back - 1]
+                {
+                    back += 1;
+                }
+
+                if len + back >= MIN_COPY_LEN {
+                    self.pending_insert
+                        .truncate(self.pending_insert.len() - back);
+                    self.flush();
+                    self.ops.push(PatchOp::Copy {
+                        offset: old_start - back,
+                        len: len + back,
+                    });
+                    position += len;
+                    continue;
+                }
+            }
+
+            self.pending_insert.push(new[position]);
+            position += 1;
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.pending_insert.is_empty() == false {
+            let bytes = std::mem::take(&mut self.pending_insert);
+            self.ops.push(PatchOp::Insert(bytes));
+        }
+    }
+}
+
+impl Patch {
+    /// Diffs two blobs. Serialized indexes are diffed section by section, so the blocks of each section
+    /// are only looked for in the same section of the old index
+    pub fn diff(old: &[u8], new: &[u8]) -> Patch {
+        let regions = match (section_ranges(old), section_ranges(new)) {
+            (Some(old_ranges), Some(new_ranges)) => {
+                old_ranges.into_iter().zip(new_ranges).collect()
+            }
+            _ => vec![(0..old.len(), 0..new.len())],
+        };
+
+        let mut diff = Diff {
+            old,
+            new,
+            ops: Vec::new(),
+            pending_insert: Vec::new(),
+        };
+        for (old_range, new_range) in regions {
+            diff.region(old_range, new_range);
+        }
+        diff.flush();
+
+        Patch {
+            base_checksum: checksum(old),
+            target_checksum: checksum(new),
+            target_len: new.len(),
+            ops: diff.ops,
+        }
+    }
+
+    pub fn apply(&self, old: &[u8]) -> Option<Vec<u8>> {
+        if checksum(old) != self.base_checksum {
+            return None;
+        }
+
+        // The length comes from the patch, so we only reserve what its ops can give
+        let most = self.ops.iter().fold(0usize, |total, op| {
+            let len = match op {
+                PatchOp::Copy { len, .. } => (*len).min(old.len()),
+                PatchOp::Insert(bytes) => bytes.len(),
+            };
+            total.saturating_add(len)
+        });
+        if self.target_len > most {
+            return None;
+        }
+
+        let mut out = Vec::with_capacity(self.target_len);
+        for op in &self.ops {
+            match op {
+                PatchOp::Copy { offset, len } => {
+                    out.extend_from_slice(old.get(*offset..offset.checked_add(*len)?)?);
+                }
+                PatchOp::Insert(bytes) => out.extend_from_slice(bytes),
+            }
+            if out.len() > self.target_len {
+                return None;
+            }
+        }
+
+        if out.len() != self.target_len || checksum(&out) != self.target_checksum {
+            return None;
+        }
+        Some(out)
+    }
+}
+
+pub fn make_patch(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    Patch::diff(old, new).serialize(&mut |byte| out.push(byte));
+    out
+}
+
+pub fn apply_patch(old: &[u8], patch: &[u8]) -> Option<Vec<u8>> {
+    let (_, patch) = Patch::deserialize(patch)?;
+    patch.apply(old)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_patch, checksum, make_patch, Patch, PatchOp};
+    use crate::serialize::Serializable;
+
+    fn pseudo_random_bytes(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1);
+                #[allow(clippy::cast_possible_truncation)]
+                let byte = (state >> 56) as u8;
+                byte
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_patch_roundtrip() {
+        let old = pseudo_random_bytes(10_000, 1);
+
+        let mut changed = old.clone();
+        changed[5_000] ^= 0xff;
+        changed.splice(200..200, [1, 2, 3, 4, 5]);
+        changed.drain(8_000..8_100);
+
+        for new in [
+            old.clone(),
+            changed,
+            Vec::new(),
+            pseudo_random_bytes(300, 2),
+        ] {
+            let patch = make_patch(&old, &new);
+            assert_eq!(apply_patch(&old, &patch), Some(new));
+        }
+    }
+
+    #[test]
+    fn test_small_change_gives_small_patch() {
+        let old = pseudo_random_bytes(100_000, 3);
+        let mut new = old.clone();
+        new[50_000] = new[50_000].wrapping_add(1);
+
+        let patch = make_patch(&old, &new);
+        assert!(patch.len() < 100, "Patch was {} bytes", patch.len());
+    }
+
+    #[test]
+    fn test_catalogue_change_gives_small_patch() {
+        use crate::{
+            config::IndexConfig,
+            serialize::{IndexSections, Section},
+            testing::{build_test_index, test_products},
+        };
+
+        // A catalogue where one product changed its price, and a new product was added at the end
+        let mut products = test_products();
+        let added = products.pop().unwrap();
+        let old = build_test_index(products.clone(), IndexConfig::default()).blob;
+        products[3].price.min.amount = "1234.5".to_string();
+        products.push(added);
+        let new = build_test_index(products, IndexConfig::default()).blob;
+
+        let patch = make_patch(&old, &new);
+        assert_eq!(apply_patch(&old, &patch).as_ref(), Some(&new));
+        // Most of the patch is the rewritten grams, see `Patch`
+        assert!(
+            patch.len() < new.len() / 5,
+            "Patch was {} bytes",
+            patch.len()
+        );
+
+        let (old, new) = (
+            IndexSections::parse(&old).unwrap(),
+            IndexSections::parse(&new).unwrap(),
+        );
+        for section in [Section::Products, Section::Classic] {
+            let patch = make_patch(old.get(section), new.get(section));
+            assert!(
+                patch.len() < 2_000,
+                "Patch of the {} was {} bytes",
+                section.name(),
+                patch.len()
+            );
+        }
+    }
+
+    #[test]
+    fn test_patch_rejects_wrong_base() {
+        let old = pseudo_random_bytes(1_000, 4);
+        let new = pseudo_random_bytes(1_000, 5);
+        let patch = make_patch(&old, &new);
+        assert_eq!(apply_patch(&new, &patch), None);
+    }
+
+    #[test]
+    fn test_patch_rejects_broken_ops() {
+        let old = pseudo_random_bytes(1_000, 6);
+        let broken = |target_len, ops| {
+            let patch = Patch {
+                base_checksum: checksum(&old),
+                target_checksum: 0,
+                target_len,
+                ops,
+            };
+            let mut bytes = Vec::new();
+            patch.serialize(&mut |byte| bytes.push(byte));
+            apply_patch(&old, &bytes)
+        };
+        // Neither a length the ops can't give nor a copy past the end is read
+        assert_eq!(broken(usize::MAX, vec![PatchOp::Insert(vec![1, 2])]), None);
+        let past_end = PatchOp::Copy {
+            offset: usize::MAX - 1,
+            len: 2,
+        };
+        assert_eq!(broken(2, vec![past_end]), None);
+    }
+}
@@ -0,0 +1,122 @@
+use ahash::AHashMap;
+
+use crate::{
+    classic_indexes::ClassicIndexes,
+    data::Product,
+    ngram::{GramAtom, GramIndex},
+};
+
+use super::{
+    all_indexes::to_bytes, patch::checksum, serialize_gram_data, serialize_gram_tree,
+    write_sections, Deserializable, IndexSections, Section, Serializable,
+};
+
+/// Which shard holds the grams starting with `gram`.
+///
+/// Since a search never changes the first gram of an n-gram, every lookup for a window of the query stays within one shard.
+pub fn shard_for<G: GramAtom>(gram: &G, shard_count: usize) -> usize {
+    let mut bytes = Vec::with_capacity(4);
+    gram.serialize(&mut |input| bytes.push(input));
+    #[allow(clippy::cast_possible_truncation)]
+    let shard = (checksum(&bytes) % shard_count as u64) as usize;
+    shard
+}
+
+/// An index split into a core blob with the products and classic indexes, and gram shards that can be fetched later
+pub struct ShardedBlobs {
+    pub core: Vec<u8>,
+    pub shards: Vec<Vec<u8>>,
+}
+
+pub fn serialize_sharded<G: GramAtom, const N: usize>(
+    ngram: &GramIndex<'_, G, Product<'_>, N>,
+    classic: &ClassicIndexes<'_>,
+    shard_count: usize,
+) -> ShardedBlobs {
+    assert!(shard_count > 0, "An index needs atleast one shard");
+
+    let mut roots = vec![AHashMap::new(); shard_count];
+    for (gram, node) in &ngram.roots {
+        roots[shard_for(gram, shard_count)].insert(*gram, *node);
+    }
+    let mut data = vec![AHashMap::new(); shard_count];
+    for (key, products) in &ngram.data {
+        data[shard_for(&key[0], shard_count)].insert(*key, products.clone());
+    }
+
+    let normalization = &ngram.container.config.normalization;
+    let core = write_sections(
+        N,
+        normalization,
+        vec![
+            (Section::Products, to_bytes(ngram.container)),
+            (
+                Section::GramTree,
+                serialize_gram_tree::<G>(&AHashMap::new()),
+            ),
+            (
+                Section::GramData,
+                serialize_gram_data::<G, Product, N>(&AHashMap::new()),
+            ),
+            (Section::Classic, to_bytes(classic)),
+            (Section::Shards, to_bytes(&shard_count)),
+            (Section::Rules, to_bytes(&ngram.container.config.rules)),
+            (Section::Documents, Vec::new()),
+        ],
+    );
+
+    // Shards are only added to the index of the core they were built with
+    let core_checksum = checksum(&core);
+    let shards = roots
+        .iter()
+        .zip(data.iter())
+        .enumerate()
+        .map(|(index, (roots, data))| {
+            let tree = serialize_gram_tree(roots);
+            let data = serialize_gram_data(data);
+
+            let mut out = Vec::with_capacity(tree.len() + data.len() + 8);
+            let mut save = |input: u8| out.push(input);
+            index.serialize(&mut save);
+            core_checksum.serialize(&mut save);
+            tree.len().serialize(&mut save);
+            data.len().serialize(&mut save);
+            out.extend(tree);
+            out.extend(data);
+            out
+        })
+        .collect();
+
+    ShardedBlobs { core, shards }
+}
+
+pub fn shard_count(sections: &IndexSections<'_>) -> Option<usize> {
+    let (_, count) = usize::deserialize(sections.get(Section::Shards))?;
+    Some(count)
+}
+
+/// The gram tree and gram data sections of a single shard
+pub struct GramShard<'s> {
+    pub index: usize,
+    // The checksum of the core blob the shard belongs to
+    pub core: u64,
+    pub gram_tree: &'s [u8],
+    pub gram_data: &'s [u8],
+}
+
+impl<'s> GramShard<'s> {
+    pub fn parse(input: &'s [u8]) -> Option<GramShard<'s>> {
+        let (input, index) = usize::deserialize(input)?;
+        let (input, core) = u64::deserialize(input)?;
+        let (input, tree_len) = usize::deserialize(input)?;
+        let (input, data_len) = usize::deserialize(input)?;
+        let gram_tree = input.get(..tree_len)?;
+        let gram_data = input.get(tree_len..tree_len.checked_add(data_len)?)?;
+        Some(GramShard {
+            index,
+            core,
+            gram_tree,
+            gram_data,
+        })
+    }
+}
@@ -0,0 +1,50 @@
+use crate::{
+    build_index,
+    classic_indexes::ClassicIndexes,
+    config::IndexConfig,
+    data::{ProductContainer, RawProduct, SuperAlloc},
+    ngram::GramIndex,
+    serialize::{serialize_all, IndexSections, Section},
+    Product, NGRAM_INDEX_SIZE,
+};
+
+lazy_static::lazy_static! {
+    // Indexes built by tests are never freed, like the ones loaded in the browser
+    pub static ref TEST_ARENA: SuperAlloc = SuperAlloc::new();
+    static ref TEST_JSON: String = std::fs::read_to_string("./test.json").unwrap();
+}
+
+/// The products of `./test.json`, ordered by id so they get the same serialization ids on every run
+pub fn test_products() -> Vec<RawProduct<'static>> {
+    let products: ahash::AHashMap<String, RawProduct> = serde_json::from_str(&TEST_JSON).unwrap();
+    let mut products: Vec<_> = products.into_iter().map(|(_, v)| v).collect();
+    products.sort_by(|a, b| a.id.cmp(&b.id));
+    products
+}
+
+/// An index of test products as it's built, and as a client reads it back from the blob
+pub struct TestIndex {
+    pub index: GramIndex<'static, char, Product<'static>, NGRAM_INDEX_SIZE>,
+    pub classic: ClassicIndexes<'static>,
+    pub blob: Vec<u8>,
+    pub container: &'static ProductContainer<'static>,
+    pub loaded_classic: ClassicIndexes<'static>,
+}
+
+pub fn build_test_index(products: Vec<RawProduct<'_>>, config: IndexConfig) -> TestIndex {
+    let (index, classic) = build_index::<NGRAM_INDEX_SIZE>(products, config, &TEST_ARENA);
+    let blob = serialize_all(&index, &classic);
+
+    let sections = IndexSections::parse(&blob).unwrap();
+    let container = sections.products(&TEST_ARENA).unwrap();
+    let (_, loaded_classic) =
+        ClassicIndexes::deserialize(sections.get(Section::Classic), container).unwrap();
+
+    TestIndex {
+        index,
+        classic,
+        blob,
+        container,
+        loaded_classic,
+    }
+}
@@ -0,0 +1,33 @@
+use colosseum::sync::Arena;
+use indexer_lib::{
+    data::{optimize, Product, RawProduct, SuperAlloc},
+    ngram::GramIndex,
+    preprocessor::index_feed,
+};
+
+lazy_static::lazy_static! {
+    static ref JSON_TEST_DATA: String = std::fs::read_to_string("./test.json").unwrap();
+    static ref TEST_PRODUCTS: Vec<RawProduct<'static>> = {
+        let products: ahash::AHashMap<String, RawProduct<'_>> = serde_json::from_str(&JSON_TEST_DATA).unwrap();
+
+        products.into_iter().map(|(_, v)| v).collect()
+    };
+    static ref SUPER_ALLOC: SuperAlloc = SuperAlloc::new();
+}
+
+#[test]
+#[ignore = "Only for statistics around gram frequencies"]
+fn gram_frequencies() -> Result<(), Box<dyn std::error::Error>> {
+    let (prods, _) = optimize(TEST_PRODUCTS.clone(), &SUPER_ALLOC);
+
+    let iter = prods.products.iter().map(|p| index_feed(p, &prods.config));
+
+    let arena = Arena::new();
+
+    let (_, statistics): (GramIndex<char, Product, 5>, _) =
+        GramIndex::index_from_with_pruning(iter, &arena, prods, &prods.config.pruning);
+
+    std::fs::write("gram_frequencies.csv", statistics.to_csv())?;
+
+    Ok(())
+}